@@ -79,6 +79,7 @@ pub const JOIN: Action = Action(JOIN_U32);
 pub const COUNT: Action = Action(COUNT_U32);
 pub const AGGREGATE: Action = Action(AGGREGATE_U32);
 pub const GROUP_BY: Action = Action(GROUP_BY_U32);
+pub const EXPORT: Action = Action(EXPORT_U32);
 pub const CODE_NAME: Action = Action(CODE_NAME_U32);
 
 pub const UPSERT: Action = Action(UPSERT_U32);
@@ -112,12 +113,14 @@ pub(crate) const COPY_HANDLER: Action = Action(COPY_HANDLER_U32);
 pub(crate) const UPSERT_HANDLER: Action = Action(UPSERT_HANDLER_U32);
 pub(crate) const DELETE_HANDLER: Action = Action(DELETE_HANDLER_U32);
 pub(crate) const CREATE_MANY_HANDLER: Action = Action(CREATE_MANY_HANDLER_U32);
+pub(crate) const UPSERT_MANY_HANDLER: Action = Action(UPSERT_MANY_HANDLER_U32);
 pub(crate) const UPDATE_MANY_HANDLER: Action = Action(UPDATE_MANY_HANDLER_U32);
 pub(crate) const COPY_MANY_HANDLER: Action = Action(COPY_MANY_HANDLER_U32);
 pub(crate) const DELETE_MANY_HANDLER: Action = Action(DELETE_MANY_HANDLER_U32);
 pub(crate) const COUNT_HANDLER: Action = Action(COUNT_HANDLER_U32);
 pub(crate) const AGGREGATE_HANDLER: Action = Action(AGGREGATE_HANDLER_U32);
 pub(crate) const GROUP_BY_HANDLER: Action = Action(GROUP_BY_HANDLER_U32);
+pub(crate) const EXPORT_HANDLER: Action = Action(EXPORT_HANDLER_U32);
 
 pub(crate) const NESTED_CREATE_ACTION: Action = Action(NESTED_CREATE_ACTION_U32);
 pub(crate) const NESTED_UPDATE_ACTION: Action = Action(NESTED_UPDATE_ACTION_U32);
@@ -164,6 +167,7 @@ impl Action {
             "count" => COUNT,
             "aggregate" => AGGREGATE,
             "groupBy" => GROUP_BY,
+            "export" => EXPORT,
             "codeName" => CODE_NAME,
             "entry" => ENTRY,
             "nested" => NESTED,
@@ -252,7 +256,7 @@ impl Action {
     }
 
     pub fn builtin_handlers() -> Iter<'static, Action> {
-        static HANDLER_TYPES: [Action; 15] = [
+        static HANDLER_TYPES: [Action; 17] = [
             FIND_UNIQUE_HANDLER,
             FIND_FIRST_HANDLER,
             FIND_MANY_HANDLER,
@@ -262,12 +266,14 @@ impl Action {
             COPY_HANDLER,
             DELETE_HANDLER,
             CREATE_MANY_HANDLER,
+            UPSERT_MANY_HANDLER,
             UPDATE_MANY_HANDLER,
             COPY_MANY_HANDLER,
             DELETE_MANY_HANDLER,
             COUNT_HANDLER,
             AGGREGATE_HANDLER,
             GROUP_BY_HANDLER,
+            EXPORT_HANDLER,
         ];
         HANDLER_TYPES.iter()
     }
@@ -283,12 +289,14 @@ impl Action {
             UPSERT_HANDLER => "upsert",
             DELETE_HANDLER => "delete",
             CREATE_MANY_HANDLER => "createMany",
+            UPSERT_MANY_HANDLER => "upsertMany",
             UPDATE_MANY_HANDLER => "updateMany",
             COPY_MANY_HANDLER => "copyMany",
             DELETE_MANY_HANDLER => "deleteMany",
             COUNT_HANDLER => "count",
             AGGREGATE_HANDLER => "aggregate",
             GROUP_BY_HANDLER => "groupBy",
+            EXPORT_HANDLER => "export",
             _ => unreachable!()
         }
     }
@@ -1,5 +1,6 @@
 use std::any::Any;
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 use deferred_box::DeferredBox;
 use educe::Educe;
 use teo_result::{Result, Error};
@@ -21,6 +22,9 @@ struct Inner {
     /// A place to store dynamic runtime classes
     #[educe(Debug(ignore))]
     dynamic_classes: DeferredBox<Arc<dyn Any>>,
+    /// State which std modules keep for this app, like parsed keys or caches
+    #[educe(Debug(ignore))]
+    extensions: Mutex<BTreeMap<String, Arc<dyn Any + Send + Sync>>>,
 }
 
 impl AppData {
@@ -31,6 +35,7 @@ impl AppData {
                 entrance,
                 runtime_version,
                 dynamic_classes: DeferredBox::new(),
+                extensions: Mutex::new(BTreeMap::new()),
             })
         }
     }
@@ -56,6 +61,24 @@ impl AppData {
             Err(_) => Err(Error::new("Dynamic classes have been set")),
         }
     }
+
+    pub fn extension<T>(&self, key: &str) -> Option<Arc<T>> where T: Any + Send + Sync {
+        self.inner.extensions.lock().unwrap().get(key).cloned().and_then(|value| value.downcast::<T>().ok())
+    }
+
+    pub fn set_extension<T>(&self, key: impl Into<String>, value: T) where T: Any + Send + Sync {
+        self.inner.extensions.lock().unwrap().insert(key.into(), Arc::new(value));
+    }
+
+    pub fn extension_or_insert_with<T, F>(&self, key: &str, f: F) -> Arc<T> where T: Any + Send + Sync, F: FnOnce() -> T {
+        let mut extensions = self.inner.extensions.lock().unwrap();
+        if let Some(value) = extensions.get(key).cloned().and_then(|value| value.downcast::<T>().ok()) {
+            return value;
+        }
+        let value = Arc::new(f());
+        extensions.insert(key.to_owned(), value.clone());
+        value
+    }
 }
 
 unsafe impl Send for AppData { }
@@ -0,0 +1,36 @@
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use teo_result::Result;
+use crate::connection::connection::Connection;
+use crate::connection::memory::store::Store;
+use crate::connection::memory::transaction::MemoryTransaction;
+use crate::connection::transaction::Transaction;
+
+/// A connection to an in-memory database. It needs no external service, which makes it
+/// suitable for tests and prototyping. The data is lost when the connection is dropped.
+///
+/// Writes of a transaction are applied immediately and undone if the transaction is aborted,
+/// so other transactions can observe uncommitted data.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryConnection {
+    store: Arc<Mutex<Store>>,
+}
+
+impl MemoryConnection {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Connection for MemoryConnection {
+
+    async fn transaction(&self) -> Result<Arc<dyn Transaction>> {
+        Ok(Arc::new(MemoryTransaction::new(self.store.clone(), true)))
+    }
+
+    async fn no_transaction(&self) -> Result<Arc<dyn Transaction>> {
+        Ok(Arc::new(MemoryTransaction::new(self.store.clone(), false)))
+    }
+}
@@ -0,0 +1,7 @@
+mod store;
+mod query;
+pub mod connection;
+pub mod transaction;
+
+pub use connection::MemoryConnection;
+pub use transaction::MemoryTransaction;
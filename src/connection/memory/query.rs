@@ -0,0 +1,654 @@
+use std::cmp::Ordering;
+use indexmap::IndexMap;
+use key_path::KeyPath;
+use regex::Regex;
+use teo_result::Result;
+use crate::connection::memory::store::{Row, Store};
+use crate::error_ext;
+use crate::model::{Model, Relation};
+use crate::model::object::input::Input;
+use crate::namespace::Namespace;
+use crate::value::Value;
+
+const AGGREGATES: [&str; 5] = ["_count", "_sum", "_avg", "_min", "_max"];
+
+/// Evaluates the arguments of the synthesized `FindManyArgs`, `CountArgs`, `AggregateArgs` and
+/// `GroupByArgs` shapes against the rows of a store.
+pub(super) struct Query<'a> {
+    namespace: &'a Namespace,
+    store: &'a Store,
+}
+
+impl<'a> Query<'a> {
+
+    pub(super) fn new(namespace: &'a Namespace, store: &'a Store) -> Self {
+        Self { namespace, store }
+    }
+
+    /// The rows matched by `where`, `orderBy`, `cursor`, `distinct` and the pagination
+    /// arguments of `finder`.
+    pub(super) fn find_rows(&self, model: &Model, finder: &Value, path: &KeyPath) -> Result<Vec<Row>> {
+        let rows = self.store.rows(model.path()).into_iter().map(|(_, row)| row).collect();
+        self.apply_finder(model, rows, finder, path)
+    }
+
+    pub(super) fn apply_finder(&self, model: &Model, rows: Vec<Row>, finder: &Value, path: &KeyPath) -> Result<Vec<Row>> {
+        let mut rows = self.filter(model, rows, finder.get("where"), &(path + "where"))?;
+        if let Some(order_by) = finder.get("orderBy") {
+            sort_rows(&mut rows, order_by, &(path + "orderBy"))?;
+        }
+        if let Some(cursor) = finder.get("cursor") {
+            let Some(cursor) = cursor.as_dictionary() else {
+                return Err(error_ext::unexpected_input(path + "cursor"));
+            };
+            let Some(position) = rows.iter().position(|row| {
+                cursor.iter().all(|(k, v)| row.get(k).unwrap_or(&Value::Null) == v)
+            }) else {
+                return Ok(vec![]);
+            };
+            if Input::has_negative_take(finder) {
+                rows.truncate(position + 1);
+            } else {
+                rows.drain(..position);
+            }
+        }
+        if let Some(distinct) = finder.get("distinct") {
+            let keys = key_names(distinct);
+            let mut seen: Vec<Vec<Value>> = vec![];
+            rows.retain(|row| {
+                let values: Vec<Value> = keys.iter().map(|k| row.get(k).cloned().unwrap_or(Value::Null)).collect();
+                if seen.contains(&values) {
+                    false
+                } else {
+                    seen.push(values);
+                    true
+                }
+            });
+        }
+        Ok(paginate(rows, finder))
+    }
+
+    pub(super) fn filter(&self, model: &Model, rows: Vec<Row>, r#where: Option<&Value>, path: &KeyPath) -> Result<Vec<Row>> {
+        let Some(r#where) = r#where else {
+            return Ok(rows);
+        };
+        let mut result = vec![];
+        for row in rows {
+            if self.matches(model, &row, r#where, path)? {
+                result.push(row);
+            }
+        }
+        Ok(result)
+    }
+
+    pub(super) fn matches(&self, model: &Model, row: &Row, r#where: &Value, path: &KeyPath) -> Result<bool> {
+        let Some(map) = r#where.as_dictionary() else {
+            return Err(error_ext::unexpected_input(path.clone()));
+        };
+        for (key, filter) in map {
+            let path = path + key.as_str();
+            let matched = match key.as_str() {
+                "AND" => match filter {
+                    Value::Array(items) => self.matches_all(model, row, items, &path)?,
+                    _ => self.matches(model, row, filter, &path)?,
+                },
+                "OR" => match filter {
+                    Value::Array(items) => self.matches_any(model, row, items, &path)?,
+                    _ => self.matches(model, row, filter, &path)?,
+                },
+                "NOT" => match filter {
+                    Value::Array(items) => !self.matches_any(model, row, items, &path)?,
+                    _ => !self.matches(model, row, filter, &path)?,
+                },
+                _ => if model.field(key).is_some() {
+                    matches_value(row.get(key).unwrap_or(&Value::Null), filter, &path)?
+                } else if let Some(relation) = model.relation(key) {
+                    self.matches_relation(relation, row, filter, &path)?
+                } else {
+                    return Err(error_ext::invalid_key_on_model(path, key, model));
+                }
+            };
+            if !matched {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn matches_all(&self, model: &Model, row: &Row, items: &Vec<Value>, path: &KeyPath) -> Result<bool> {
+        for (index, item) in items.iter().enumerate() {
+            if !self.matches(model, row, item, &(path + index))? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn matches_any(&self, model: &Model, row: &Row, items: &Vec<Value>, path: &KeyPath) -> Result<bool> {
+        for (index, item) in items.iter().enumerate() {
+            if self.matches(model, row, item, &(path + index))? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn matches_relation(&self, relation: &Relation, row: &Row, filter: &Value, path: &KeyPath) -> Result<bool> {
+        let related_model = self.namespace.model_at_path(relation.model_path()).unwrap();
+        let related = self.related_rows(relation, row);
+        if filter.is_null() && !relation.is_vec() {
+            return Ok(related.is_empty());
+        }
+        let Some(map) = filter.as_dictionary() else {
+            return Err(error_ext::unexpected_input(path.clone()));
+        };
+        if !relation.is_vec() && !map.contains_key("is") && !map.contains_key("isNot") {
+            return match related.first() {
+                Some(related_row) => self.matches(related_model, related_row, filter, path),
+                None => Ok(false),
+            };
+        }
+        for (key, inner) in map {
+            let path = path + key.as_str();
+            let matched = match key.as_str() {
+                "some" => {
+                    let mut matched = false;
+                    for related_row in &related {
+                        if self.matches(related_model, related_row, inner, &path)? {
+                            matched = true;
+                            break;
+                        }
+                    }
+                    matched
+                }
+                "every" => {
+                    let mut matched = true;
+                    for related_row in &related {
+                        if !self.matches(related_model, related_row, inner, &path)? {
+                            matched = false;
+                            break;
+                        }
+                    }
+                    matched
+                }
+                "none" => {
+                    let mut matched = true;
+                    for related_row in &related {
+                        if self.matches(related_model, related_row, inner, &path)? {
+                            matched = false;
+                            break;
+                        }
+                    }
+                    matched
+                }
+                "is" => match (related.first(), inner.is_null()) {
+                    (None, is_null) => is_null,
+                    (Some(_), true) => false,
+                    (Some(related_row), false) => self.matches(related_model, related_row, inner, &path)?,
+                },
+                "isNot" => match (related.first(), inner.is_null()) {
+                    (None, is_null) => !is_null,
+                    (Some(_), true) => true,
+                    (Some(related_row), false) => !self.matches(related_model, related_row, inner, &path)?,
+                },
+                _ => return Err(error_ext::unexpected_input(path)),
+            };
+            if !matched {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The rows which are related to `row` through `relation`.
+    pub(super) fn related_rows(&self, relation: &Relation, row: &Row) -> Vec<Row> {
+        let related_model = self.namespace.model_at_path(relation.model_path()).unwrap();
+        let related_rows = self.store.rows(related_model.path());
+        if let Some(through_path) = relation.through_path() {
+            let through_model = self.namespace.model_at_path(through_path).unwrap();
+            let local = through_model.relation(relation.local().unwrap()).unwrap();
+            let foreign = through_model.relation(relation.foreign().unwrap()).unwrap();
+            let mut result = vec![];
+            for (_, through_row) in self.store.rows(through_model.path()) {
+                if !joins(&through_row, local.fields(), row, local.references()) {
+                    continue;
+                }
+                for (_, related_row) in &related_rows {
+                    if joins(&through_row, foreign.fields(), related_row, foreign.references()) {
+                        result.push(related_row.clone());
+                    }
+                }
+            }
+            result
+        } else {
+            related_rows.into_iter().map(|(_, related_row)| related_row).filter(|related_row| {
+                joins(row, relation.fields(), related_row, relation.references())
+            }).collect()
+        }
+    }
+
+    /// Embeds the related rows requested by `include` into each row, in the form which
+    /// `Object::set_from_database_result_value` accepts.
+    pub(super) fn include(&self, model: &Model, rows: Vec<Row>, include: &Value, path: &KeyPath) -> Result<Vec<Row>> {
+        let Some(include) = include.as_dictionary() else {
+            return Err(error_ext::unexpected_input(path.clone()));
+        };
+        let mut result = vec![];
+        for mut row in rows {
+            for (key, arguments) in include {
+                if arguments.is_false() {
+                    continue;
+                }
+                let path = path + key.as_str();
+                let Some(relation) = model.relation(key) else {
+                    return Err(error_ext::invalid_key_on_model(path, key, model));
+                };
+                let related_model = self.namespace.model_at_path(relation.model_path()).unwrap();
+                let mut related = self.related_rows(relation, &row);
+                if arguments.is_dictionary() {
+                    related = self.apply_finder(related_model, related, arguments, &path)?;
+                    if let Some(nested) = arguments.get("include") {
+                        related = self.include(related_model, related, nested, &(path + "include"))?;
+                    }
+                }
+                row.insert(key.clone(), Value::Array(related.into_iter().map(Value::Dictionary).collect()));
+            }
+            result.push(row);
+        }
+        Ok(result)
+    }
+
+    pub(super) fn group_by(&self, model: &Model, finder: &Value, path: &KeyPath) -> Result<Vec<Value>> {
+        let rows = self.store.rows(model.path()).into_iter().map(|(_, row)| row).collect();
+        let rows = self.filter(model, rows, finder.get("where"), &(path + "where"))?;
+        let Some(by) = finder.get("by") else {
+            return Err(error_ext::missing_required_input(path + "by"));
+        };
+        let by = key_names(by);
+        let mut groups: Vec<(Vec<Value>, Vec<Row>)> = vec![];
+        for row in rows {
+            let key: Vec<Value> = by.iter().map(|k| row.get(k).cloned().unwrap_or(Value::Null)).collect();
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push(row),
+                None => groups.push((key, vec![row])),
+            }
+        }
+        let mut results: Vec<Row> = vec![];
+        for (key, group) in groups {
+            if let Some(having) = finder.get("having") {
+                if !matches_having(&group, having, &(path + "having"))? {
+                    continue;
+                }
+            }
+            let mut result: Row = by.iter().cloned().zip(key).collect();
+            result.extend(aggregate_rows(&group, finder, path)?);
+            results.push(result);
+        }
+        if let Some(order_by) = finder.get("orderBy") {
+            sort_rows(&mut results, order_by, &(path + "orderBy"))?;
+        }
+        Ok(paginate(results, finder).into_iter().map(Value::Dictionary).collect())
+    }
+}
+
+/// Computes the `_count`, `_sum`, `_avg`, `_min` and `_max` aggregates requested by `finder`.
+pub(super) fn aggregate_rows(rows: &Vec<Row>, finder: &Value, path: &KeyPath) -> Result<Row> {
+    let mut result = Row::new();
+    for function in AGGREGATES {
+        let Some(fields) = finder.get(function) else {
+            continue;
+        };
+        let Some(fields) = fields.as_dictionary() else {
+            return Err(error_ext::unexpected_input(path + function));
+        };
+        let mut values: IndexMap<String, Value> = IndexMap::new();
+        for (field, enabled) in fields {
+            if enabled.is_true() {
+                values.insert(field.clone(), aggregate_field(function, field, rows)?);
+            }
+        }
+        result.insert(function.to_owned(), Value::Dictionary(values));
+    }
+    Ok(result)
+}
+
+fn aggregate_field(function: &str, field: &str, rows: &Vec<Row>) -> Result<Value> {
+    if function == "_count" && field == "_all" {
+        return Ok(Value::Int64(rows.len() as i64));
+    }
+    let values: Vec<&Value> = rows.iter().filter_map(|row| row.get(field)).filter(|v| !v.is_null()).collect();
+    Ok(match function {
+        "_count" => Value::Int64(values.len() as i64),
+        "_sum" => {
+            let mut sum: Option<Value> = None;
+            for value in values {
+                sum = Some(match sum {
+                    Some(sum) => (&sum + value)?,
+                    None => value.clone(),
+                });
+            }
+            sum.unwrap_or(Value::Null)
+        }
+        "_avg" => if values.is_empty() {
+            Value::Null
+        } else {
+            Value::Float(values.iter().map(|v| v.to_float().unwrap_or(0.0)).sum::<f64>() / values.len() as f64)
+        },
+        "_min" => values.into_iter().min_by(|a, b| compare_values(a, b)).cloned().unwrap_or(Value::Null),
+        "_max" => values.into_iter().max_by(|a, b| compare_values(a, b)).cloned().unwrap_or(Value::Null),
+        _ => unreachable!(),
+    })
+}
+
+fn matches_having(group: &Vec<Row>, having: &Value, path: &KeyPath) -> Result<bool> {
+    let Some(having) = having.as_dictionary() else {
+        return Err(error_ext::unexpected_input(path.clone()));
+    };
+    for (field, filter) in having {
+        let path = path + field.as_str();
+        let aggregates = filter.as_dictionary().filter(|map| map.keys().all(|k| AGGREGATES.contains(&k.as_str())));
+        match aggregates {
+            Some(aggregates) => for (function, filter) in aggregates {
+                let value = aggregate_field(function, field, group)?;
+                if !matches_value(&value, filter, &(&path + function.as_str()))? {
+                    return Ok(false);
+                }
+            },
+            None => {
+                let value = group.first().and_then(|row| row.get(field)).unwrap_or(&Value::Null);
+                if !matches_value(value, filter, &path)? {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Whether `value` matches a field filter, such as `{ "gt": 5, "lte": 10 }` or a plain value.
+pub(super) fn matches_value(value: &Value, filter: &Value, path: &KeyPath) -> Result<bool> {
+    let Some(map) = filter.as_dictionary() else {
+        return Ok(value == filter);
+    };
+    let insensitive = Input::has_i_mode(map);
+    for (key, expected) in map {
+        let path = path + key.as_str();
+        let matched = match key.as_str() {
+            "equals" => if insensitive {
+                lowercased(value) == lowercased(expected)
+            } else {
+                value == expected
+            },
+            "not" => if expected.is_dictionary() {
+                !matches_value(value, expected, &path)?
+            } else {
+                value != expected
+            },
+            "gt" => !value.is_null() && compare_values(value, expected) == Ordering::Greater,
+            "gte" => !value.is_null() && compare_values(value, expected) != Ordering::Less,
+            "lt" => !value.is_null() && compare_values(value, expected) == Ordering::Less,
+            "lte" => !value.is_null() && compare_values(value, expected) != Ordering::Greater,
+            "in" => expected_array(expected, &path)?.contains(value),
+            "notIn" => !expected_array(expected, &path)?.contains(value),
+            "contains" | "startsWith" | "endsWith" => match (value.as_str(), expected.as_str()) {
+                (Some(string), Some(expected)) => {
+                    let (string, expected) = if insensitive {
+                        (string.to_lowercase(), expected.to_lowercase())
+                    } else {
+                        (string.to_owned(), expected.to_owned())
+                    };
+                    match key.as_str() {
+                        "contains" => string.contains(&expected),
+                        "startsWith" => string.starts_with(&expected),
+                        _ => string.ends_with(&expected),
+                    }
+                }
+                _ => false,
+            },
+            "matches" => match value.as_str() {
+                Some(string) => match expected {
+                    Value::Regex(regex) => regex.is_match(string),
+                    _ => {
+                        let Some(pattern) = expected.as_str() else {
+                            return Err(error_ext::unexpected_input(path));
+                        };
+                        let regex = Regex::new(pattern).map_err(|_| error_ext::unexpected_input_value_with_reason(path.clone(), "invalid regular expression"))?;
+                        regex.is_match(string)
+                    }
+                },
+                None => false,
+            },
+            "mode" => true,
+            "has" => value.as_array().map_or(false, |items| items.contains(expected)),
+            "hasEvery" => {
+                let expected = expected_array(expected, &path)?;
+                value.as_array().map_or(false, |items| expected.iter().all(|e| items.contains(e)))
+            }
+            "hasSome" => {
+                let expected = expected_array(expected, &path)?;
+                value.as_array().map_or(false, |items| expected.iter().any(|e| items.contains(e)))
+            }
+            "isEmpty" => value.as_array().map_or(false, |items| items.is_empty() == expected.is_true()),
+            "length" => value.as_array().map_or(false, |items| Some(items.len()) == expected.to_usize()),
+            _ => return Err(error_ext::unexpected_input(path)),
+        };
+        if !matched {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn expected_array<'b>(value: &'b Value, path: &KeyPath) -> Result<&'b Vec<Value>> {
+    value.as_array().ok_or_else(|| error_ext::unexpected_input(path.clone()))
+}
+
+fn lowercased(value: &Value) -> Value {
+    match value.as_str() {
+        Some(string) => Value::String(string.to_lowercase()),
+        None => value.clone(),
+    }
+}
+
+/// Nulls are ordered before other values.
+fn compare_values(lhs: &Value, rhs: &Value) -> Ordering {
+    match (lhs.is_null(), rhs.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal),
+    }
+}
+
+fn joins(lhs: &Row, lhs_keys: &Vec<String>, rhs: &Row, rhs_keys: &Vec<String>) -> bool {
+    lhs_keys.iter().zip(rhs_keys.iter()).all(|(l, r)| {
+        match (lhs.get(l), rhs.get(r)) {
+            (Some(l), Some(r)) => !l.is_null() && l == r,
+            _ => false,
+        }
+    })
+}
+
+fn key_names(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items.iter().filter_map(|item| item.as_str().map(|s| s.to_owned())).collect(),
+        _ => value.as_str().map_or(vec![], |s| vec![s.to_owned()]),
+    }
+}
+
+/// Sorts rows by an `orderBy` argument. Keys can be nested, such as
+/// `{ "_count": { "id": "desc" } }` for grouped rows.
+pub(super) fn sort_rows(rows: &mut Vec<Row>, order_by: &Value, path: &KeyPath) -> Result<()> {
+    let mut items: Vec<(Vec<String>, bool)> = vec![];
+    let order_by_items: Vec<&Value> = match order_by {
+        Value::Array(order_by_items) => order_by_items.iter().collect(),
+        _ => vec![order_by],
+    };
+    for order_by_item in order_by_items {
+        collect_ordering_items(vec![], order_by_item, &mut items, path)?;
+    }
+    rows.sort_by(|lhs, rhs| {
+        for (keys, desc) in &items {
+            let ordering = compare_values(lookup(lhs, keys), lookup(rhs, keys));
+            if ordering != Ordering::Equal {
+                return if *desc { ordering.reverse() } else { ordering };
+            }
+        }
+        Ordering::Equal
+    });
+    Ok(())
+}
+
+fn collect_ordering_items(keys: Vec<String>, value: &Value, items: &mut Vec<(Vec<String>, bool)>, path: &KeyPath) -> Result<()> {
+    match value.as_str() {
+        Some("asc") if !keys.is_empty() => items.push((keys, false)),
+        Some("desc") if !keys.is_empty() => items.push((keys, true)),
+        _ => {
+            let Some(map) = value.as_dictionary() else {
+                return Err(error_ext::unexpected_input_value_with_reason(path.clone(), "unexpected order direction"));
+            };
+            for (key, value) in map {
+                let mut keys = keys.clone();
+                keys.push(key.clone());
+                collect_ordering_items(keys, value, items, path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'b>(row: &'b Row, keys: &Vec<String>) -> &'b Value {
+    let mut value = row.get(&keys[0]).unwrap_or(&Value::Null);
+    for key in &keys[1..] {
+        value = value.get(key.as_str()).unwrap_or(&Value::Null);
+    }
+    value
+}
+
+fn paginate<T>(mut rows: Vec<T>, finder: &Value) -> Vec<T> {
+    let mut skip = finder.get("skip").and_then(|v| v.to_usize()).unwrap_or(0);
+    let mut take = finder.get("take").and_then(|v| v.to_int64());
+    if let Some(page_size) = finder.get("pageSize").and_then(|v| v.to_int64()) {
+        let page_number = finder.get("pageNumber").and_then(|v| v.to_int64()).unwrap_or(1);
+        skip = ((page_number - 1).max(0) * page_size) as usize;
+        take = Some(page_size);
+    }
+    let reversed = take.map_or(false, |take| take < 0);
+    if reversed {
+        rows.reverse();
+    }
+    let mut rows: Vec<T> = rows.into_iter().skip(skip).collect();
+    if let Some(take) = take {
+        rows.truncate(take.unsigned_abs() as usize);
+    }
+    if reversed {
+        rows.reverse();
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use key_path::path;
+    use crate::teon;
+    use super::*;
+
+    fn row(id: i64, name: Option<&str>) -> Row {
+        let mut row = Row::new();
+        row.insert("id".to_owned(), Value::Int64(id));
+        row.insert("name".to_owned(), name.map_or(Value::Null, |n| Value::String(n.to_owned())));
+        row
+    }
+
+    fn ids(rows: &Vec<Row>) -> Vec<i64> {
+        rows.iter().map(|row| row.get("id").unwrap().to_int64().unwrap()).collect()
+    }
+
+    #[test]
+    fn plain_values_match_by_equality() {
+        assert!(matches_value(&Value::Int64(1), &Value::Int64(1), &path![]).unwrap());
+        assert!(!matches_value(&Value::Int64(1), &Value::Int64(2), &path![]).unwrap());
+        assert!(matches_value(&Value::Null, &Value::Null, &path![]).unwrap());
+    }
+
+    #[test]
+    fn comparisons_never_match_null() {
+        for operator in ["gt", "gte", "lt", "lte"] {
+            assert!(!matches_value(&Value::Null, &teon!({ operator: 0 }), &path![]).unwrap());
+        }
+        assert!(matches_value(&Value::Int64(5), &teon!({ "gt": 1, "lte": 5 }), &path![]).unwrap());
+        assert!(!matches_value(&Value::Int64(6), &teon!({ "gt": 1, "lte": 5 }), &path![]).unwrap());
+    }
+
+    #[test]
+    fn list_and_negated_filters() {
+        assert!(matches_value(&Value::Int64(2), &teon!({ "in": [1, 2] }), &path![]).unwrap());
+        assert!(matches_value(&Value::Int64(3), &teon!({ "notIn": [1, 2] }), &path![]).unwrap());
+        assert!(matches_value(&Value::Int64(3), &teon!({ "not": { "in": [1, 2] } }), &path![]).unwrap());
+        assert!(!matches_value(&Value::Int64(1), &teon!({ "not": 1 }), &path![]).unwrap());
+        assert!(matches_value(&Value::Int64(1), &teon!({ "in": 1 }), &path![]).is_err());
+    }
+
+    #[test]
+    fn string_filters_respect_insensitive_mode() {
+        let value = Value::String("Hello World".to_owned());
+        assert!(matches_value(&value, &teon!({ "contains": "World" }), &path![]).unwrap());
+        assert!(!matches_value(&value, &teon!({ "startsWith": "hello" }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "startsWith": "hello", "mode": "caseInsensitive" }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "equals": "hello world", "mode": "caseInsensitive" }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "matches": "^H.*d$" }), &path![]).unwrap());
+        assert!(!matches_value(&Value::Null, &teon!({ "endsWith": "d" }), &path![]).unwrap());
+    }
+
+    #[test]
+    fn array_filters() {
+        let value = teon!([1, 2, 3]);
+        assert!(matches_value(&value, &teon!({ "has": 2 }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "hasEvery": [1, 3] }), &path![]).unwrap());
+        assert!(!matches_value(&value, &teon!({ "hasEvery": [1, 4] }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "hasSome": [4, 3] }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "isEmpty": false }), &path![]).unwrap());
+        assert!(matches_value(&value, &teon!({ "length": 3 }), &path![]).unwrap());
+    }
+
+    #[test]
+    fn unknown_filters_are_rejected() {
+        assert!(matches_value(&Value::Int64(1), &teon!({ "around": 1 }), &path![]).is_err());
+    }
+
+    #[test]
+    fn nulls_are_sorted_first() {
+        let mut rows = vec![row(1, Some("b")), row(2, None), row(3, Some("a"))];
+        sort_rows(&mut rows, &teon!({ "name": "asc" }), &path![]).unwrap();
+        assert_eq!(ids(&rows), vec![2, 3, 1]);
+        sort_rows(&mut rows, &teon!({ "name": "desc" }), &path![]).unwrap();
+        assert_eq!(ids(&rows), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn later_ordering_items_break_ties() {
+        let mut rows = vec![row(1, Some("a")), row(2, Some("b")), row(3, Some("a"))];
+        sort_rows(&mut rows, &teon!([{ "name": "asc" }, { "id": "desc" }]), &path![]).unwrap();
+        assert_eq!(ids(&rows), vec![3, 1, 2]);
+        assert!(sort_rows(&mut rows, &teon!({ "name": "up" }), &path![]).is_err());
+    }
+
+    #[test]
+    fn pagination() {
+        let rows = vec![row(1, None), row(2, None), row(3, None), row(4, None), row(5, None)];
+        assert_eq!(ids(&paginate(rows.clone(), &teon!({ "skip": 1, "take": 2 }))), vec![2, 3]);
+        assert_eq!(ids(&paginate(rows.clone(), &teon!({ "take": -2 }))), vec![4, 5]);
+        assert_eq!(ids(&paginate(rows.clone(), &teon!({ "pageSize": 2, "pageNumber": 3 }))), vec![5]);
+    }
+
+    #[test]
+    fn aggregates_skip_nulls() {
+        let mut rows = vec![row(1, None), row(2, Some("x")), row(6, None)];
+        rows[1].insert("id".to_owned(), Value::Null);
+        let result = aggregate_rows(&rows, &teon!({ "_count": { "_all": true, "id": true }, "_sum": { "id": true }, "_max": { "id": true } }), &path![]).unwrap();
+        assert_eq!(result.get("_count").unwrap().get("_all"), Some(&Value::Int64(3)));
+        assert_eq!(result.get("_count").unwrap().get("id"), Some(&Value::Int64(2)));
+        assert_eq!(result.get("_sum").unwrap().get("id"), Some(&Value::Int64(7)));
+        assert_eq!(result.get("_max").unwrap().get("id"), Some(&Value::Int64(6)));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use indexmap::IndexMap;
+use crate::value::Value;
+
+/// A record of the in-memory database, keyed by field names.
+pub(super) type Row = IndexMap<String, Value>;
+
+/// The data of an in-memory database. Tables are keyed by model paths, and rows are keyed by
+/// an internal row id, so that tables keep the order of insertion.
+#[derive(Debug, Default)]
+pub(super) struct Store {
+    tables: BTreeMap<Vec<String>, BTreeMap<u64, Row>>,
+    sequences: BTreeMap<(Vec<String>, String), i64>,
+    next_row_id: u64,
+}
+
+impl Store {
+
+    pub(super) fn rows(&self, path: &Vec<String>) -> Vec<(u64, Row)> {
+        match self.tables.get(path) {
+            Some(table) => table.iter().map(|(id, row)| (*id, row.clone())).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the row whose values equal all values of `identifier`.
+    pub(super) fn find_row(&self, path: &Vec<String>, identifier: &IndexMap<String, Value>) -> Option<(u64, Row)> {
+        let table = self.tables.get(path)?;
+        table.iter().find(|(_, row)| {
+            identifier.iter().all(|(k, v)| row.get(k).unwrap_or(&Value::Null) == v)
+        }).map(|(id, row)| (*id, row.clone()))
+    }
+
+    pub(super) fn insert(&mut self, path: &Vec<String>, row: Row) -> u64 {
+        self.next_row_id += 1;
+        let row_id = self.next_row_id;
+        self.tables.entry(path.clone()).or_default().insert(row_id, row);
+        row_id
+    }
+
+    /// Sets the row at `row_id`, or removes it if `row` is `None`. Returns the previous row.
+    pub(super) fn put(&mut self, path: &Vec<String>, row_id: u64, row: Option<Row>) -> Option<Row> {
+        let table = self.tables.entry(path.clone()).or_default();
+        match row {
+            Some(row) => table.insert(row_id, row),
+            None => table.remove(&row_id),
+        }
+    }
+
+    pub(super) fn create_table(&mut self, path: &Vec<String>) {
+        self.tables.entry(path.clone()).or_default();
+    }
+
+    pub(super) fn clear_table(&mut self, path: &Vec<String>) {
+        self.tables.remove(path);
+        self.sequences.retain(|(sequence_path, _), _| sequence_path != path);
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.tables.clear();
+        self.sequences.clear();
+    }
+
+    /// Generates the next value of an auto increment field. Like in most databases, sequences
+    /// are not rolled back with the transaction.
+    pub(super) fn next_sequence_value(&mut self, path: &Vec<String>, field: &str) -> i64 {
+        let max = self.tables.get(path).map_or(0, |table| {
+            table.values().filter_map(|row| row.get(field).and_then(|v| v.to_int64())).max().unwrap_or(0)
+        });
+        let sequence = self.sequences.entry((path.clone(), field.to_owned())).or_insert(0);
+        *sequence = (*sequence).max(max) + 1;
+        *sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Vec<String> {
+        vec!["Post".to_owned()]
+    }
+
+    fn row(id: i64) -> Row {
+        let mut row = Row::new();
+        row.insert("id".to_owned(), Value::Int64(id));
+        row
+    }
+
+    #[test]
+    fn sequences_start_after_the_stored_values() {
+        let mut store = Store::default();
+        assert_eq!(store.next_sequence_value(&table(), "id"), 1);
+        store.insert(&table(), row(10));
+        assert_eq!(store.next_sequence_value(&table(), "id"), 11);
+        assert_eq!(store.next_sequence_value(&table(), "id"), 12);
+    }
+
+    #[test]
+    fn sequences_are_not_reused_after_rows_are_removed() {
+        let mut store = Store::default();
+        let id = store.next_sequence_value(&table(), "id");
+        let row_id = store.insert(&table(), row(id));
+        store.put(&table(), row_id, None);
+        assert_eq!(store.next_sequence_value(&table(), "id"), 2);
+        store.clear_table(&table());
+        assert_eq!(store.next_sequence_value(&table(), "id"), 1);
+    }
+
+    #[test]
+    fn rows_keep_the_order_of_insertion() {
+        let mut store = Store::default();
+        for id in [3, 1, 2] {
+            store.insert(&table(), row(id));
+        }
+        let ids: Vec<i64> = store.rows(&table()).iter().map(|(_, row)| row.get("id").unwrap().to_int64().unwrap()).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+        let mut identifier = IndexMap::new();
+        identifier.insert("id".to_owned(), Value::Int64(1));
+        assert_eq!(store.find_row(&table(), &identifier).map(|(_, row)| row), Some(row(1)));
+        assert!(store.rows(&vec!["Comment".to_owned()]).is_empty());
+    }
+}
@@ -0,0 +1,439 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use async_trait::async_trait;
+use indexmap::{IndexMap, indexmap};
+use key_path::KeyPath;
+use teo_result::{Error, Result};
+use crate::action::Action;
+use crate::connection::memory::query::{aggregate_rows, Query};
+use crate::connection::memory::store::{Row, Store};
+use crate::connection::transaction;
+use crate::connection::transaction::Transaction;
+use crate::error_ext;
+use crate::model;
+use crate::model::Model;
+use crate::request::Request;
+use crate::value::Value;
+
+#[derive(Debug)]
+pub struct MemoryTransaction {
+    store: Arc<Mutex<Store>>,
+    is_transaction: bool,
+    committed: AtomicBool,
+    /// The previous state of each row written by this transaction, in the order of writes
+    undo_log: Mutex<Vec<(Vec<String>, u64, Option<Row>)>>,
+}
+
+impl MemoryTransaction {
+
+    pub(super) fn new(store: Arc<Mutex<Store>>, is_transaction: bool) -> Self {
+        Self {
+            store,
+            is_transaction,
+            committed: AtomicBool::new(false),
+            undo_log: Mutex::new(vec![]),
+        }
+    }
+
+    fn record_undo(&self, path: &Vec<String>, row_id: u64, previous: Option<Row>) {
+        if self.is_transaction {
+            self.undo_log.lock().unwrap().push((path.clone(), row_id, previous));
+        }
+    }
+
+    /// Inserts the row of a new object, generating the auto increment values.
+    fn insert_row(&self, store: &mut Store, object: &model::Object, path: &KeyPath) -> Result<u64> {
+        let model = object.model();
+        let mut row = Row::new();
+        for key in object.keys_for_save() {
+            let Some(field) = model.field(key) else {
+                continue;
+            };
+            let mut value = object.get_value(key)?;
+            if value.is_null() && field.auto_increment() {
+                object.set_value(key, Value::Int64(store.next_sequence_value(model.path(), key)))?;
+                value = object.get_value(key)?;
+            }
+            row.insert(key.to_owned(), value);
+        }
+        check_unique(model, store, &row, None, path)?;
+        let row_id = store.insert(model.path(), row);
+        self.record_undo(model.path(), row_id, None);
+        Ok(row_id)
+    }
+
+    /// Removes the rows which are just inserted by `insert_row`, along with their undo records.
+    fn remove_inserted_rows(&self, store: &mut Store, path: &Vec<String>, row_ids: &Vec<u64>) {
+        for row_id in row_ids.iter().rev() {
+            store.put(path, *row_id, None);
+        }
+        if self.is_transaction {
+            let mut undo_log = self.undo_log.lock().unwrap();
+            let len = undo_log.len() - row_ids.len();
+            undo_log.truncate(len);
+        }
+    }
+
+    /// Writes the values of a saved object into its row. If the row doesn't have the values of
+    /// `condition`, it's left as is and this returns false.
+    fn update_row(&self, store: &mut Store, object: &model::Object, condition: Option<&IndexMap<String, Value>>, path: &KeyPath) -> Result<bool> {
+        let model = object.model();
+        let Some((row_id, previous)) = store.find_row(model.path(), &stored_identifier(object)) else {
+            return Err(error_ext::unknown_database_write_error(path.clone(), "record is not found"));
+        };
+        if let Some(condition) = condition {
+            if condition.iter().any(|(key, value)| previous.get(key).unwrap_or(&Value::Null) != value) {
+                return Ok(false);
+            }
+        }
+        let atomic_updaters = object.atomic_updators().clone();
+        let mut row = previous.clone();
+        for key in object.keys_for_save() {
+            if model.field(key).is_none() {
+                continue;
+            }
+            let value = match atomic_updaters.get(key) {
+                Some(updater) => apply_atomic_updater(row.get(key).unwrap_or(&Value::Null), updater, &(path + key))?,
+                None => object.get_value(key)?,
+            };
+            row.insert(key.to_owned(), value);
+        }
+        check_unique(model, store, &row, Some(row_id), path)?;
+        store.put(model.path(), row_id, Some(row.clone()));
+        self.record_undo(model.path(), row_id, Some(previous));
+        // the object reflects the values computed by the atomic updaters
+        if !atomic_updaters.is_empty() {
+            object.atomic_updators().clear();
+            object.set_from_database_result_value(&Value::Dictionary(row), None, None);
+        }
+        Ok(true)
+    }
+
+    fn find_rows(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, transaction_ctx: transaction::Ctx, request: Option<Request>, path: KeyPath) -> Result<Vec<model::Object>> {
+        let namespace = transaction_ctx.namespace();
+        let include = if ignore_select_and_include { None } else { finder.get("include") };
+        let select = if ignore_select_and_include { None } else { finder.get("select") };
+        let rows = {
+            let store = self.store.lock().unwrap();
+            let query = Query::new(namespace, &store);
+            let rows = query.find_rows(model, finder, &path)?;
+            match include {
+                Some(include) => query.include(model, rows, include, &(&path + "include"))?,
+                None => rows,
+            }
+        };
+        let mut result = vec![];
+        for row in rows {
+            let object = transaction_ctx.new_object(model, action, request.clone())?;
+            object.set_from_database_result_value(&Value::Dictionary(row), select, include);
+            result.push(object);
+        }
+        Ok(result)
+    }
+
+    fn count_rows(&self, model: &Model, finder: &Value, transaction_ctx: &transaction::Ctx, path: &KeyPath) -> Result<Vec<Row>> {
+        let store = self.store.lock().unwrap();
+        Query::new(transaction_ctx.namespace(), &store).find_rows(model, finder, path)
+    }
+}
+
+/// The primary key values of the record as it's stored, keyed by field names.
+fn stored_identifier(object: &model::Object) -> IndexMap<String, Value> {
+    let model = object.model();
+    let identifier = object.db_identifier();
+    identifier.as_dictionary().unwrap().iter().map(|(column, value)| {
+        let key = model.field_with_column_name(column).map_or(column.clone(), |f| f.name().to_owned());
+        (key, value.clone())
+    }).collect()
+}
+
+fn check_unique(model: &Model, store: &Store, row: &Row, row_id: Option<u64>, path: &KeyPath) -> Result<()> {
+    let unique_keys = model.indexes().values().filter(|index| index.r#type().is_unique_or_primary()).map(|index| index.keys());
+    match duplicated_keys(store, model.path(), unique_keys, row, row_id) {
+        Some(keys) => Err(error_ext::unique_value_duplicated(path.clone(), keys.join(", "))),
+        None => Ok(()),
+    }
+}
+
+/// Returns the first of `unique_keys` whose values in `row` are taken by another row of the
+/// table. Keys with null values are never duplicated.
+fn duplicated_keys<'a>(store: &Store, table: &Vec<String>, unique_keys: impl Iterator<Item=&'a Vec<String>>, row: &Row, row_id: Option<u64>) -> Option<&'a Vec<String>> {
+    let rows = store.rows(table);
+    for keys in unique_keys {
+        let values: Vec<&Value> = keys.iter().map(|k| row.get(k).unwrap_or(&Value::Null)).collect();
+        if values.iter().any(|v| v.is_null()) {
+            continue;
+        }
+        let duplicated = rows.iter().any(|(id, other)| {
+            Some(*id) != row_id && keys.iter().zip(values.iter()).all(|(k, v)| other.get(k) == Some(*v))
+        });
+        if duplicated {
+            return Some(keys);
+        }
+    }
+    None
+}
+
+fn apply_atomic_updater(current: &Value, updater: &Value, path: &KeyPath) -> Result<Value> {
+    let Some((operator, operand)) = updater.as_dictionary().and_then(|map| map.iter().next()) else {
+        return Err(error_ext::unexpected_input(path.clone()));
+    };
+    Ok(match operator.as_str() {
+        "increment" => (current + operand)?,
+        "decrement" => (current - operand)?,
+        "multiply" => (current * operand)?,
+        "divide" => (current / operand)?,
+        "push" => {
+            let mut items = current.as_array().cloned().unwrap_or_default();
+            items.push(operand.clone());
+            Value::Array(items)
+        }
+        _ => return Err(error_ext::unexpected_input(path + operator.as_str())),
+    })
+}
+
+#[async_trait]
+impl Transaction for MemoryTransaction {
+
+    async fn migrate(&self, models: Vec<&Model>, _dry_run: bool, reset_database: bool, _silent: bool) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        if reset_database {
+            store.clear();
+        }
+        for model in models {
+            store.create_table(model.path());
+        }
+        Ok(())
+    }
+
+    async fn purge(&self, models: Vec<&Model>) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        for model in models {
+            store.clear_table(model.path());
+        }
+        Ok(())
+    }
+
+    async fn query_raw(&self, _value: &Value) -> Result<Value> {
+        Err(Error::new("in-memory database doesn't support raw queries"))
+    }
+
+    async fn save_object(&self, object: &model::Object, path: KeyPath) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        if object.is_new() {
+            self.insert_row(&mut store, object, &path)?;
+        } else {
+            self.update_row(&mut store, object, None, &path)?;
+        }
+        Ok(())
+    }
+
+    async fn save_object_if(&self, object: &model::Object, condition: &IndexMap<String, Value>, path: KeyPath) -> Result<bool> {
+        let mut store = self.store.lock().unwrap();
+        self.update_row(&mut store, object, Some(condition), &path)
+    }
+
+    /// Inserts the rows of all objects or none of them, like a multi-row insert does.
+    async fn save_objects(&self, objects: &[model::Object], path: KeyPath) -> Result<()> {
+        let Some(first) = objects.first() else {
+            return Ok(());
+        };
+        if let Some(index) = objects.iter().position(|object| !object.is_new()) {
+            return Err(error_ext::invalid_operation(&path + index, "save objects only inserts new objects"));
+        }
+        let mut store = self.store.lock().unwrap();
+        let mut row_ids = vec![];
+        for (index, object) in objects.iter().enumerate() {
+            match self.insert_row(&mut store, object, &(&path + index)) {
+                Ok(row_id) => row_ids.push(row_id),
+                Err(err) => {
+                    self.remove_inserted_rows(&mut store, first.model().path(), &row_ids);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_object(&self, object: &model::Object, path: KeyPath) -> Result<()> {
+        if object.is_new() {
+            return Err(error_ext::object_is_not_saved_thus_cant_be_deleted(path));
+        }
+        let model = object.model();
+        let mut store = self.store.lock().unwrap();
+        let Some((row_id, previous)) = store.find_row(model.path(), &stored_identifier(object)) else {
+            return Err(error_ext::unknown_database_delete_error(path, "record is not found"));
+        };
+        store.put(model.path(), row_id, None);
+        self.record_undo(model.path(), row_id, Some(previous));
+        Ok(())
+    }
+
+    async fn find_unique(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, transaction_ctx: transaction::Ctx, request: Option<Request>, path: KeyPath) -> Result<Option<model::Object>> {
+        let objects = self.find_rows(model, finder, ignore_select_and_include, action, transaction_ctx, request, path)?;
+        Ok(objects.into_iter().next())
+    }
+
+    async fn find_many(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, transaction_ctx: transaction::Ctx, request: Option<Request>, path: KeyPath) -> Result<Vec<model::Object>> {
+        self.find_rows(model, finder, ignore_select_and_include, action, transaction_ctx, request, path)
+    }
+
+    async fn count(&self, model: &Model, finder: &Value, transaction_ctx: transaction::Ctx, path: KeyPath) -> Result<Value> {
+        if finder.get("select").is_some() {
+            self.count_fields(model, finder, transaction_ctx, path).await
+        } else {
+            Ok(Value::Int64(self.count_objects(model, finder, transaction_ctx, path).await? as i64))
+        }
+    }
+
+    async fn count_objects(&self, model: &Model, finder: &Value, transaction_ctx: transaction::Ctx, path: KeyPath) -> Result<usize> {
+        Ok(self.count_rows(model, finder, &transaction_ctx, &path)?.len())
+    }
+
+    async fn count_fields(&self, model: &Model, finder: &Value, transaction_ctx: transaction::Ctx, path: KeyPath) -> Result<Value> {
+        let rows = self.count_rows(model, finder, &transaction_ctx, &path)?;
+        let select = finder.get("select").cloned().unwrap_or(Value::Dictionary(indexmap!{ "_all".to_owned() => Value::Bool(true) }));
+        let mut result = aggregate_rows(&rows, &Value::Dictionary(indexmap!{ "_count".to_owned() => select }), &path)?;
+        Ok(result.shift_remove("_count").unwrap())
+    }
+
+    async fn aggregate(&self, model: &Model, finder: &Value, transaction_ctx: transaction::Ctx, path: KeyPath) -> Result<Value> {
+        let rows = self.count_rows(model, finder, &transaction_ctx, &path)?;
+        Ok(Value::Dictionary(aggregate_rows(&rows, finder, &path)?))
+    }
+
+    async fn group_by(&self, model: &Model, finder: &Value, transaction_ctx: transaction::Ctx, path: KeyPath) -> Result<Vec<Value>> {
+        let store = self.store.lock().unwrap();
+        Query::new(transaction_ctx.namespace(), &store).group_by(model, finder, &path)
+    }
+
+    async fn sql(&self, _model: &Model, _sql: &str, _transaction_ctx: transaction::Ctx) -> Result<Vec<Value>> {
+        Err(Error::new("in-memory database doesn't support SQL"))
+    }
+
+    fn is_committed(&self) -> bool {
+        self.committed.load(Ordering::SeqCst)
+    }
+
+    fn is_transaction(&self) -> bool {
+        self.is_transaction
+    }
+
+    async fn commit(&self) -> Result<()> {
+        self.undo_log.lock().unwrap().clear();
+        self.committed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn abort(&self) -> Result<()> {
+        let mut store = self.store.lock().unwrap();
+        let mut undo_log = self.undo_log.lock().unwrap();
+        while let Some((path, row_id, previous)) = undo_log.pop() {
+            store.put(&path, row_id, previous);
+        }
+        Ok(())
+    }
+
+    async fn spawn(&self) -> Result<Arc<dyn Transaction>> {
+        Ok(Arc::new(MemoryTransaction::new(self.store.clone(), self.is_transaction)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Vec<String> {
+        vec!["User".to_owned()]
+    }
+
+    fn row(id: i64, email: Option<&str>) -> Row {
+        let mut row = Row::new();
+        row.insert("id".to_owned(), Value::Int64(id));
+        row.insert("email".to_owned(), email.map_or(Value::Null, |e| Value::String(e.to_owned())));
+        row
+    }
+
+    fn unique_keys() -> Vec<Vec<String>> {
+        vec![vec!["id".to_owned()], vec!["email".to_owned()]]
+    }
+
+    #[test]
+    fn duplicated_unique_values_are_found() {
+        let mut store = Store::default();
+        let row_id = store.insert(&table(), row(1, Some("a@example.com")));
+        let keys = unique_keys();
+        assert_eq!(duplicated_keys(&store, &table(), keys.iter(), &row(2, Some("a@example.com")), None), Some(&keys[1]));
+        assert_eq!(duplicated_keys(&store, &table(), keys.iter(), &row(1, Some("b@example.com")), None), Some(&keys[0]));
+        assert_eq!(duplicated_keys(&store, &table(), keys.iter(), &row(2, Some("b@example.com")), None), None);
+        // a row doesn't conflict with itself
+        assert_eq!(duplicated_keys(&store, &table(), keys.iter(), &row(1, Some("a@example.com")), Some(row_id)), None);
+    }
+
+    #[test]
+    fn null_unique_values_never_conflict() {
+        let mut store = Store::default();
+        store.insert(&table(), row(1, None));
+        let keys = unique_keys();
+        assert_eq!(duplicated_keys(&store, &table(), keys.iter(), &row(2, None), None), None);
+    }
+
+    fn stored_rows(store: &Arc<Mutex<Store>>) -> Vec<Row> {
+        store.lock().unwrap().rows(&table()).into_iter().map(|(_, row)| row).collect()
+    }
+
+    #[tokio::test]
+    async fn abort_undoes_writes_in_reverse_order() {
+        let store = Arc::new(Mutex::new(Store::default()));
+        let kept = store.lock().unwrap().insert(&table(), row(1, Some("a@example.com")));
+        let transaction = MemoryTransaction::new(store.clone(), true);
+        {
+            let mut store = store.lock().unwrap();
+            let inserted = store.insert(&table(), row(2, None));
+            transaction.record_undo(&table(), inserted, None);
+            let previous = store.put(&table(), kept, Some(row(1, Some("b@example.com"))));
+            transaction.record_undo(&table(), kept, previous);
+            let previous = store.put(&table(), kept, None);
+            transaction.record_undo(&table(), kept, previous);
+        }
+        transaction.abort().await.unwrap();
+        assert_eq!(stored_rows(&store), vec![row(1, Some("a@example.com"))]);
+    }
+
+    #[tokio::test]
+    async fn commit_keeps_writes() {
+        let store = Arc::new(Mutex::new(Store::default()));
+        let transaction = MemoryTransaction::new(store.clone(), true);
+        let inserted = store.lock().unwrap().insert(&table(), row(1, None));
+        transaction.record_undo(&table(), inserted, None);
+        transaction.commit().await.unwrap();
+        assert!(transaction.is_committed());
+        transaction.abort().await.unwrap();
+        assert_eq!(stored_rows(&store), vec![row(1, None)]);
+    }
+
+    #[test]
+    fn removed_inserted_rows_leave_no_undo_records() {
+        let store = Arc::new(Mutex::new(Store::default()));
+        let transaction = MemoryTransaction::new(store.clone(), true);
+        let mut guard = store.lock().unwrap();
+        let kept = guard.insert(&table(), row(1, None));
+        transaction.record_undo(&table(), kept, None);
+        let row_ids: Vec<u64> = vec![row(2, None), row(3, None)].into_iter().map(|row| {
+            let row_id = guard.insert(&table(), row);
+            transaction.record_undo(&table(), row_id, None);
+            row_id
+        }).collect();
+        transaction.remove_inserted_rows(&mut guard, &table(), &row_ids);
+        drop(guard);
+        assert_eq!(stored_rows(&store), vec![row(1, None)]);
+        assert_eq!(transaction.undo_log.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn writes_outside_transactions_are_not_recorded() {
+        let transaction = MemoryTransaction::new(Arc::new(Mutex::new(Store::default())), false);
+        transaction.record_undo(&table(), 1, None);
+        assert!(transaction.undo_log.lock().unwrap().is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 pub mod connection;
 pub mod transaction;
+pub mod memory;
 
 pub use connection::ctx::Ctx;
@@ -1,31 +1,67 @@
-use std::borrow::Borrow;
-use std::collections::BTreeMap;
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::sync::atomic::{AtomicBool, Ordering};
-use key_path::KeyPath;
+use key_path::{path, KeyPath};
+use chrono::Utc;
+use indexmap::indexmap;
 use maplit::btreemap;
+use serde_json::Value as JsonValue;
 use teo_result::{Result, Error};
 use crate::value::Value;
+use crate::teon;
 use crate::{connection, model};
 use crate::connection::connection::Connection;
 use crate::connection::transaction::{ExtractFromTransactionCtx, Transaction};
+use crate::connection::transaction::loader::{load_includes, take_include};
 use crate::model::Model;
+use crate::model::policy::{identity_values, policy_where, PolicyKind};
+use crate::model::tenant::tenant_field_value;
 use crate::namespace::Namespace;
 use crate::action::*;
 use crate::action::action::{CODE_AMOUNT, CODE_NAME, CODE_POSITION, CREATE, SINGLE};
 use crate::request::Request;
+use crate::model::change::{deliver_model_change, dispatch_model_changes, ModelChange, notify_model_written};
+use crate::model::object::change::model_change_from_outbox_entry;
 
 #[derive(Debug, Clone)]
 pub struct Ctx {
-    inner: Arc<Inner>
+    inner: Arc<Inner>,
+    bypass_policies: bool,
 }
 
 #[derive(Debug)]
 struct Inner {
     connection_ctx: connection::Ctx,
     is_transaction: AtomicBool,
-    transactions: tokio::sync::Mutex<BTreeMap<Vec<String>, Arc<dyn Transaction>>>
+    transactions: tokio::sync::Mutex<BTreeMap<Vec<String>, Arc<dyn Transaction>>>,
+    model_changes: std::sync::Mutex<Vec<ModelChange>>,
+    stored_files: std::sync::Mutex<Vec<(String, String)>>,
+    written_models: std::sync::Mutex<BTreeSet<Vec<String>>>,
+    policy_scope: std::sync::Mutex<PolicyScope>,
+    tenant: std::sync::Mutex<Option<Value>>,
+    identity_map: std::sync::Mutex<HashMap<IdentityKey, Weak<model::object::object::Inner>>>,
+}
+
+/// The key of an object in the identity map. Objects of the same record are distinct if
+/// they're loaded with different select and include arguments, since these decide what they
+/// output.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct IdentityKey {
+    model: Vec<String>,
+    identifier: String,
+    arguments: String,
+    action: u32,
+    bypass_policies: bool,
+}
+
+/// Whether the `@policy` of models apply to the queries of a ctx, and the identity they're
+/// evaluated against.
+#[derive(Debug, Clone)]
+enum PolicyScope {
+    Disabled,
+    Enforced(Option<Value>),
 }
 
 impl Ctx {
@@ -35,8 +71,15 @@ impl Ctx {
             inner: Arc::new(Inner {
                 connection_ctx,
                 is_transaction: AtomicBool::new(false),
-                transactions: tokio::sync::Mutex::new(btreemap!{})
-            })
+                transactions: tokio::sync::Mutex::new(btreemap!{}),
+                model_changes: std::sync::Mutex::new(vec![]),
+                stored_files: std::sync::Mutex::new(vec![]),
+                written_models: std::sync::Mutex::new(BTreeSet::new()),
+                policy_scope: std::sync::Mutex::new(PolicyScope::Disabled),
+                tenant: std::sync::Mutex::new(None),
+                identity_map: std::sync::Mutex::new(HashMap::new()),
+            }),
+            bypass_policies: false,
         }
     }
 
@@ -45,8 +88,15 @@ impl Ctx {
             inner: Arc::new(Inner {
                 connection_ctx: self.inner.connection_ctx.clone(),
                 is_transaction: AtomicBool::new(true),
-                transactions: tokio::sync::Mutex::new(btreemap!{})
-            })
+                transactions: tokio::sync::Mutex::new(btreemap!{}),
+                model_changes: std::sync::Mutex::new(vec![]),
+                stored_files: std::sync::Mutex::new(vec![]),
+                written_models: std::sync::Mutex::new(BTreeSet::new()),
+                policy_scope: std::sync::Mutex::new(self.inner.policy_scope.lock().unwrap().clone()),
+                tenant: std::sync::Mutex::new(self.tenant()),
+                identity_map: std::sync::Mutex::new(HashMap::new()),
+            }),
+            bypass_policies: self.bypass_policies,
         }
     }
 
@@ -55,11 +105,126 @@ impl Ctx {
             inner: Arc::new(Inner {
                 connection_ctx: self.inner.connection_ctx.clone(),
                 is_transaction: AtomicBool::new(false),
-                transactions: tokio::sync::Mutex::new(btreemap!{})
-            })
+                transactions: tokio::sync::Mutex::new(btreemap!{}),
+                model_changes: std::sync::Mutex::new(vec![]),
+                stored_files: std::sync::Mutex::new(vec![]),
+                written_models: std::sync::Mutex::new(BTreeSet::new()),
+                policy_scope: std::sync::Mutex::new(self.inner.policy_scope.lock().unwrap().clone()),
+                tenant: std::sync::Mutex::new(self.tenant()),
+                identity_map: std::sync::Mutex::new(HashMap::new()),
+            }),
+            bypass_policies: self.bypass_policies,
+        }
+    }
+
+    /// Applies the `@policy` of models to the queries made with this ctx, evaluated against
+    /// `identity`. Requests call this with no identity when they're created, and the identity
+    /// middlewares call it again once the identity is known.
+    pub fn enforce_policies(&self, identity: Option<&model::Object>) {
+        *self.inner.policy_scope.lock().unwrap() = PolicyScope::Enforced(identity.map(identity_values));
+    }
+
+    /// Returns a ctx sharing the connections and transactions of this ctx, whose queries are
+    /// not restricted by `@policy`. This is meant for internal queries like integrity checks.
+    pub fn bypassing_policies(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            bypass_policies: true,
+        }
+    }
+
+    /// The identity which the `@policy` of models is evaluated against. This is `None` if
+    /// policies don't apply, and `Value::Null` if they're evaluated without an identity.
+    pub(crate) fn policy_identity(&self) -> Option<Value> {
+        if self.bypass_policies {
+            return None;
+        }
+        match &*self.inner.policy_scope.lock().unwrap() {
+            PolicyScope::Disabled => None,
+            PolicyScope::Enforced(identity) => Some(identity.clone().unwrap_or(Value::Null)),
+        }
+    }
+
+    /// The where clause of the `@policy` of `model` which applies to this ctx.
+    pub(crate) fn policy_where(&self, model: &Model, kind: PolicyKind) -> Option<Value> {
+        if self.bypass_policies {
+            return None;
+        }
+        match &*self.inner.policy_scope.lock().unwrap() {
+            PolicyScope::Disabled => None,
+            PolicyScope::Enforced(identity) => policy_where(model, kind, identity.as_ref()),
         }
     }
 
+    /// Scopes the queries and the created records of `@tenant` models to `tenant`. Tenant
+    /// scoping is not affected by `bypassing_policies`.
+    pub fn set_tenant(&self, tenant: Option<Value>) {
+        *self.inner.tenant.lock().unwrap() = tenant;
+    }
+
+    pub fn tenant(&self) -> Option<Value> {
+        self.inner.tenant.lock().unwrap().clone()
+    }
+
+    /// The tenant field of `model` and its value for the current tenant.
+    pub(crate) fn tenant_where(&self, model: &Model) -> Result<Option<(String, Value)>> {
+        let Some(tenant) = self.tenant() else {
+            return Ok(None);
+        };
+        Ok(tenant_field_value(model, &tenant, self.namespace())?.map(|(field, value)| (field.to_owned(), value)))
+    }
+
+    /// Applies soft delete, the tenant and the read policy of `model` to the finder.
+    fn scoped_finder<'a>(&self, model: &Model, finder: &'a Value) -> Result<Cow<'a, Value>> {
+        let mut finder = soft_delete_finder(model, finder);
+        if let Some((field, value)) = self.tenant_where(model)? {
+            finder = Cow::Owned(and_where(finder.as_ref(), Value::Dictionary(indexmap! { field => value })));
+        }
+        if let Some(clause) = self.policy_where(model, PolicyKind::Read) {
+            finder = Cow::Owned(and_where(finder.as_ref(), clause));
+        }
+        Ok(finder)
+    }
+
+    /// Replaces each object with the object of the same record which is loaded earlier in this
+    /// transaction with the same `arguments`, if it's still in use, and remembers the others.
+    /// This keeps the include loader from materializing a related record more than once.
+    pub(crate) fn identity_mapped(&self, objects: Vec<model::Object>, arguments: &Value) -> Vec<model::Object> {
+        let Ok(arguments) = JsonValue::try_from(arguments) else {
+            return objects;
+        };
+        let arguments = arguments.to_string();
+        let mut map = self.inner.identity_map.lock().unwrap();
+        map.retain(|_, object| object.strong_count() > 0);
+        objects.into_iter().map(|object| {
+            let Ok(identifier) = JsonValue::try_from(&object.identifier()) else {
+                return object;
+            };
+            let key = IdentityKey {
+                model: object.model().path().clone(),
+                identifier: identifier.to_string(),
+                arguments: arguments.clone(),
+                action: object.action().into(),
+                bypass_policies: self.bypass_policies,
+            };
+            if let Some(inner) = map.get(&key).and_then(|o| o.upgrade()) {
+                return model::Object { inner };
+            }
+            map.insert(key, Arc::downgrade(&object.inner));
+            object
+        }).collect()
+    }
+
+    /// Removes the objects of a record from the identity map. This is called when the record is
+    /// written, so that later loads don't return stale objects.
+    pub(crate) fn forget_identity(&self, model: &Model, identifier: &Value) {
+        let Ok(identifier) = JsonValue::try_from(identifier) else {
+            return;
+        };
+        let identifier = identifier.to_string();
+        self.inner.identity_map.lock().unwrap().retain(|key, _| key.model != *model.path() || key.identifier != identifier);
+    }
+
     pub fn model_ctx_for_model_at_path(&self, path: &Vec<String>) -> Option<model::Ctx> {
         if let Some(model) = self.namespace().model_at_path(path) {
             Some(model::Ctx::new(self.clone(), model))
@@ -184,6 +349,15 @@ impl Ctx {
         }
         *self.inner.transactions.lock().await = btreemap! {};
         self.inner.is_transaction.store(false, Ordering::SeqCst);
+        self.inner.model_changes.lock().unwrap().clear();
+        self.inner.written_models.lock().unwrap().clear();
+        let stored_files = std::mem::take(&mut *self.inner.stored_files.lock().unwrap());
+        for (storage_name, key) in stored_files {
+            if let Some(storage) = self.namespace().file_storage(&storage_name) {
+                // the transaction is aborted anyway, a file which cannot be deleted is left behind
+                let _ = storage.delete(&key).await;
+            }
+        }
         Ok(())
     }
 
@@ -195,9 +369,70 @@ impl Ctx {
         }
         *self.inner.transactions.lock().await = btreemap! {};
         self.inner.is_transaction.store(false, Ordering::SeqCst);
+        self.inner.stored_files.lock().unwrap().clear();
+        let written_models = std::mem::take(&mut *self.inner.written_models.lock().unwrap());
+        for path in written_models.iter() {
+            notify_model_written(self.namespace(), path);
+        }
+        let model_changes = std::mem::take(&mut *self.inner.model_changes.lock().unwrap());
+        dispatch_model_changes(self.namespace(), model_changes).await;
         Ok(())
     }
 
+    /// Hands a change to the change listeners. Inside a transaction, the change is held until
+    /// the transaction is committed.
+    pub(crate) async fn push_model_change(&self, change: ModelChange) {
+        if self.inner.is_transaction.load(Ordering::SeqCst) {
+            self.inner.model_changes.lock().unwrap().push(change);
+        } else {
+            dispatch_model_changes(self.namespace(), vec![change]).await;
+        }
+    }
+
+    /// Reports a write of `model` to the write listeners. Inside a transaction, the write is
+    /// reported when the transaction is committed, since other requests can't see it before.
+    pub(crate) fn record_model_write(&self, model: &Model) {
+        if self.inner.is_transaction.load(Ordering::SeqCst) {
+            self.inner.written_models.lock().unwrap().insert(model.path().clone());
+        } else {
+            notify_model_written(self.namespace(), model.path());
+        }
+    }
+
+    /// Remembers a file which is put into a file storage for a record written in this
+    /// transaction, so that the file is deleted if the transaction is aborted.
+    pub(crate) fn push_stored_file(&self, storage_name: &str, key: &str) {
+        if self.inner.is_transaction.load(Ordering::SeqCst) {
+            self.inner.stored_files.lock().unwrap().push((storage_name.to_owned(), key.to_owned()));
+        }
+    }
+
+    /// Delivers up to `limit` pending entries of each outbox to the change listeners, oldest
+    /// first, and marks them as delivered. This is meant to be called periodically by a
+    /// background worker. Delivery stops at the first failing entry, which is retried on the
+    /// next call, so a change may be delivered more than once. Returns the number of delivered
+    /// entries.
+    pub async fn deliver_outbox(&self, limit: usize) -> Result<usize> {
+        let mut delivered = 0;
+        let take = limit as i64;
+        let finder = teon!({
+            "where": { "deliveredAt": null },
+            "orderBy": { "createdAt": "asc" },
+            "take": take,
+        });
+        for store in self.namespace().collect_models(|m| m.data().get("outbox:store").is_some()) {
+            let entries: Vec<model::Object> = self.find_many(store, &finder, None, path![]).await?;
+            for entry in entries {
+                let change = model_change_from_outbox_entry(&entry)?;
+                deliver_model_change(self.namespace(), change).await?;
+                entry.set_value("deliveredAt", Value::DateTime(Utc::now()))?;
+                entry.save().await?;
+                delivered += 1;
+            }
+        }
+        Ok(delivered)
+    }
+
     // database methods
 
     pub async fn find_unique<T: From<model::Object>>(&self, model: &Model, finder: &Value, request: Option<Request>, path: KeyPath) -> teo_result::Result<Option<T>> {
@@ -228,26 +463,51 @@ impl Ctx {
     }
 
     pub async fn find_unique_internal(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, request: Option<Request>, path: KeyPath) -> teo_result::Result<Option<model::Object>> {
+        // a unique where input cannot be extended with a tenant or a policy, it's a valid where
+        // input of find first though
+        if self.tenant_where(model)?.is_some() || self.policy_where(model, PolicyKind::Read).is_some() {
+            return self.find_first_internal(model, finder, ignore_select_and_include, action, request, path).await;
+        }
         let transaction = self.transaction_for_model(model).await;
-        transaction.find_unique(model, finder, ignore_select_and_include, action, self.clone(), request, path).await
+        let select = finder.get("select");
+        // a unique where input cannot be extended, soft deleted records are filtered afterwards
+        let (finder, with_deleted) = take_with_deleted(finder);
+        let (finder, include) = take_include(self.namespace(), model, finder.as_ref(), ignore_select_and_include);
+        let result = transaction.find_unique(model, finder.as_ref(), ignore_select_and_include, action, self.clone(), request.clone(), path.clone()).await?;
+        if let (Some(soft_delete), Some(object)) = (model.soft_delete(), result.as_ref()) {
+            if !with_deleted && !object.get_value(soft_delete)?.is_null() {
+                return Ok(None);
+            }
+        }
+        if let (Some(include), Some(object)) = (include, result.as_ref()) {
+            load_includes(self, model, &vec![object.clone()], &include, select, request, &(&path + "include")).await?;
+        }
+        Ok(result)
     }
 
     pub async fn find_first_internal(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, request: Option<Request>, path: KeyPath) -> teo_result::Result<Option<model::Object>> {
         let transaction = self.transaction_for_model(model).await;
-        let mut finder = finder.as_dictionary().clone().unwrap().clone();
+        let select = finder.get("select");
+        let (finder, include) = take_include(self.namespace(), model, finder, ignore_select_and_include);
+        let mut finder = self.scoped_finder(model, finder.as_ref())?.as_dictionary().clone().unwrap().clone();
         finder.insert("take".to_string(), Value::Int64(1));
         let finder = Value::Dictionary(finder);
-        let result = transaction.find_many(model, &finder, ignore_select_and_include, action, self.clone(), request, path).await?;
-        if result.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(result.get(0).unwrap().clone()))
+        let result = transaction.find_many(model, &finder, ignore_select_and_include, action, self.clone(), request.clone(), path.clone()).await?;
+        if let Some(include) = include {
+            load_includes(self, model, &result, &include, select, request, &(&path + "include")).await?;
         }
+        Ok(result.into_iter().next())
     }
 
     pub async fn find_many_internal(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, request: Option<Request>, path: KeyPath) -> teo_result::Result<Vec<model::Object>> {
         let transaction = self.transaction_for_model(model).await;
-        transaction.find_many(model, finder, ignore_select_and_include, action, self.clone(), request, path).await
+        let select = finder.get("select");
+        let (finder, include) = take_include(self.namespace(), model, finder, ignore_select_and_include);
+        let result = transaction.find_many(model, self.scoped_finder(model, finder.as_ref())?.as_ref(), ignore_select_and_include, action, self.clone(), request.clone(), path.clone()).await?;
+        if let Some(include) = include {
+            load_includes(self, model, &result, &include, select, request, &(&path + "include")).await?;
+        }
+        Ok(result)
     }
 
     pub async fn batch<F, Fut>(&self, model: &Model, finder: &Value, action: Action, request: Option<Request>, path: KeyPath, f: F) -> Result<()> where
@@ -272,28 +532,28 @@ impl Ctx {
 
     pub async fn count(&self, model: &Model, finder: &Value, path: KeyPath) -> teo_result::Result<Value> {
         let transaction = self.transaction_for_model(model).await;
-        transaction.count(model, finder, self.clone(), path).await
+        transaction.count(model, self.scoped_finder(model, finder)?.as_ref(), self.clone(), path).await
     }
 
     pub async fn count_objects(&self, model: &Model, finder: &Value, path: KeyPath) -> teo_result::Result<usize> {
         let transaction = self.transaction_for_model(model).await;
-        transaction.count_objects(model, finder, self.clone(), path).await
+        transaction.count_objects(model, self.scoped_finder(model, finder)?.as_ref(), self.clone(), path).await
     }
 
     pub async fn count_fields<T, E>(&self, model: &Model, finder: &Value, path: KeyPath) -> teo_result::Result<T> where T: TryFrom<Value, Error=E>, teo_result::Error: From<E> {
         let transaction = self.transaction_for_model(model).await;
-        let value = transaction.count_fields(model, finder, self.clone(), path).await?;
+        let value = transaction.count_fields(model, self.scoped_finder(model, finder)?.as_ref(), self.clone(), path).await?;
         Ok(value.try_into()?)
     }
 
     pub async fn aggregate(&self, model: &Model, finder: &Value, path: KeyPath) -> teo_result::Result<Value> {
         let transaction = self.transaction_for_model(model).await;
-        transaction.aggregate(model, finder, self.clone(), path).await
+        transaction.aggregate(model, self.scoped_finder(model, finder)?.as_ref(), self.clone(), path).await
     }
 
     pub async fn group_by(&self, model: &Model, finder: &Value, path: KeyPath) -> teo_result::Result<Vec<Value>> {
         let transaction = self.transaction_for_model(model).await;
-        transaction.group_by(model, finder, self.clone(), path).await
+        transaction.group_by(model, self.scoped_finder(model, finder)?.as_ref(), self.clone(), path).await
     }
 
     pub async fn sql<T, E>(&self, model: &Model, sql: &str) -> Result<Vec<T>> where T: TryFrom<Value, Error=E>, Error: From<E> {
@@ -312,7 +572,11 @@ impl Ctx {
     // MARK: - Create an object
 
     pub fn new_object(&self, model: &Model, action: Action, request: Option<Request>) -> Result<model::Object> {
-        Ok(model::Object::new(request, self.clone(), model, action))
+        let object = model::Object::new(request, self.clone(), model, action);
+        if let Some((field, value)) = self.tenant_where(model)? {
+            object.set_value(field, value)?;
+        }
+        Ok(object)
     }
 
     pub async fn new_object_with_teon_and_path<'a>(&self, model: &Model, initial: &Value, path: &KeyPath, action: Action, request: Option<Request>) -> Result<model::Object> {
@@ -343,4 +607,41 @@ impl ExtractFromTransactionCtx for Ctx {
     fn extract(ctx: &Ctx) -> Self {
         ctx.clone()
     }
-}
\ No newline at end of file
+}
+
+/// Removes the `withDeleted` argument from the finder, and returns whether it is set.
+fn take_with_deleted(finder: &Value) -> (Cow<Value>, bool) {
+    let Some(map) = finder.as_dictionary() else {
+        return (Cow::Borrowed(finder), false);
+    };
+    if !map.contains_key("withDeleted") {
+        return (Cow::Borrowed(finder), false);
+    }
+    let mut map = map.clone();
+    let with_deleted = map.shift_remove("withDeleted").map_or(false, |v| v.as_bool() == Some(true));
+    (Cow::Owned(Value::Dictionary(map)), with_deleted)
+}
+
+/// Excludes soft deleted records from the finder, unless `withDeleted` is set.
+fn soft_delete_finder<'a>(model: &Model, finder: &'a Value) -> Cow<'a, Value> {
+    let (finder, with_deleted) = take_with_deleted(finder);
+    let Some(soft_delete) = model.soft_delete() else {
+        return finder;
+    };
+    if with_deleted {
+        return finder;
+    }
+    let not_deleted = Value::Dictionary(indexmap! { soft_delete.to_owned() => Value::Null });
+    Cow::Owned(and_where(finder.as_ref(), not_deleted))
+}
+
+/// Combines the where input of the finder and `clause` with `AND`.
+pub(super) fn and_where(finder: &Value, clause: Value) -> Value {
+    let mut map = finder.as_dictionary().cloned().unwrap_or_default();
+    let r#where = match map.shift_remove("where") {
+        Some(r#where) => Value::Dictionary(indexmap! { "AND".to_owned() => Value::Array(vec![r#where, clause]) }),
+        None => clause,
+    };
+    map.insert("where".to_owned(), r#where);
+    Value::Dictionary(map)
+}
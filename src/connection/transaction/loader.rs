@@ -0,0 +1,302 @@
+use std::borrow::Cow;
+use async_recursion::async_recursion;
+use indexmap::IndexMap;
+use key_path::KeyPath;
+use teo_result::Result;
+use crate::action::Action;
+use crate::action::action::{FIND, MANY, NESTED, SINGLE};
+use crate::connection::transaction::Ctx;
+use crate::connection::transaction::ctx::and_where;
+use crate::error_ext;
+use crate::model;
+use crate::model::{Model, Relation};
+use crate::namespace::Namespace;
+use crate::request::Request;
+use crate::teon;
+use crate::value::Value;
+
+/// The include arguments which apply to the related records of each parent record. Relations
+/// with these are loaded for each parent separately.
+const PER_PARENT_ARGUMENTS: [&str; 6] = ["take", "skip", "cursor", "distinct", "pageSize", "pageNumber"];
+
+/// Removes the `include` argument from the finder, so that the connector doesn't resolve it,
+/// and returns it. The keys which the included relations are joined on are added to the
+/// `select` argument, since connectors may fetch only the selected fields. `load_includes`
+/// sets the selection of the loaded objects back, so these keys are output only if selected.
+pub(super) fn take_include<'a>(namespace: &Namespace, model: &Model, finder: &'a Value, ignore_select_and_include: bool) -> (Cow<'a, Value>, Option<Value>) {
+    if ignore_select_and_include {
+        return (Cow::Borrowed(finder), None);
+    }
+    let Some(map) = finder.as_dictionary() else {
+        return (Cow::Borrowed(finder), None);
+    };
+    if !map.contains_key("include") {
+        return (Cow::Borrowed(finder), None);
+    }
+    let mut map = map.clone();
+    let include = map.shift_remove("include");
+    if let (Some(select), Some(include)) = (map.get_mut("select"), include.as_ref().and_then(|i| i.as_dictionary())) {
+        let mut keys: Vec<&String> = vec![];
+        for (name, arguments) in include {
+            if arguments.is_false() {
+                continue;
+            }
+            if let Some(relation) = model.relation(name) {
+                keys.extend(parent_join_keys(namespace, relation));
+            }
+        }
+        *select = with_selected_keys(select, &keys);
+    }
+    (Cow::Owned(Value::Dictionary(map)), include)
+}
+
+/// The keys of the parent records which `relation` is joined on.
+fn parent_join_keys<'a>(namespace: &'a Namespace, relation: &'a Relation) -> &'a Vec<String> {
+    match relation.through_path().and_then(|path| namespace.model_at_path(path)) {
+        Some(through_model) => through_model.relation(relation.local().unwrap()).unwrap().references(),
+        None => relation.fields(),
+    }
+}
+
+/// Adds `keys` to a `select` argument. A selection of fields is extended with the keys, and
+/// keys which are deselected are selected again.
+fn with_selected_keys(select: &Value, keys: &Vec<&String>) -> Value {
+    let Some(map) = select.as_dictionary() else {
+        return select.clone();
+    };
+    let mut map = map.clone();
+    let selects_fields = map.values().any(|v| v.as_bool() == Some(true));
+    for key in keys {
+        if map.get(key.as_str()).map_or(false, |v| v.as_bool() == Some(false)) {
+            map.shift_remove(key.as_str());
+        } else if selects_fields && !map.contains_key(key.as_str()) {
+            map.insert(key.to_string(), Value::Bool(true));
+        }
+    }
+    Value::Dictionary(map)
+}
+
+/// Sets the selection of `objects` back to the `select` argument of the request, which the
+/// join keys are added to by `take_include` and `find_related`.
+fn restore_select(objects: &Vec<model::Object>, select: Option<&Value>) -> Result<()> {
+    if let Some(select) = select {
+        for object in objects {
+            object.set_select(Some(select))?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the relations requested by `include` for all of `objects` together. Each relation
+/// is fetched with a single query for all parents, or two for relations with a join table,
+/// and the results are distributed to the parents. Nested includes are loaded the same way,
+/// one query per relation level. `select` is the select argument of the parents.
+#[async_recursion]
+pub(super) async fn load_includes(ctx: &Ctx, model: &Model, objects: &Vec<model::Object>, include: &Value, select: Option<&Value>, request: Option<Request>, path: &KeyPath) -> Result<()> {
+    let Some(include) = include.as_dictionary() else {
+        return Err(error_ext::unexpected_input(path.clone()));
+    };
+    if objects.is_empty() {
+        return Ok(());
+    }
+    restore_select(objects, select)?;
+    for (key, arguments) in include {
+        if arguments.is_false() {
+            continue;
+        }
+        let path = path + key.as_str();
+        let Some(relation) = model.relation(key) else {
+            return Err(error_ext::invalid_key_on_model(path, key, model));
+        };
+        let arguments = if arguments.is_dictionary() { arguments.clone() } else { teon!({}) };
+        let per_parent = arguments.as_dictionary().unwrap().keys().any(|k| PER_PARENT_ARGUMENTS.contains(&k.as_str()));
+        if per_parent {
+            for object in objects {
+                load_relation(ctx, relation, &vec![object.clone()], &arguments, request.clone(), &path).await?;
+            }
+        } else {
+            load_relation(ctx, relation, objects, &arguments, request.clone(), &path).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn load_relation(ctx: &Ctx, relation: &Relation, objects: &Vec<model::Object>, arguments: &Value, request: Option<Request>, path: &KeyPath) -> Result<()> {
+    let namespace = ctx.namespace();
+    let related_model = namespace.model_at_path(relation.model_path()).unwrap();
+    let action = FIND | NESTED | (if relation.is_vec() { MANY } else { SINGLE });
+    if let Some(through_path) = relation.through_path() {
+        let through_model = namespace.model_at_path(through_path).unwrap();
+        let local = through_model.relation(relation.local().unwrap()).unwrap();
+        let foreign = through_model.relation(relation.foreign().unwrap()).unwrap();
+        let parent_keys = key_values(objects, local.references())?;
+        let Some(through_where) = where_in(&parent_keys, local.fields()) else {
+            set_no_related(objects, relation);
+            return Ok(());
+        };
+        let through_objects = ctx.find_many_internal(through_model, &teon!({ "where": through_where }), true, FIND | NESTED | MANY, request.clone(), path.clone()).await?;
+        let through_local_keys = key_values(&through_objects, local.fields())?;
+        let through_foreign_keys = key_values(&through_objects, foreign.fields())?;
+        let Some(related_where) = where_in(&through_foreign_keys, foreign.references()) else {
+            set_no_related(objects, relation);
+            return Ok(());
+        };
+        let related = find_related(ctx, related_model, arguments, related_where, foreign.references(), action, request, path).await?;
+        let related_keys = key_values(&related, foreign.references())?;
+        let distribution = distribute_through(&parent_keys, &through_local_keys, &through_foreign_keys, &related_keys);
+        set_related(objects, relation, &related, distribution);
+    } else {
+        let parent_keys = key_values(objects, relation.fields())?;
+        let Some(related_where) = where_in(&parent_keys, relation.references()) else {
+            set_no_related(objects, relation);
+            return Ok(());
+        };
+        let related = find_related(ctx, related_model, arguments, related_where, relation.references(), action, request, path).await?;
+        let related_keys = key_values(&related, relation.references())?;
+        let distribution = distribute(&parent_keys, &related_keys);
+        set_related(objects, relation, &related, distribution);
+    }
+    Ok(())
+}
+
+/// Fetches the related records matching `related_where` and the include arguments. The
+/// results go through the identity map of the transaction. The `keys` which the records are
+/// joined on are fetched even if they're not selected.
+async fn find_related(ctx: &Ctx, model: &Model, arguments: &Value, related_where: Value, keys: &Vec<String>, action: Action, request: Option<Request>, path: &KeyPath) -> Result<Vec<model::Object>> {
+    let mut finder = and_where(arguments, related_where);
+    let select = arguments.get("select");
+    if let (Some(select), Some(map)) = (select, finder.as_dictionary_mut()) {
+        map.insert("select".to_owned(), with_selected_keys(select, &keys.iter().collect()));
+    }
+    let related = ctx.find_many_internal(model, &finder, false, action, request, path.clone()).await?;
+    restore_select(&related, select)?;
+    Ok(ctx.identity_mapped(related, arguments))
+}
+
+fn set_no_related(objects: &Vec<model::Object>, relation: &Relation) {
+    for object in objects {
+        object.set_query_relation_objects(relation.name(), vec![]);
+    }
+}
+
+fn set_related(objects: &Vec<model::Object>, relation: &Relation, related: &Vec<model::Object>, distribution: Vec<Vec<usize>>) {
+    for (object, indexes) in objects.iter().zip(distribution) {
+        object.set_query_relation_objects(relation.name(), indexes.into_iter().map(|index| related[index].clone()).collect());
+    }
+}
+
+/// The values of `keys` of each object. Values with a null never join, these are `None`.
+fn key_values(objects: &Vec<model::Object>, keys: &Vec<String>) -> Result<Vec<Option<Vec<Value>>>> {
+    let mut result = vec![];
+    for object in objects {
+        let mut values = vec![];
+        for key in keys {
+            values.push(object.get_value(key)?);
+        }
+        result.push(if values.iter().any(|v| v.is_null()) { None } else { Some(values) });
+    }
+    Ok(result)
+}
+
+/// Builds the where input which matches the records whose `keys` equal any of `values`, or
+/// returns `None` if there's nothing to match.
+fn where_in(values: &Vec<Option<Vec<Value>>>, keys: &Vec<String>) -> Option<Value> {
+    let mut tuples: Vec<&Vec<Value>> = vec![];
+    for tuple in values.iter().flatten() {
+        if !tuples.contains(&tuple) {
+            tuples.push(tuple);
+        }
+    }
+    if tuples.is_empty() {
+        return None;
+    }
+    if keys.len() == 1 {
+        let values = tuples.into_iter().map(|tuple| tuple[0].clone()).collect();
+        return Some(teon!({ keys[0].as_str(): { "in": Value::Array(values) } }));
+    }
+    let alternatives = tuples.into_iter().map(|tuple| {
+        Value::Dictionary(keys.iter().cloned().zip(tuple.iter().cloned()).collect::<IndexMap<String, Value>>())
+    }).collect();
+    Some(teon!({ "OR": Value::Array(alternatives) }))
+}
+
+fn joins(values: &Option<Vec<Value>>, other: &Option<Vec<Value>>) -> bool {
+    match (values, other) {
+        (Some(values), Some(other)) => values == other,
+        _ => false,
+    }
+}
+
+/// Returns the indexes of the related records of each parent, in the order of the related
+/// records.
+fn distribute(parent_keys: &Vec<Option<Vec<Value>>>, related_keys: &Vec<Option<Vec<Value>>>) -> Vec<Vec<usize>> {
+    parent_keys.iter().map(|parent| {
+        related_keys.iter().enumerate().filter(|(_, related)| joins(parent, related)).map(|(index, _)| index).collect()
+    }).collect()
+}
+
+/// Returns the indexes of the related records of each parent which are linked to the parent by
+/// a record of the join table, in the order of the related records.
+fn distribute_through(parent_keys: &Vec<Option<Vec<Value>>>, through_local_keys: &Vec<Option<Vec<Value>>>, through_foreign_keys: &Vec<Option<Vec<Value>>>, related_keys: &Vec<Option<Vec<Value>>>) -> Vec<Vec<usize>> {
+    distribute(parent_keys, through_local_keys).into_iter().map(|through_indexes| {
+        related_keys.iter().enumerate().filter(|(_, related)| {
+            through_indexes.iter().any(|index| joins(&through_foreign_keys[*index], related))
+        }).map(|(index, _)| index).collect()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(values: Vec<Option<i64>>) -> Vec<Option<Vec<Value>>> {
+        values.into_iter().map(|v| v.map(|v| vec![Value::Int64(v)])).collect()
+    }
+
+    #[test]
+    fn related_records_are_distributed_by_keys() {
+        let parents = keys(vec![Some(1), Some(2), None, Some(3)]);
+        let related = keys(vec![Some(2), Some(1), Some(2), None]);
+        assert_eq!(distribute(&parents, &related), vec![vec![1], vec![0, 2], vec![], vec![]]);
+    }
+
+    #[test]
+    fn compound_keys_join_on_every_value() {
+        let parents = vec![Some(vec![Value::Int64(1), Value::String("a".to_owned())])];
+        let related = vec![
+            Some(vec![Value::Int64(1), Value::String("b".to_owned())]),
+            Some(vec![Value::Int64(1), Value::String("a".to_owned())]),
+        ];
+        assert_eq!(distribute(&parents, &related), vec![vec![1]]);
+    }
+
+    #[test]
+    fn join_table_records_link_parents_and_related_records() {
+        let parents = keys(vec![Some(1), Some(2), Some(3)]);
+        // links 1 -> 10, 1 -> 20, 2 -> 20
+        let through_local = keys(vec![Some(1), Some(1), Some(2)]);
+        let through_foreign = keys(vec![Some(10), Some(20), Some(20)]);
+        let related = keys(vec![Some(20), Some(10)]);
+        assert_eq!(distribute_through(&parents, &through_local, &through_foreign, &related), vec![vec![0, 1], vec![0], vec![]]);
+    }
+
+    #[test]
+    fn where_in_skips_nulls_and_duplicates() {
+        let id = vec!["id".to_owned()];
+        assert_eq!(where_in(&keys(vec![None]), &id), None);
+        assert_eq!(where_in(&keys(vec![Some(1), None, Some(2), Some(1)]), &id), Some(teon!({ "id": { "in": [1, 2] } })));
+        let compound = vec!["a".to_owned(), "b".to_owned()];
+        let values = vec![Some(vec![Value::Int64(1), Value::Int64(2)])];
+        assert_eq!(where_in(&values, &compound), Some(teon!({ "OR": [{ "a": 1, "b": 2 }] })));
+    }
+
+    #[test]
+    fn join_keys_are_selected() {
+        let id = "id".to_owned();
+        let keys = vec![&id];
+        assert_eq!(with_selected_keys(&teon!({ "name": true }), &keys), teon!({ "name": true, "id": true }));
+        assert_eq!(with_selected_keys(&teon!({ "id": false, "name": false }), &keys), teon!({ "name": false }));
+        assert_eq!(with_selected_keys(&teon!({ "name": false }), &keys), teon!({ "name": false }));
+        assert_eq!(with_selected_keys(&teon!({ "id": true }), &keys), teon!({ "id": true }));
+    }
+}
@@ -1,6 +1,7 @@
 pub mod ctx;
 pub mod transaction;
 pub mod extract;
+mod loader;
 
 pub use transaction::Transaction;
 pub use ctx::Ctx;
@@ -1,10 +1,11 @@
 use std::fmt::Debug;
 use std::sync::Arc;
 use async_trait::async_trait;
+use indexmap::IndexMap;
 use key_path::KeyPath;
 use crate::value::Value;
 use crate::action::Action;
-use teo_result::Result;
+use teo_result::{Error, Result};
 use crate::model;
 use crate::connection::transaction;
 use crate::model::Model;
@@ -29,6 +30,29 @@ pub trait Transaction: Send + Sync + Debug {
 
     async fn save_object(&self, object: &model::Object, path: KeyPath) -> Result<()>;
 
+    /// Inserts new objects of the same model. Errors of the object at `index` are reported at
+    /// `path + index`.
+    ///
+    /// This is only a hook: the default saves the objects one by one with `save_object`, and a
+    /// failing object leaves the ones before it inserted. Connectors which can insert many rows
+    /// in one statement override this, insert all of the objects or none of them, and assign
+    /// the generated values like auto increment identifiers to the objects.
+    async fn save_objects(&self, objects: &[model::Object], path: KeyPath) -> Result<()> {
+        for (index, object) in objects.iter().enumerate() {
+            self.save_object(object, &path + index).await?;
+        }
+        Ok(())
+    }
+
+    /// Updates a saved object only if the stored record still has the values of `condition`,
+    /// and returns whether it's updated. The condition must be put into the where clause of the
+    /// update and the affected rows checked, so that no other write can come in between. A
+    /// check before the write can't guarantee this, so connectors which support `@version`
+    /// must override this.
+    async fn save_object_if(&self, object: &model::Object, _condition: &IndexMap<String, Value>, _path: KeyPath) -> Result<bool> {
+        Err(Error::new(format!("connector doesn't support conditional updates of {}", object.model().path().join("."))))
+    }
+
     async fn delete_object(&self, object: &model::Object, path: KeyPath) -> Result<()>;
 
     async fn find_unique(&self, model: &Model, finder: &Value, ignore_select_and_include: bool, action: Action, transaction_ctx: transaction::Ctx, request: Option<Request>, path: KeyPath) -> Result<Option<model::Object>>;
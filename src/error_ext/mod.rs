@@ -35,6 +35,16 @@ pub fn updation_denied(path: KeyPath, relation_name: &str) -> Error {
     Error::invalid_request_pathed(path, format!("updation denied {}", relation_name))
 }
 
+pub fn version_conflict(path: KeyPath, model: &Model) -> Error {
+    let mut error = Error::invalid_request_pathed(path, format!("record of model {} is modified by others", model.path().join(".")));
+    error.code = 409;
+    error
+}
+
+pub fn write_denied_by_policy(path: KeyPath, model: &Model) -> Error {
+    Error::unauthorized_pathed(path, format!("not allowed to write record of model {}", model.path().join(".")))
+}
+
 pub fn invalid_operation(path: KeyPath, reason: impl AsRef<str>) -> Error {
     Error::internal_server_error_pathed(path, reason.as_ref())
 }
@@ -52,7 +62,15 @@ pub fn unknown_database_find_error(path: KeyPath, reason: impl AsRef<str>) -> Er
 }
 
 pub fn unique_value_duplicated(path: KeyPath, field: impl AsRef<str>) -> Error {
-    Error::invalid_request_pathed(path, format!("unique value duplicated: {}", field.as_ref()))
+    let mut error = Error::invalid_request_pathed(path, format!("unique value duplicated: {}", field.as_ref()));
+    error.code = 409;
+    error
+}
+
+/// Whether `error` reports a conflict with the saved records, a duplicated unique value or a
+/// version conflict.
+pub fn is_conflict(error: &Error) -> bool {
+    error.code == 409
 }
 
 pub fn invalid_sql_query(reason: impl AsRef<str>) -> Error {
@@ -12,12 +12,27 @@ pub fn builtin_action_handler_from_name(name: &str) -> Option<Action> {
         "delete" => DELETE_HANDLER,
         "copy" => COPY_HANDLER,
         "createMany" => CREATE_MANY_HANDLER,
+        "upsertMany" => UPSERT_MANY_HANDLER,
         "updateMany" => UPDATE_MANY_HANDLER,
         "deleteMany" => DELETE_MANY_HANDLER,
         "copyMany" => COPY_MANY_HANDLER,
         "count" => COUNT_HANDLER,
         "aggregate" => AGGREGATE_HANDLER,
         "groupBy" => GROUP_BY_HANDLER,
+        "export" => EXPORT_HANDLER,
         _ => None?
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_handlers_are_found_by_name() {
+        for action in Action::builtin_handlers() {
+            assert_eq!(builtin_action_handler_from_name(action.as_handler_str()), Some(*action));
+        }
+        assert!(Action::builtin_handlers().any(|action| *action == UPSERT_MANY_HANDLER));
+        assert_eq!(builtin_action_handler_from_name("upsertMany"), Some(UPSERT_MANY_HANDLER));
+    }
+}
@@ -7,6 +7,7 @@ use crate::app::data::AppData;
 use crate::handler::{Handler, handler};
 use hyper::Method;
 use crate::middleware::next::Next;
+use crate::request::multipart::UploadLimits;
 
 #[derive(Debug, Clone)]
 pub struct Builder {
@@ -26,8 +27,9 @@ struct Inner {
     url: Arc<Mutex<Option<String>>>,
     interface: Arc<Mutex<Option<String>>>,
     ignore_prefix: AtomicBool,
+    upload_limits: Arc<Mutex<UploadLimits>>,
     #[educe(Debug(ignore))]
-    call: Next,
+    call: Arc<Mutex<Next>>,
     app_data: AppData,
 }
 
@@ -46,7 +48,8 @@ impl Builder {
                 url: Arc::new(Mutex::new(None)),
                 interface: Arc::new(Mutex::new(None)),
                 ignore_prefix: AtomicBool::new(false),
-                call,
+                upload_limits: Arc::new(Mutex::new(UploadLimits::default())),
+                call: Arc::new(Mutex::new(call)),
                 app_data
             })
         }
@@ -112,8 +115,21 @@ impl Builder {
         self.inner.ignore_prefix.store(ignore_prefix, std::sync::atomic::Ordering::Relaxed);
     }
 
+    pub fn upload_limits(&self) -> UploadLimits {
+        self.inner.upload_limits.lock().unwrap().clone()
+    }
+
+    pub fn set_upload_limits(&self, upload_limits: UploadLimits) {
+        *self.inner.upload_limits.lock().unwrap() = upload_limits;
+    }
+
     pub fn call(&self) -> Next {
-        self.inner.call.clone()
+        self.inner.call.lock().unwrap().clone()
+    }
+
+    /// Replaces the call of the handler. Decorators use this to wrap the handler.
+    pub fn set_call(&self, call: Next) {
+        *self.inner.call.lock().unwrap() = call;
     }
 
     pub fn app_data(&self) -> &AppData {
@@ -133,7 +149,8 @@ impl Builder {
                 url: self.inner.url.lock().unwrap().clone(),
                 interface: self.inner.interface.lock().unwrap().clone(),
                 ignore_prefix: self.inner.ignore_prefix.load(std::sync::atomic::Ordering::Relaxed),
-                call: self.inner.call.clone(),
+                upload_limits: self.inner.upload_limits.lock().unwrap().clone(),
+                call: self.inner.call.lock().unwrap().clone(),
             })
         }
     }
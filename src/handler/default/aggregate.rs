@@ -1,9 +1,10 @@
 use key_path::path;
+use crate::handler::default::internal::conditional::conditional_read_response;
 use crate::request::Request;
 use crate::response::Response;
 
 pub async fn aggregate(request: Request) -> teo_result::Result<Response> {
     let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match()?.path()).unwrap().clone();
     let result = request.transaction_ctx().aggregate(&model, request.body_value()?, path![]).await?;
-    Ok(Response::data(result))
+    conditional_read_response(&request, Response::data(result))
 }
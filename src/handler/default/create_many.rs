@@ -1,15 +1,18 @@
 use key_path::path;
+use teo_result::Error;
 use crate::value::Value;
 use crate::teon;
 use crate::response::Response;
 use crate::action::action::*;
 use crate::connection::transaction;
-use crate::handler::default::internal::create::create_internal;
+use crate::handler::default::internal::conflict::{conflict_indexes, Outcome, outcomes_value, save_unless_conflicting};
+use crate::handler::default::internal::create::new_object_internal;
+use crate::model::object::batch::save_new_objects;
 use crate::request::Request;
 
 pub async fn create_many(request: Request) -> teo_result::Result<Response> {
     let action = CREATE | MANY | ENTRY;
-    let (objects, count) = request.transaction_ctx().run_transaction(move |ctx: transaction::Ctx| {
+    let (objects, count, outcomes) = request.transaction_ctx().run_transaction(move |ctx: transaction::Ctx| {
         let request = request.clone();
         let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
         async move {
@@ -17,16 +20,56 @@ pub async fn create_many(request: Request) -> teo_result::Result<Response> {
             let create = input.get("create");
             let include = input.get("include");
             let select = input.get("select");
+            let skip_duplicates = input.get("skipDuplicates").and_then(|v| v.as_bool()).unwrap_or(false);
+            let conflict_target = input.get("conflictTarget").filter(|v| !v.is_null());
+            if conflict_target.is_some() && !skip_duplicates {
+                return Err(Error::invalid_request_pathed(path!["conflictTarget"], "conflictTarget requires skipDuplicates"));
+            }
+            let indexes = if skip_duplicates {
+                conflict_indexes(&model, conflict_target, &path!["conflictTarget"])?
+            } else {
+                vec![]
+            };
             let create = create.unwrap().as_array().unwrap();
             let mut count = 0;
             let mut ret_data: Vec<Value> = vec![];
+            let mut outcomes: Vec<Outcome> = vec![];
+            // records without nested relation inputs are handed to the connector in one call,
+            // duplicates are skipped while saving records one by one
+            let has_relation_inputs = create.iter().any(|val| val.as_dictionary().map_or(false, |d| d.keys().any(|k| model.relation(k).is_some())));
+            if !has_relation_inputs && !skip_duplicates {
+                let mut objects = vec![];
+                for (index, val) in create.iter().enumerate() {
+                    let path = path!["create", index];
+                    objects.push(new_object_internal(ctx.clone(), request.clone(), Some(val), &model, &path, action).await?);
+                    outcomes.push(Outcome::Created);
+                }
+                save_new_objects(&model, &objects, &path!["create"]).await?;
+                for object in objects {
+                    let refreshed = object.refreshed(include, select).await?;
+                    ret_data.push(refreshed.to_teon_internal(&path!["data"]).await?);
+                    count += 1;
+                }
+                return Ok((ret_data, count, outcomes));
+            }
             for (index, val) in create.iter().enumerate() {
-                let result = create_internal(ctx.clone(), request.clone(), Some(val), include, select, &model, &path!["create", index], action).await?;
+                let path = path!["create", index];
+                let object = new_object_internal(ctx.clone(), request.clone(), Some(val), &model, &path, action).await?;
+                if skip_duplicates {
+                    if save_unless_conflicting(&ctx, &model, &indexes, &object, action, &request, &path).await?.is_some() {
+                        outcomes.push(Outcome::Skipped);
+                        continue;
+                    }
+                } else {
+                    object.save_with_session_and_path(&path).await?;
+                }
+                let refreshed = object.refreshed(include, select).await?;
+                ret_data.push(refreshed.to_teon_internal(&path!["data"]).await?);
+                outcomes.push(Outcome::Created);
                 count += 1;
-                ret_data.push(result);
             }
-            Ok((ret_data, count))
+            Ok((ret_data, count, outcomes))
         }
     }).await?;
-    Ok(Response::data_meta(Value::Array(objects), teon!({"count": count})))
+    Ok(Response::data_meta(Value::Array(objects), teon!({"count": count, "outcomes": outcomes_value(&outcomes)})))
 }
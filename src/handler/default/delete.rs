@@ -4,6 +4,7 @@ use crate::request::Request;
 use crate::response::Response;
 use crate::action::action::*;
 use crate::connection::transaction;
+use crate::handler::default::internal::conditional::check_if_match;
 use crate::model::object::object::ErrorIfNotFound;
 
 pub async fn delete(request: Request) -> teo_result::Result<Response> {
@@ -13,6 +14,7 @@ pub async fn delete(request: Request) -> teo_result::Result<Response> {
         let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
         async move {
             let object = ctx.find_unique_internal(&model, request.body_value()?, true, action, Some(request.clone()), path![]).await.into_not_found_error(path![])?;
+            check_if_match(&request, &object).await?;
             object.delete_internal(path!["delete"]).await?;
             Ok(object.to_teon_internal(&path!["data"]).await?)
         }
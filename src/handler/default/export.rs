@@ -0,0 +1,312 @@
+use std::sync::Mutex;
+use bytes::Bytes;
+use futures::StreamExt;
+use hyper::header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE};
+use key_path::path;
+use serde_json::Value as JsonValue;
+use tokio::sync::mpsc;
+use teo_result::Error;
+use crate::action::action::*;
+use crate::handler::default::internal::cursor::CursorOrdering;
+use crate::model::Model;
+use crate::request::Request;
+use crate::response::{Body, Response};
+use crate::teon;
+use crate::value::Value;
+
+/// The number of records fetched at once.
+const EXPORT_BATCH_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+
+    fn from_request(request: &Request) -> teo_result::Result<Self> {
+        if let Some(query) = request.query() {
+            if let Some(format) = query.split("&").find_map(|item| item.strip_prefix("format=")) {
+                return match format {
+                    "ndjson" => Ok(ExportFormat::Ndjson),
+                    "csv" => Ok(ExportFormat::Csv),
+                    _ => Err(Error::invalid_request_pathed(path!["format"], "unsupported export format")),
+                };
+            }
+        }
+        match request.headers().get(ACCEPT.as_str())? {
+            Some(accept) if accept.contains("text/csv") => Ok(ExportFormat::Csv),
+            _ => Ok(ExportFormat::Ndjson),
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+fn csv_cell(value: Option<&JsonValue>) -> String {
+    let text = match value {
+        None | Some(JsonValue::Null) => return "".to_owned(),
+        Some(JsonValue::String(string)) => string.clone(),
+        Some(value) => value.to_string(),
+    };
+    if text.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", text.replace("\"", "\"\""))
+    } else {
+        text
+    }
+}
+
+/// Formats a row as CSV. The columns are taken from the first exported row, which is preceded
+/// by the header line.
+fn csv_line(json: &JsonValue, columns: &Mutex<Option<Vec<String>>>) -> String {
+    let mut result = String::new();
+    let mut columns = columns.lock().unwrap();
+    if columns.is_none() {
+        let keys: Vec<String> = json.as_object().map_or(vec![], |o| o.keys().cloned().collect());
+        result.push_str(&keys.iter().map(|k| csv_cell(Some(&JsonValue::String(k.clone())))).collect::<Vec<String>>().join(","));
+        result.push_str("\r\n");
+        *columns = Some(keys);
+    }
+    let cells: Vec<String> = columns.as_ref().unwrap().iter().map(|k| csv_cell(json.get(k))).collect();
+    result.push_str(&cells.join(","));
+    result.push_str("\r\n");
+    result
+}
+
+/// The keyset pages of an export. Each batch starts after the last record of the previous one.
+struct Batches {
+    finder: Value,
+    r#where: Option<Value>,
+    ordering: CursorOrdering,
+    last: Option<Vec<Value>>,
+    exported: usize,
+    done: bool,
+}
+
+impl Batches {
+
+    fn new(mut finder: Value, ordering: CursorOrdering) -> Self {
+        let finder_obj = finder.as_dictionary_mut().unwrap();
+        for key in ["skip", "take", "pageSize", "pageNumber"] {
+            finder_obj.shift_remove(key);
+        }
+        finder_obj.insert("orderBy".to_owned(), ordering.order_by());
+        finder_obj.insert("take".to_owned(), Value::Int64(EXPORT_BATCH_SIZE as i64));
+        let r#where = finder_obj.shift_remove("where");
+        Self { finder, r#where, ordering, last: None, exported: 0, done: false }
+    }
+
+    /// The finder of the next batch, `None` after the last batch is fetched.
+    fn next_finder(&self) -> Option<Value> {
+        if self.done {
+            return None;
+        }
+        let mut finder = self.finder.clone();
+        let batch_where = match (self.r#where.clone(), self.last.as_ref()) {
+            (Some(r#where), Some(last)) => Some(teon!({ "AND": [r#where, self.ordering.where_after(last)] })),
+            (None, Some(last)) => Some(self.ordering.where_after(last)),
+            (r#where, None) => r#where,
+        };
+        if let Some(batch_where) = batch_where {
+            finder.as_dictionary_mut().unwrap().insert("where".to_owned(), batch_where);
+        }
+        Some(finder)
+    }
+
+    /// Records a fetched batch. A batch shorter than the batch size is the last one.
+    fn advance(&mut self, count: usize, last: Option<Vec<Value>>) {
+        self.exported += count;
+        match last {
+            Some(last) if count == EXPORT_BATCH_SIZE => self.last = Some(last),
+            _ => self.done = true,
+        }
+    }
+}
+
+/// Fetches and formats the next batch, `None` if every record is exported.
+async fn next_batch(request: &Request, model: &Model, batches: &mut Batches, format: ExportFormat, columns: &Mutex<Option<Vec<String>>>) -> teo_result::Result<Option<Bytes>> {
+    let Some(finder) = batches.next_finder() else {
+        return Ok(None);
+    };
+    let results = request.transaction_ctx().find_many_internal(model, &finder, true, FIND | MANY | ENTRY, Some(request.clone()), path![]).await?;
+    let mut chunk = String::new();
+    for (offset, object) in results.iter().enumerate() {
+        let index = batches.exported + offset;
+        let Ok(value) = object.to_teon_internal(&path!["data", index]).await else {
+            return Err(Error::unauthorized_pathed(path!["data", index], "not allowed to read"));
+        };
+        let json = JsonValue::try_from(&value)?;
+        match format {
+            ExportFormat::Ndjson => {
+                chunk.push_str(&json.to_string());
+                chunk.push('\n');
+            }
+            ExportFormat::Csv => chunk.push_str(&csv_line(&json, columns)),
+        }
+    }
+    let last = match results.last() {
+        Some(object) => Some(batches.ordering.values(object)?),
+        None => None,
+    };
+    batches.advance(results.len(), last);
+    Ok(Some(Bytes::from(chunk)))
+}
+
+/// Streams the matched records as NDJSON or CSV. The records are fetched in batches, each
+/// batch starts after the last record of the previous one, so large tables are never loaded
+/// into memory at once and concurrent writes don't shift the pages. Pagination arguments are
+/// ignored.
+///
+/// The first batch is fetched before responding, so that invalid finders and denied reads are
+/// answered with an error status. An error in a later batch fails the body stream, which
+/// aborts the transfer instead of ending it as if the export were complete.
+pub async fn export(request: Request) -> teo_result::Result<Response> {
+    let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match()?.path()).unwrap().clone();
+    let format = ExportFormat::from_request(&request)?;
+    let finder = request.body_value()?.clone();
+    let ordering = CursorOrdering::new(&model, finder.get("orderBy"))?;
+    let mut batches = Batches::new(finder, ordering);
+    let file_name = format!("{}.{}", model.path().last().unwrap(), format.extension());
+    let columns = Mutex::new(None);
+    let first = next_batch(&request, &model, &mut batches, format, &columns).await?.unwrap_or_default();
+    let (sender, mut receiver) = mpsc::channel::<teo_result::Result<Bytes>>(16);
+    tokio::spawn(async move {
+        let result: teo_result::Result<()> = async {
+            while let Some(chunk) = next_batch(&request, &model, &mut batches, format, &columns).await? {
+                if sender.send(Ok(chunk)).await.is_err() {
+                    return Err(Error::new("export is cancelled"));
+                }
+            }
+            Ok(())
+        }.await;
+        if let Err(err) = result {
+            let _ = sender.send(Err(err)).await;
+        }
+    });
+    let rest = futures::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+    let stream = futures::stream::once(async move { Ok(first) }).chain(rest);
+    let res = Response::empty();
+    res.headers().insert(CONTENT_TYPE.as_str(), format.content_type())?;
+    res.headers().insert(CONTENT_DISPOSITION.as_str(), format!("attachment; filename=\"{}\"", file_name))?;
+    res.set_body(Body::stream(stream));
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn csv_starts_with_a_header_line() {
+        let columns = Mutex::new(None);
+        assert_eq!(csv_line(&json!({ "id": 1, "name": "Ann" }), &columns), "id,name\r\n1,Ann\r\n");
+        assert_eq!(csv_line(&json!({ "name": "Bob", "id": 2 }), &columns), "2,Bob\r\n");
+        assert_eq!(csv_line(&json!({ "id": 3 }), &columns), "3,\r\n");
+    }
+
+    #[test]
+    fn csv_header_cells_are_escaped() {
+        let columns = Mutex::new(None);
+        assert_eq!(csv_line(&json!({ "a,b": 1, "c\"d": 2 }), &columns), "\"a,b\",\"c\"\"d\"\r\n1,2\r\n");
+    }
+
+    #[test]
+    fn csv_cells_are_escaped() {
+        assert_eq!(csv_cell(Some(&json!("plain"))), "plain");
+        assert_eq!(csv_cell(Some(&json!("a,b"))), "\"a,b\"");
+        assert_eq!(csv_cell(Some(&json!("say \"hi\""))), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_cell(Some(&json!("line\nbreak"))), "\"line\nbreak\"");
+        assert_eq!(csv_cell(Some(&json!("carriage\rreturn"))), "\"carriage\rreturn\"");
+        assert_eq!(csv_cell(Some(&json!([1, 2]))), "\"[1,2]\"");
+        assert_eq!(csv_cell(Some(&json!(true))), "true");
+        assert_eq!(csv_cell(Some(&json!(null))), "");
+        assert_eq!(csv_cell(None), "");
+    }
+
+    fn matches(row: &Value, r#where: &Value) -> bool {
+        r#where.as_dictionary().unwrap().iter().all(|(key, filter)| match key.as_str() {
+            "AND" => filter.as_array().unwrap().iter().all(|item| matches(row, item)),
+            "OR" => filter.as_array().unwrap().iter().any(|item| matches(row, item)),
+            _ => filter.as_dictionary().unwrap().iter().all(|(operator, operand)| {
+                let ordering = row.get(key.as_str()).unwrap().partial_cmp(operand).unwrap();
+                match operator.as_str() {
+                    "equals" => ordering == Ordering::Equal,
+                    "gt" => ordering == Ordering::Greater,
+                    "lt" => ordering == Ordering::Less,
+                    _ => panic!("unexpected operator {}", operator),
+                }
+            }),
+        })
+    }
+
+    /// Runs `finder` against `rows` ordered by group descending, then by id.
+    fn fetch(rows: &Vec<Value>, finder: &Value) -> Vec<Value> {
+        let mut result: Vec<Value> = rows.iter().filter(|row| finder.get("where").map_or(true, |r#where| matches(row, r#where))).cloned().collect();
+        result.sort_by(|a, b| b.get("group").partial_cmp(&a.get("group")).unwrap().then(a.get("id").partial_cmp(&b.get("id")).unwrap()));
+        result.truncate(finder.get("take").unwrap().to_int64().unwrap() as usize);
+        result
+    }
+
+    fn export_ids(rows: &Vec<Value>, finder: Value) -> (Vec<i64>, usize) {
+        let ordering = CursorOrdering::from_items(vec![("group".to_owned(), true), ("id".to_owned(), false)]);
+        let mut batches = Batches::new(finder, ordering);
+        let mut ids = vec![];
+        let mut fetches = 0;
+        while let Some(finder) = batches.next_finder() {
+            fetches += 1;
+            let page = fetch(rows, &finder);
+            ids.extend(page.iter().map(|row| row.get("id").unwrap().to_int64().unwrap()));
+            let last = page.last().map(|row| vec![row.get("group").unwrap().clone(), row.get("id").unwrap().clone()]);
+            batches.advance(page.len(), last);
+        }
+        (ids, fetches)
+    }
+
+    fn rows(count: i64) -> Vec<Value> {
+        (0..count).map(|id| teon!({ "id": Value::Int64(id), "group": Value::Int64(id % 3) })).collect()
+    }
+
+    fn expected_ids(count: i64) -> Vec<i64> {
+        let mut ids: Vec<i64> = (0..count).collect();
+        ids.sort_by(|a, b| (b % 3).cmp(&(a % 3)).then(a.cmp(b)));
+        ids
+    }
+
+    #[test]
+    fn keyset_batches_export_every_record_once() {
+        let count = EXPORT_BATCH_SIZE as i64 * 2 + 50;
+        let (ids, fetches) = export_ids(&rows(count), teon!({ "skip": 10, "take": 5 }));
+        assert_eq!(ids, expected_ids(count));
+        assert_eq!(fetches, 3);
+    }
+
+    #[test]
+    fn a_full_last_batch_is_followed_by_an_empty_one() {
+        let count = EXPORT_BATCH_SIZE as i64 * 2;
+        let (ids, fetches) = export_ids(&rows(count), teon!({}));
+        assert_eq!(ids, expected_ids(count));
+        assert_eq!(fetches, 3);
+    }
+
+    #[test]
+    fn keyset_batches_keep_the_requested_filter() {
+        let count = EXPORT_BATCH_SIZE as i64 * 2 + 50;
+        let (ids, _) = export_ids(&rows(count), teon!({ "where": { "id": { "lt": Value::Int64(300) } } }));
+        assert_eq!(ids, expected_ids(300));
+    }
+}
@@ -3,38 +3,116 @@ use crate::request::Request;
 use crate::value::Value;
 use crate::teon;
 use crate::action::action::*;
+use crate::handler::default::internal::conditional::conditional_read_response;
+use crate::handler::default::internal::cursor::CursorOrdering;
 use crate::response::Response;
 
 pub async fn find_many(request: Request) -> teo_result::Result<Response> {
     let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
     let action = FIND | MANY | ENTRY;
-    let results = request.transaction_ctx().find_many_internal(
+    let mut finder = request.body_value()?.clone();
+    let finder_obj = finder.as_dictionary_mut().unwrap();
+    let after = finder_obj.shift_remove("after");
+    let before = finder_obj.shift_remove("before");
+    let should_count = finder_obj.shift_remove("count").map_or(true, |c| !c.is_false());
+    // cursor pagination is requested with `after` or `before`, a null cursor starts from the
+    // first or the last record
+    let uses_cursor = after.is_some() || before.is_some();
+    let take = match finder_obj.get("take") {
+        Some(take) => match take.to_int64() {
+            Some(take) => Some(take),
+            None => return Err(teo_result::Error::invalid_request_pathed(path!["take"], "expect integer")),
+        },
+        None => None,
+    };
+    if after.is_some() && before.is_some() {
+        return Err(teo_result::Error::invalid_request_pathed(path!["before"], "after and before cannot be used together"));
+    }
+    if uses_cursor && (finder_obj.contains_key("pageSize") || finder_obj.contains_key("pageNumber")) {
+        return Err(teo_result::Error::invalid_request_pathed(path!["after"], "cursor cannot be used with pageSize or pageNumber"));
+    }
+    if uses_cursor && take.map_or(false, |t| t < 0) {
+        return Err(teo_result::Error::invalid_request_pathed(path!["take"], "cursor requires a positive take"));
+    }
+    let ordering = if uses_cursor {
+        let ordering = CursorOrdering::new(&model, finder_obj.get("orderBy"))?;
+        Some(if before.is_some() { ordering.reversed() } else { ordering })
+    } else {
+        None
+    };
+    let count_input = if should_count {
+        let mut count_input = finder.clone();
+        let count_input_obj = count_input.as_dictionary_mut().unwrap();
+        count_input_obj.remove("skip");
+        count_input_obj.remove("take");
+        count_input_obj.remove("pageSize");
+        count_input_obj.remove("pageNumber");
+        Some(count_input)
+    } else {
+        None
+    };
+    if let Some(ordering) = ordering.as_ref() {
+        let finder_obj = finder.as_dictionary_mut().unwrap();
+        finder_obj.insert("orderBy".to_owned(), ordering.order_by());
+        if let Some(take) = take {
+            // fetch one more record to find out whether there is a next page
+            finder_obj.insert("take".to_owned(), Value::Int64(take + 1));
+        }
+        let cursor = after.as_ref().map(|a| (a, path!["after"])).or(before.as_ref().map(|b| (b, path!["before"])));
+        if let Some((cursor, cursor_path)) = cursor.filter(|(c, _)| !c.is_null()) {
+            let Some(cursor) = cursor.as_str() else {
+                return Err(teo_result::Error::invalid_request_pathed(cursor_path, "invalid cursor"));
+            };
+            let values = ordering.decode(cursor, &model, request.transaction_ctx().namespace(), &cursor_path)?;
+            let keyset = ordering.where_after(&values);
+            let r#where = match finder_obj.shift_remove("where") {
+                Some(r#where) => teon!({ "AND": [r#where, keyset] }),
+                None => keyset,
+            };
+            finder_obj.insert("where".to_owned(), r#where);
+        }
+    }
+    let mut results = request.transaction_ctx().find_many_internal(
         &model,
-        request.body_value()?,
+        &finder,
         false,
         action,
         Some(request.clone()),
         path![],
     ).await?;
-    let mut count_input = request.body_value()?.clone();
-    let count_input_obj = count_input.as_dictionary_mut().unwrap();
-    count_input_obj.remove("skip");
-    count_input_obj.remove("take");
-    count_input_obj.remove("pageSize");
-    count_input_obj.remove("pageNumber");
-    let count = request.transaction_ctx().count_objects(&model, &count_input, path![]).await.unwrap();
-    let mut meta = teon!({"count": count});
-    let page_size = request.body_value()?.get("pageSize");
-    if page_size.is_some() {
-        let page_size = page_size.unwrap().to_int64().unwrap();
-        let count = count as i64;
-        let mut number_of_pages = count / page_size;
-        if count % page_size != 0 {
-            number_of_pages += 1;
+    let mut meta = teon!({});
+    if let Some(count_input) = count_input {
+        let count = request.transaction_ctx().count_objects(&model, &count_input, path![]).await.unwrap();
+        meta.as_dictionary_mut().unwrap().insert("count".to_string(), count.into());
+        let page_size = request.body_value()?.get("pageSize");
+        if page_size.is_some() {
+            let page_size = page_size.unwrap().to_int64().unwrap();
+            let count = count as i64;
+            let mut number_of_pages = count / page_size;
+            if count % page_size != 0 {
+                number_of_pages += 1;
+            }
+            meta.as_dictionary_mut().unwrap().insert("numberOfPages".to_string(), number_of_pages.into());
+        }
+    }
+    if let Some(ordering) = ordering.as_ref() {
+        let has_more = take.map_or(false, |take| results.len() as i64 > take);
+        if has_more {
+            results.pop();
+        }
+        let (has_next, has_prev) = if let Some(before) = before.as_ref() {
+            results.reverse();
+            (!before.is_null(), has_more)
+        } else {
+            (has_more, after.as_ref().map_or(false, |a| !a.is_null()))
+        };
+        if let (true, Some(last)) = (has_next, results.last()) {
+            meta.as_dictionary_mut().unwrap().insert("nextCursor".to_string(), ordering.encode(last)?.into());
+        }
+        if let (true, Some(first)) = (has_prev, results.first()) {
+            meta.as_dictionary_mut().unwrap().insert("prevCursor".to_string(), ordering.encode(first)?.into());
         }
-        meta.as_dictionary_mut().unwrap().insert("numberOfPages".to_string(), number_of_pages.into());
     }
-
     let mut result_json: Vec<Value> = vec![];
     for (index, result) in results.iter().enumerate() {
         match result.to_teon_internal(&path!["data", index]).await {
@@ -42,5 +120,5 @@ pub async fn find_many(request: Request) -> teo_result::Result<Response> {
             Err(_) => return Err(teo_result::Error::unauthorized_pathed(path!["data", index], "not allowed to read")),
         }
     }
-    Ok(Response::data_meta(Value::Array(result_json), meta))
+    conditional_read_response(&request, Response::data_meta(Value::Array(result_json), meta))
 }
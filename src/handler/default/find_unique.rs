@@ -2,6 +2,7 @@ use key_path::path;
 use crate::value::Value;
 use crate::response::Response;
 use crate::action::action::*;
+use crate::handler::default::internal::conditional::conditional_read_response;
 use crate::request::Request;
 
 pub async fn find_unique(request: Request) -> teo_result::Result<Response> {
@@ -15,12 +16,13 @@ pub async fn find_unique(request: Request) -> teo_result::Result<Response> {
         Some(request.clone()),
         path![],
     ).await?;
-    match result {
-        None => Ok(Response::data(Value::Null)),
+    let response = match result {
+        None => Response::data(Value::Null),
         Some(obj) => {
             let obj_data = obj.to_teon_internal(&path!["data"]).await?;
-            Ok(Response::data(obj_data))
+            Response::data(obj_data)
         }
-    }
+    };
+    conditional_read_response(&request, response)
 }
 
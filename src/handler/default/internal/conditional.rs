@@ -0,0 +1,41 @@
+use hyper::header::{IF_MATCH, IF_NONE_MATCH};
+use key_path::path;
+use teo_result::{Error, Result};
+use crate::model::Object;
+use crate::request::Request;
+use crate::response::etag::{etag_for_value, etag_matches};
+use crate::response::Response;
+use crate::teon;
+
+/// Sets the `ETag` header of a read handler's response, and replaces the response with
+/// `304 Not Modified` if it matches `If-None-Match`. The cache middleware responds with cached
+/// responses through this too.
+pub(crate) fn conditional_read_response(request: &Request, response: Response) -> Result<Response> {
+    let Some(etag) = response.set_teon_etag()? else {
+        return Ok(response);
+    };
+    if let Some(if_none_match) = request.headers().get(IF_NONE_MATCH.as_str())? {
+        if etag_matches(&if_none_match, &etag, true) {
+            return Response::not_modified(&etag);
+        }
+    }
+    Ok(response)
+}
+
+/// Checks `If-Match` against the ETag of the object's current representation, which is the
+/// ETag `findUnique` responds with. Responds with `412 Precondition Failed` on mismatch.
+///
+/// The representation is the one without `select` and `include`, so clients must send the
+/// ETag of a `findUnique` response made without these arguments. ETags of responses with a
+/// selection or included relations never match.
+pub(in crate::handler::default) async fn check_if_match(request: &Request, object: &Object) -> Result<()> {
+    let Some(if_match) = request.headers().get(IF_MATCH.as_str())? else {
+        return Ok(());
+    };
+    let etag = etag_for_value(&teon!({ "data": object.to_teon_internal(&path!["data"]).await? }))?;
+    if etag_matches(&if_match, &etag, false) {
+        Ok(())
+    } else {
+        Err(Error::new_with_code("precondition failed", 412))
+    }
+}
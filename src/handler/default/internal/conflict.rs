@@ -0,0 +1,110 @@
+use indexmap::IndexMap;
+use itertools::Itertools;
+use key_path::KeyPath;
+use teo_result::{Error, Result};
+use crate::action::Action;
+use crate::connection::transaction;
+use crate::error_ext;
+use crate::model::{Model, Object};
+use crate::model::index::Index;
+use crate::request::Request;
+use crate::teon;
+use crate::value::Value;
+
+/// The outcome of a row of `createMany` with `skipDuplicates` and `upsertMany`, reported in
+/// the `outcomes` of the response meta.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(in crate::handler::default) enum Outcome {
+    Created,
+    Updated,
+    Skipped,
+}
+
+impl Outcome {
+
+    pub(in crate::handler::default) fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Created => "created",
+            Outcome::Updated => "updated",
+            Outcome::Skipped => "skipped",
+        }
+    }
+}
+
+pub(in crate::handler::default) fn outcomes_value(outcomes: &Vec<Outcome>) -> Value {
+    Value::Array(outcomes.iter().map(|o| Value::String(o.as_str().to_owned())).collect())
+}
+
+/// Returns the indexes which conflicts are resolved against. This is the unique index named
+/// `conflict_target`, or every unique index of the model in declaration order if no target is
+/// given.
+pub(in crate::handler::default) fn conflict_indexes<'a>(model: &'a Model, conflict_target: Option<&Value>, path: &KeyPath) -> Result<Vec<&'a Index>> {
+    match conflict_target.and_then(|t| t.as_str()) {
+        Some(name) => match model.indexes().get(name) {
+            Some(index) if index.r#type().is_unique_or_primary() => Ok(vec![index]),
+            _ => Err(Error::invalid_request_pathed(path.clone(), format!("unique index '{}' is not found", name))),
+        },
+        None => Ok(model.indexes().values().filter(|i| i.r#type().is_unique_or_primary()).collect()),
+    }
+}
+
+/// The values of the index keys of `object`. Null values never conflict, so this is `None` if
+/// any of them is null.
+fn index_values(object: &Object, index: &Index) -> Result<Option<IndexMap<String, Value>>> {
+    let mut values = IndexMap::new();
+    for key in index.keys() {
+        let value = object.get_value(key)?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        values.insert(key.clone(), value);
+    }
+    Ok(Some(values))
+}
+
+/// Finds the values of the new `object` on the first of `indexes` which a saved record has.
+/// The unique indexes hold every record, so the lookup includes soft deleted records and the
+/// records which the policies hide. It only tells whether there's a conflict, the conflicting
+/// record is fetched again with `fetch_conflicting_object` before it's used.
+pub(in crate::handler::default) async fn find_conflict(ctx: &transaction::Ctx, model: &Model, indexes: &Vec<&Index>, object: &Object, action: Action, request: &Request, path: &KeyPath) -> Result<Option<IndexMap<String, Value>>> {
+    let ctx = ctx.bypassing_policies();
+    for index in indexes {
+        let Some(values) = index_values(object, index)? else {
+            continue;
+        };
+        let finder = teon!({ "where": Value::Dictionary(values.clone()), "withDeleted": true });
+        if ctx.find_first_internal(model, &finder, true, action, Some(request.clone()), path.clone()).await?.is_some() {
+            return Ok(Some(values));
+        }
+    }
+    Ok(None)
+}
+
+/// Fetches the record which has the conflicting `values` through `ctx`, so that the policies,
+/// the tenant and the soft deletion apply. A record which the request can't see is reported as
+/// a duplicated unique value.
+pub(in crate::handler::default) async fn fetch_conflicting_object(ctx: &transaction::Ctx, model: &Model, values: IndexMap<String, Value>, action: Action, request: &Request, path: &KeyPath) -> Result<Object> {
+    let keys = values.keys().join(", ");
+    let finder = teon!({ "where": Value::Dictionary(values) });
+    match ctx.find_first_internal(model, &finder, true, action, Some(request.clone()), path.clone()).await? {
+        Some(object) => Ok(object),
+        None => Err(error_ext::unique_value_duplicated(path.clone(), keys)),
+    }
+}
+
+/// Saves the new `object` unless a saved record conflicts with it on `indexes`, and returns the
+/// conflicting values if one does. A record can be inserted by others between the lookup and
+/// the save, so a duplicated unique value reported by the connector is looked up again.
+pub(in crate::handler::default) async fn save_unless_conflicting(ctx: &transaction::Ctx, model: &Model, indexes: &Vec<&Index>, object: &Object, action: Action, request: &Request, path: &KeyPath) -> Result<Option<IndexMap<String, Value>>> {
+    if let Some(values) = find_conflict(ctx, model, indexes, object, action, request, path).await? {
+        return Ok(Some(values));
+    }
+    match object.save_with_session_and_path(path).await {
+        Ok(()) => Ok(None),
+        Err(error) if error_ext::is_conflict(&error) => match find_conflict(ctx, model, indexes, object, action, request, path).await? {
+            Some(values) => Ok(Some(values)),
+            None => Err(error),
+        },
+        Err(error) => Err(error),
+    }
+}
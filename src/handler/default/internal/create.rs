@@ -3,12 +3,19 @@ use crate::value::Value;
 use crate::teon;
 use crate::action::Action;
 use crate::connection::transaction;
-use crate::model::Model;
+use crate::model::{Model, Object};
 use crate::error_ext;
 use teo_result::Result;
 use crate::request::Request;
 
 pub(in crate::handler) async fn create_internal<'a>(transaction_ctx: transaction::Ctx, request: Request, create: Option<&'a Value>, include: Option<&'a Value>, select: Option<&'a Value>, model: &Model, path: &'a KeyPath, action: Action) -> Result<Value> {
+    let obj = new_object_internal(transaction_ctx, request, create, model, path, action).await?;
+    obj.save_with_session_and_path(path).await?;
+    let refreshed = obj.refreshed(include, select).await?;
+    refreshed.to_teon_internal(&path!["data"]).await
+}
+
+pub(in crate::handler) async fn new_object_internal<'a>(transaction_ctx: transaction::Ctx, request: Request, create: Option<&'a Value>, model: &Model, path: &'a KeyPath, action: Action) -> Result<Object> {
     let obj = transaction_ctx.new_object(model, action, Some(request))?;
     match create {
         Some(create) => {
@@ -21,7 +28,5 @@ pub(in crate::handler) async fn create_internal<'a>(transaction_ctx: transaction
             obj.set_teon_with_path(&teon!({}), path).await
         }
     }?;
-    obj.save_with_session_and_path(path).await?;
-    let refreshed = obj.refreshed(include, select).await?;
-    refreshed.to_teon_internal(&path!["data"]).await
+    Ok(obj)
 }
\ No newline at end of file
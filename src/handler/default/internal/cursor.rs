@@ -0,0 +1,125 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use indexmap::IndexMap;
+use key_path::{KeyPath, path};
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::coder::json_to_teon::json_to_teon;
+use crate::model;
+use crate::model::Model;
+use crate::model::field::is_optional::IsOptional;
+use crate::model::field::typed::Typed;
+use crate::namespace::Namespace;
+use crate::teon;
+use crate::value::Value;
+
+/// The ordering of a keyset paginated query. It's the requested `orderBy` followed by the
+/// keys of the primary index, so that every row has a distinct position. Optional fields
+/// are rejected, databases differ in where they sort nulls, and `gt` and `lt` never match
+/// them.
+pub(in crate::handler) struct CursorOrdering {
+    items: Vec<(String, bool)>,
+}
+
+impl CursorOrdering {
+
+    pub(in crate::handler) fn new(model: &Model, order_by: Option<&Value>) -> Result<Self> {
+        let mut items: Vec<(String, bool)> = vec![];
+        let order_by_items: Vec<&Value> = match order_by {
+            None => vec![],
+            Some(Value::Array(items)) => items.iter().collect(),
+            Some(value) => vec![value],
+        };
+        for order_by_item in order_by_items {
+            let Some(map) = order_by_item.as_dictionary() else {
+                return Err(Error::invalid_request_pathed(path!["orderBy"], "unexpected value"));
+            };
+            for (key, direction) in map {
+                let Some(field) = model.field(key) else {
+                    return Err(Error::invalid_request_pathed(path!["orderBy", key.as_str()], "cursor pagination only supports ordering by fields"));
+                };
+                if field.is_optional() {
+                    return Err(Error::invalid_request_pathed(path!["orderBy", key.as_str()], "cursor pagination doesn't support ordering by optional fields"));
+                }
+                let desc = match direction.as_str() {
+                    Some("asc") => false,
+                    Some("desc") => true,
+                    _ => return Err(Error::invalid_request_pathed(path!["orderBy", key.as_str()], "unexpected value")),
+                };
+                items.push((key.clone(), desc));
+            }
+        }
+        let Some(primary_index) = model.primary_index() else {
+            return Err(Error::internal_server_error_message("cursor pagination requires a primary index"));
+        };
+        for key in primary_index.keys() {
+            if !items.iter().any(|(k, _)| k == key) {
+                items.push((key.clone(), false));
+            }
+        }
+        Ok(Self { items })
+    }
+
+    #[cfg(test)]
+    pub(in crate::handler) fn from_items(items: Vec<(String, bool)>) -> Self {
+        Self { items }
+    }
+
+    pub(in crate::handler) fn reversed(&self) -> Self {
+        Self { items: self.items.iter().map(|(k, desc)| (k.clone(), !desc)).collect() }
+    }
+
+    pub(in crate::handler) fn order_by(&self) -> Value {
+        Value::Array(self.items.iter().map(|(k, desc)| {
+            let mut map = IndexMap::new();
+            map.insert(k.clone(), Value::String(if *desc { "desc" } else { "asc" }.to_owned()));
+            Value::Dictionary(map)
+        }).collect())
+    }
+
+    /// The values of the ordering keys of `object`.
+    pub(in crate::handler) fn values(&self, object: &model::Object) -> Result<Vec<Value>> {
+        self.items.iter().map(|(key, _)| object.get_value(key)).collect()
+    }
+
+    pub(in crate::handler) fn encode(&self, object: &model::Object) -> Result<String> {
+        let mut values: Vec<JsonValue> = vec![];
+        for value in self.values(object)? {
+            values.push(JsonValue::try_from(&value)?);
+        }
+        Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(&JsonValue::Array(values)).unwrap()))
+    }
+
+    pub(in crate::handler) fn decode(&self, cursor: &str, model: &Model, namespace: &Namespace, path: &KeyPath) -> Result<Vec<Value>> {
+        let invalid = || Error::invalid_request_pathed(path.clone(), "invalid cursor");
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let json: JsonValue = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        let Some(json_values) = json.as_array() else {
+            return Err(invalid());
+        };
+        if json_values.len() != self.items.len() {
+            return Err(invalid());
+        }
+        let mut values = vec![];
+        for ((key, _), json_value) in self.items.iter().zip(json_values) {
+            let field = model.field(key).unwrap();
+            values.push(json_to_teon(json_value, path, field.r#type(), namespace).map_err(|_| invalid())?);
+        }
+        Ok(values)
+    }
+
+    /// Builds the where input which selects the rows positioned after the cursor values.
+    pub(in crate::handler) fn where_after(&self, values: &Vec<Value>) -> Value {
+        let mut alternatives = vec![];
+        for (index, (key, desc)) in self.items.iter().enumerate() {
+            let mut conditions = IndexMap::new();
+            for (previous_index, (previous_key, _)) in self.items.iter().enumerate().take(index) {
+                conditions.insert(previous_key.clone(), teon!({ "equals": values[previous_index].clone() }));
+            }
+            let operator = if *desc { "lt" } else { "gt" };
+            conditions.insert(key.clone(), teon!({ operator: values[index].clone() }));
+            alternatives.push(Value::Dictionary(conditions));
+        }
+        teon!({ "OR": alternatives })
+    }
+}
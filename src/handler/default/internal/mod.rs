@@ -1,3 +1,6 @@
 pub(super) mod create;
 pub(super) mod update;
-pub(super) mod copy;
\ No newline at end of file
+pub(super) mod copy;
+pub(super) mod cursor;
+pub(super) mod conditional;
+pub(super) mod conflict;
@@ -1,3 +1,9 @@
+use teo_result::{Error, Result};
+use crate::action::Action;
+use crate::action::action::*;
+use crate::request::Request;
+use crate::response::Response;
+
 mod internal;
 
 pub mod find_many;
@@ -10,12 +16,14 @@ pub mod delete;
 pub mod copy;
 pub mod copy_or_create;
 pub mod create_many;
+pub mod upsert_many;
 pub mod update_many;
 pub mod delete_many;
 pub mod copy_many;
 pub mod count;
 pub mod aggregate;
 pub mod group_by;
+pub mod export;
 
 pub use find_many::find_many;
 pub use find_first::find_first;
@@ -27,9 +35,37 @@ pub use delete::delete;
 pub use copy::copy;
 pub use copy_or_create::copy_or_create;
 pub use create_many::create_many;
+pub use upsert_many::upsert_many;
 pub use update_many::update_many;
 pub use delete_many::delete_many;
 pub use copy_many::copy_many;
 pub use count::count;
 pub use aggregate::aggregate;
-pub use group_by::group_by;
\ No newline at end of file
+pub use group_by::group_by;
+pub use export::export;
+pub(crate) use internal::conditional::conditional_read_response;
+
+/// Calls the default handler of the builtin handler `action`. The body value of `request` should
+/// be decoded with `validate_and_transform_json_input_for_builtin_action`.
+pub async fn call_builtin_handler(action: Action, request: Request) -> Result<Response> {
+    match action {
+        FIND_UNIQUE_HANDLER => find_unique(request).await,
+        FIND_FIRST_HANDLER => find_first(request).await,
+        FIND_MANY_HANDLER => find_many(request).await,
+        CREATE_HANDLER => create(request).await,
+        UPDATE_HANDLER => update(request).await,
+        UPSERT_HANDLER => upsert(request).await,
+        DELETE_HANDLER => delete(request).await,
+        COPY_HANDLER => copy(request).await,
+        CREATE_MANY_HANDLER => create_many(request).await,
+        UPSERT_MANY_HANDLER => upsert_many(request).await,
+        UPDATE_MANY_HANDLER => update_many(request).await,
+        DELETE_MANY_HANDLER => delete_many(request).await,
+        COPY_MANY_HANDLER => copy_many(request).await,
+        COUNT_HANDLER => count(request).await,
+        AGGREGATE_HANDLER => aggregate(request).await,
+        GROUP_BY_HANDLER => group_by(request).await,
+        EXPORT_HANDLER => export(request).await,
+        _ => Err(Error::not_found()),
+    }
+}
@@ -3,6 +3,7 @@ use crate::value::Value;
 use crate::response::Response;
 use crate::action::action::*;
 use crate::connection::transaction;
+use crate::handler::default::internal::conditional::check_if_match;
 use crate::handler::default::internal::update::update_internal;
 use crate::model::object::object::ErrorIfNotFound;
 use crate::request::Request;
@@ -14,6 +15,7 @@ pub async fn update(request: Request) -> teo_result::Result<Response> {
         let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
         async move {
             let object = ctx.find_unique_internal(&model, request.body_value()?, true, action, Some(request.clone()), path![]).await.into_not_found_error(path![])?;
+            check_if_match(&request, &object).await?;
             let update = request.body_value()?.get("update");
             let include = request.body_value()?.get("include");
             let select = request.body_value()?.get("select");
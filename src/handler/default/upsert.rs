@@ -19,7 +19,7 @@ pub async fn upsert(request: Request) -> teo_result::Result<Response> {
             match find_result {
                 Some(object) => {
                     let update = request.body_value()?.get("update");
-                    Ok(update_internal(object, update, include, select, &path![]).await?)
+                    Ok(update_internal(object, update, include, select, &path!["update"]).await?)
                 }
                 None => {
                     let create = request.body_value()?.get("update");
@@ -0,0 +1,54 @@
+use key_path::path;
+use crate::value::Value;
+use crate::teon;
+use crate::response::Response;
+use crate::action::action::*;
+use crate::connection::transaction;
+use crate::error_ext;
+use crate::handler::default::internal::conflict::{conflict_indexes, fetch_conflicting_object, Outcome, outcomes_value, save_unless_conflicting};
+use crate::handler::default::internal::create::new_object_internal;
+use crate::handler::default::internal::update::update_internal;
+use crate::request::Request;
+
+pub async fn upsert_many(request: Request) -> teo_result::Result<Response> {
+    let action = UPSERT | MANY | ENTRY;
+    let (objects, count, outcomes) = request.transaction_ctx().run_transaction(move |ctx: transaction::Ctx| {
+        let request = request.clone();
+        let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
+        async move {
+            let input = request.body_value()?.as_dictionary().unwrap();
+            let upsert = input.get("upsert");
+            let include = input.get("include");
+            let select = input.get("select");
+            let indexes = conflict_indexes(&model, input.get("conflictTarget").filter(|v| !v.is_null()), &path!["conflictTarget"])?;
+            let upsert = upsert.unwrap().as_array().unwrap();
+            let mut count = 0;
+            let mut ret_data: Vec<Value> = vec![];
+            let mut outcomes: Vec<Outcome> = vec![];
+            // rows are saved one by one, so that a row conflicts with the earlier rows
+            for (index, val) in upsert.iter().enumerate() {
+                let path = path!["upsert", index];
+                if !val.is_dictionary() {
+                    return Err(error_ext::unexpected_input_value_with_reason(path, "expect object"));
+                }
+                let create_path = &path + "create";
+                let object = new_object_internal(ctx.clone(), request.clone(), val.get("create"), &model, &create_path, action).await?;
+                match save_unless_conflicting(&ctx, &model, &indexes, &object, action, &request, &create_path).await? {
+                    Some(values) => {
+                        let existing = fetch_conflicting_object(&ctx, &model, values, action, &request, &create_path).await?;
+                        ret_data.push(update_internal(existing, val.get("update"), include, select, &(&path + "update")).await?);
+                        outcomes.push(Outcome::Updated);
+                    }
+                    None => {
+                        let refreshed = object.refreshed(include, select).await?;
+                        ret_data.push(refreshed.to_teon_internal(&path!["data"]).await?);
+                        outcomes.push(Outcome::Created);
+                    }
+                }
+                count += 1;
+            }
+            Ok((ret_data, count, outcomes))
+        }
+    }).await?;
+    Ok(Response::data_meta(Value::Array(objects), teon!({"count": count, "outcomes": outcomes_value(&outcomes)})))
+}
@@ -10,6 +10,7 @@ use crate::handler::group::group;
 use hyper::Method;
 use crate::middleware::next::Next;
 use crate::request::Request;
+use crate::request::multipart::UploadLimits;
 use crate::traits::named::Named;
 use crate::utils::next_path;
 
@@ -67,6 +68,7 @@ impl Builder {
                 format: HandlerInputFormat::Json,
                 path: next_path(self.path(), name),
                 ignore_prefix: false,
+                upload_limits: UploadLimits::default(),
                 method: Method::POST,
                 interface: None,
                 url: None,
@@ -5,6 +5,7 @@ use teo_parser::ast::handler::HandlerInputFormat;
 use teo_parser::r#type::Type;
 use hyper::Method;
 use crate::middleware::next::Next;
+use crate::request::multipart::UploadLimits;
 use crate::traits::named::Named;
 
 #[derive(Educe)]
@@ -35,6 +36,8 @@ pub(super) struct Inner {
     pub(super) url: Option<String>,
     pub(super) interface: Option<String>,
     pub(super) ignore_prefix: bool,
+    #[serde(skip)]
+    pub(super) upload_limits: UploadLimits,
     #[serde(skip)] #[educe(Debug(ignore))]
     pub(super) call: Next,
 }
@@ -85,6 +88,10 @@ impl Handler {
         self.inner.ignore_prefix
     }
 
+    pub fn upload_limits(&self) -> &UploadLimits {
+        &self.inner.upload_limits
+    }
+
     pub fn call(&self) -> Next {
         self.inner.call.clone()
     }
@@ -1,10 +1,46 @@
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use indexmap::IndexMap;
 use key_path::path;
 use serde_json::{Value as JsonValue};
+use teo_parser::ast::handler::HandlerInputFormat;
+use teo_result::{Error, Result};
 use crate::value::Value;
 use crate::coder::json_to_teon::json_to_teon_with_type;
 use crate::handler::Handler;
 use crate::namespace::Namespace;
+use crate::request::multipart::parse_multipart;
+use crate::request::Request;
 
 pub fn validate_and_transform_json_input_for_handler(handler: &Handler, json_body: &JsonValue, main_namespace: &Namespace) -> teo_result::Result<Value> {
     json_to_teon_with_type(json_body, &path![], handler.input_type(), main_namespace)
-}
\ No newline at end of file
+}
+
+/// Decodes the request body into the input value of `handler`. The body is JSON or
+/// `multipart/form-data` as the handler's input format declares.
+pub async fn decode_request_input_for_handler(request: &Request, handler: &Handler, main_namespace: &Namespace) -> Result<Value> {
+    if !handler.has_body_input() {
+        return Ok(Value::Dictionary(IndexMap::new()));
+    }
+    if matches!(handler.format(), HandlerInputFormat::Json) {
+        let json_body = read_json_body(request).await?;
+        validate_and_transform_json_input_for_handler(handler, &json_body, main_namespace)
+    } else {
+        parse_multipart(request, handler, main_namespace).await
+    }
+}
+
+/// Reads the request body as JSON. An empty body is an empty object.
+pub async fn read_json_body(request: &Request) -> Result<JsonValue> {
+    let bytes = if let Some(incoming) = request.take_incoming() {
+        incoming.collect().await.map_err(|err| Error::invalid_request_message(format!("cannot read request body: {}", err)))?.to_bytes()
+    } else if let Some(incoming) = request.take_incoming_bytes_for_test() {
+        incoming.collect().await.map_err(|err| Error::invalid_request_message(format!("cannot read request body: {}", err)))?.to_bytes()
+    } else {
+        Bytes::new()
+    };
+    if bytes.is_empty() {
+        return Ok(JsonValue::Object(Default::default()));
+    }
+    serde_json::from_slice(&bytes).map_err(|err| Error::invalid_request_message(format!("invalid json body: {}", err)))
+}
@@ -2,4 +2,4 @@ pub mod builtin;
 pub mod custom;
 
 pub use builtin::validate_and_transform_json_input_for_builtin_action;
-pub use custom::validate_and_transform_json_input_for_handler;
\ No newline at end of file
+pub use custom::{decode_request_input_for_handler, read_json_body, validate_and_transform_json_input_for_handler};
\ No newline at end of file
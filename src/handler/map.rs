@@ -1,22 +1,24 @@
 use indexmap::{IndexMap, indexmap};
-use regex::Regex;
 use hyper::Method;
+use teo_result::{Error, Result};
 use crate::handler::r#match::HandlerMatch;
 
 #[derive(Debug, Clone)]
 pub struct Map {
-    records: IndexMap<(Method, String), (Vec<String>, String)>
+    records: IndexMap<(Method, String), (Vec<String>, String)>,
+    routers: IndexMap<Method, Node>,
 }
 
 impl Map {
 
     pub fn new() -> Self {
         Self {
-            records: indexmap!{}
+            records: indexmap!{},
+            routers: indexmap!{},
         }
     }
 
-    pub fn add_record(&mut self, namespace_path: &Vec<&str>, group_name: Option<&str>, action_name: &str, method: Method, custom_url: Option<&str>, ignore_prefix: bool) {
+    pub fn add_record(&mut self, namespace_path: &Vec<&str>, group_name: Option<&str>, action_name: &str, method: Method, custom_url: Option<&str>, ignore_prefix: bool) -> Result<()> {
         let url = if ignore_prefix {
             if custom_url.unwrap().starts_with("/") {
                 custom_url.unwrap().to_owned()
@@ -41,7 +43,10 @@ impl Map {
         if let Some(group_name) = group_name {
             result.push(group_name.to_owned());
         }
+        let endpoint = Endpoint::compile(&url, result.clone(), action_name.to_owned())?;
+        self.routers.entry(method.clone()).or_insert_with(Node::default).insert(&method, endpoint)?;
         self.records.insert((method, url), (result, action_name.to_owned()));
+        Ok(())
     }
 
     pub fn match_all(&self, method: &Method, url: &str) -> Option<HandlerMatch> {
@@ -55,60 +60,14 @@ impl Map {
     }
 
     pub fn match_user_defined(&self, method: &Method, url: &str) -> Option<HandlerMatch> {
-        for record in &self.records {
-            if let Some(result) = self.try_match(method, url, record) {
-                return Some(result);
-            }
-        }
-        None
-    }
-
-    fn try_match(&self, method: &Method, url: &str, record: (&(Method, String), &(Vec<String>, String))) -> Option<HandlerMatch> {
-        if method != Method::OPTIONS && record.0.0 != method {
-            return None;
-        }
-        let define = &record.0.1;
-        let arg_names = self.fetch_arg_names(define);
-        let regex_string = self.replace_arg_names(define);
-        let regex_string = format!("^{regex_string}$");
-        let regex = Regex::new(&regex_string).unwrap();
-        if regex.is_match(url) {
-            if let Some(captures) = regex.captures(url) {
-                let mut retval = indexmap!{};
-                for (index, r#match) in captures.iter().enumerate() {
-                    if index == 0 {
-                        continue
-                    }
-                    if let Some(r#match) = r#match {
-                        retval.insert(arg_names.get(index - 1).unwrap().to_owned(), r#match.as_str().to_owned());
-                    }
-                }
-                return Some(HandlerMatch::new(record.1.0.clone(), record.1.1.clone(), retval))
-            } else {
-                return Some(HandlerMatch::new(record.1.0.clone(), record.1.1.clone(), indexmap!{}))
-            }
-        }
-        None
-    }
-
-    fn fetch_arg_names(&self, define: &String) -> Vec<String> {
-        let regex = Regex::new("[:*]([^/]+)").unwrap();
-        let captures = regex.captures(define);
-        if let Some(captures) = captures {
-            captures.iter().map(|m| m.unwrap().as_str()[1..].to_string()).collect()
+        let segments = split_segments(url);
+        if method == Method::OPTIONS {
+            self.routers.values().find_map(|router| router.try_match(&segments))
         } else {
-            vec![]
+            self.routers.get(method).and_then(|router| router.try_match(&segments))
         }
     }
 
-    fn replace_arg_names(&self, define: &String) -> String {
-        let normal_regex = Regex::new(":[^/]+").unwrap();
-        let replaced = normal_regex.replace(define, "([^/]+)");
-        let catch_regex = Regex::new("\\*[^/]+").unwrap();
-        let replaced = catch_regex.replace(replaced.as_ref(), "(.+)");
-        replaced.as_ref().to_string()
-    }
-
     pub fn match_default(&self, method: Method, url: &str) -> Option<HandlerMatch> {
         if method != Method::OPTIONS && method != Method::POST {
             return None;
@@ -128,4 +87,214 @@ impl Map {
         let action = result.pop().unwrap().to_string();
         Some(HandlerMatch::new(result, action, indexmap!{}))
     }
-}
\ No newline at end of file
+}
+
+fn split_segments(url: &str) -> Vec<&str> {
+    let url = url.strip_prefix("/").unwrap_or(url);
+    if url.is_empty() {
+        vec![]
+    } else {
+        url.split("/").collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param,
+    CatchAll,
+}
+
+#[derive(Debug, Clone)]
+struct Endpoint {
+    define: String,
+    segments: Vec<Segment>,
+    arg_names: Vec<String>,
+    path: Vec<String>,
+    name: String,
+}
+
+impl Endpoint {
+
+    fn compile(define: &str, path: Vec<String>, name: String) -> Result<Self> {
+        let parts = split_segments(define);
+        let mut segments = vec![];
+        let mut arg_names = vec![];
+        for (index, part) in parts.iter().enumerate() {
+            if let Some(arg_name) = part.strip_prefix(":") {
+                if arg_name.is_empty() {
+                    Err(Error::new(format!("route '{}' has a parameter without name", define)))?
+                }
+                segments.push(Segment::Param);
+                arg_names.push(arg_name.to_owned());
+            } else if let Some(arg_name) = part.strip_prefix("*") {
+                if arg_name.is_empty() {
+                    Err(Error::new(format!("route '{}' has a catch-all parameter without name", define)))?
+                }
+                if index != parts.len() - 1 {
+                    Err(Error::new(format!("route '{}' has a catch-all parameter which is not the last segment", define)))?
+                }
+                segments.push(Segment::CatchAll);
+                arg_names.push(arg_name.to_owned());
+            } else {
+                segments.push(Segment::Static(part.to_string()));
+            }
+        }
+        Ok(Self { define: define.to_owned(), segments, arg_names, path, name })
+    }
+
+    fn same_target(&self, other: &Endpoint) -> bool {
+        self.path == other.path && self.name == other.name && self.arg_names == other.arg_names
+    }
+
+    fn to_match(&self, captures: Vec<String>) -> HandlerMatch {
+        let captures = self.arg_names.iter().cloned().zip(captures).collect();
+        HandlerMatch::new(self.path.clone(), self.name.clone(), captures)
+    }
+}
+
+/// A segment based radix tree. When matching, static segments take priority
+/// over named parameters, which take priority over catch-all parameters.
+#[derive(Debug, Clone, Default)]
+struct Node {
+    statics: IndexMap<String, Node>,
+    param: Option<Box<Node>>,
+    catch_all: Option<Endpoint>,
+    endpoint: Option<Endpoint>,
+}
+
+impl Node {
+
+    fn insert(&mut self, method: &Method, endpoint: Endpoint) -> Result<()> {
+        let mut node = self;
+        for segment in endpoint.segments.clone() {
+            node = match segment {
+                Segment::Static(name) => node.statics.entry(name).or_insert_with(Node::default),
+                Segment::Param => node.param.get_or_insert_with(|| Box::new(Node::default())).as_mut(),
+                Segment::CatchAll => return Self::occupy(&mut node.catch_all, method, endpoint),
+            };
+        }
+        Self::occupy(&mut node.endpoint, method, endpoint)
+    }
+
+    fn occupy(slot: &mut Option<Endpoint>, method: &Method, endpoint: Endpoint) -> Result<()> {
+        if let Some(existing) = slot.as_ref() {
+            if existing.define == endpoint.define && existing.same_target(&endpoint) {
+                return Ok(());
+            }
+            Err(Error::new(format!(
+                "route conflict: {} '{}' of handler '{}' conflicts with '{}' of handler '{}'",
+                method,
+                endpoint.define,
+                endpoint.path.iter().chain(std::iter::once(&endpoint.name)).cloned().collect::<Vec<String>>().join("."),
+                existing.define,
+                existing.path.iter().chain(std::iter::once(&existing.name)).cloned().collect::<Vec<String>>().join("."),
+            )))?
+        }
+        *slot = Some(endpoint);
+        Ok(())
+    }
+
+    fn try_match(&self, segments: &Vec<&str>) -> Option<HandlerMatch> {
+        let mut captures = vec![];
+        self.search(segments.as_slice(), &mut captures).map(|endpoint| endpoint.to_match(captures))
+    }
+
+    fn search<'a>(&'a self, segments: &[&str], captures: &mut Vec<String>) -> Option<&'a Endpoint> {
+        let Some((first, rest)) = segments.split_first() else {
+            return self.endpoint.as_ref();
+        };
+        if let Some(child) = self.statics.get(*first) {
+            if let Some(endpoint) = child.search(rest, captures) {
+                return Some(endpoint);
+            }
+        }
+        if !first.is_empty() {
+            if let Some(child) = self.param.as_ref() {
+                captures.push(first.to_string());
+                if let Some(endpoint) = child.search(rest, captures) {
+                    return Some(endpoint);
+                }
+                captures.pop();
+            }
+        }
+        if let Some(endpoint) = self.catch_all.as_ref() {
+            let remaining = segments.join("/");
+            if !remaining.is_empty() {
+                captures.push(remaining);
+                return Some(endpoint);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Method;
+    use super::Map;
+
+    fn map_with(routes: &[(&str, &str)]) -> Map {
+        let mut map = Map::new();
+        for (url, action) in routes {
+            map.add_record(&vec![], Some("Post"), action, Method::GET, Some(url), true).unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn static_segments_take_priority_over_params() {
+        let map = map_with(&[("/posts/:id", "byId"), ("/posts/latest", "latest")]);
+        let result = map.match_user_defined(&Method::GET, "/posts/latest").unwrap();
+        assert_eq!(result.handler_name(), "latest");
+        let result = map.match_user_defined(&Method::GET, "/posts/5").unwrap();
+        assert_eq!(result.handler_name(), "byId");
+        assert_eq!(result.captures().get("id").unwrap(), "5");
+    }
+
+    #[test]
+    fn params_take_priority_over_catch_all() {
+        let map = map_with(&[("/files/*rest", "rest"), ("/files/:name", "name")]);
+        assert_eq!(map.match_user_defined(&Method::GET, "/files/a").unwrap().handler_name(), "name");
+        let result = map.match_user_defined(&Method::GET, "/files/a/b/c").unwrap();
+        assert_eq!(result.handler_name(), "rest");
+        assert_eq!(result.captures().get("rest").unwrap(), "a/b/c");
+    }
+
+    #[test]
+    fn falls_back_when_a_static_branch_dead_ends() {
+        let map = map_with(&[("/posts/latest/comments", "latestComments"), ("/posts/:id/likes", "likes")]);
+        let result = map.match_user_defined(&Method::GET, "/posts/latest/likes").unwrap();
+        assert_eq!(result.handler_name(), "likes");
+        assert_eq!(result.captures().get("id").unwrap(), "latest");
+    }
+
+    #[test]
+    fn methods_are_routed_separately() {
+        let map = map_with(&[("/posts", "list")]);
+        assert!(map.match_user_defined(&Method::GET, "/posts").is_some());
+        assert!(map.match_user_defined(&Method::DELETE, "/posts").is_none());
+        assert!(map.match_user_defined(&Method::OPTIONS, "/posts").is_some());
+    }
+
+    #[test]
+    fn conflicting_routes_are_rejected() {
+        let mut map = map_with(&[("/posts/:id", "byId")]);
+        assert!(map.add_record(&vec![], Some("Post"), "other", Method::GET, Some("/posts/:slug"), true).is_err());
+        assert!(map.add_record(&vec![], Some("Post"), "byId", Method::POST, Some("/posts/:slug"), true).is_ok());
+    }
+
+    #[test]
+    fn identical_routes_are_accepted_again() {
+        let mut map = map_with(&[("/posts/:id", "byId")]);
+        assert!(map.add_record(&vec![], Some("Post"), "byId", Method::GET, Some("/posts/:id"), true).is_ok());
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let mut map = Map::new();
+        assert!(map.add_record(&vec![], None, "a", Method::GET, Some("/posts/:"), true).is_err());
+        assert!(map.add_record(&vec![], None, "b", Method::GET, Some("/posts/*"), true).is_err());
+        assert!(map.add_record(&vec![], None, "c", Method::GET, Some("/posts/*rest/more"), true).is_err());
+    }
+}
@@ -33,5 +33,7 @@ pub mod app;
 pub mod cookies;
 pub mod message;
 pub mod headers;
+pub mod storage;
+pub mod testing;
 
 pub use value::Value;
\ No newline at end of file
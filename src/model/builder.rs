@@ -6,6 +6,7 @@ use itertools::Itertools;
 use teo_parser::ast::model::ModelResolved;
 use teo_result::{Error, Result};
 use crate::action::Action;
+use crate::action::action::EXPORT_HANDLER;
 use crate::comment::Comment;
 use crate::model::{Field, Index, Migration, Model, Property, Relation};
 use crate::model::field::indexable::Indexable;
@@ -13,8 +14,10 @@ use crate::model::relation::delete::Delete;
 use crate::pipeline::Pipeline;
 use crate::{model, Value};
 use crate::app::data::AppData;
+use crate::model::field::is_optional::IsOptional;
 use crate::model::field::typed::Typed;
 use crate::model::index::Item;
+use crate::model::builtin_input::builtin_input_type;
 use crate::traits::named::Named;
 
 #[derive(Clone)]
@@ -45,6 +48,7 @@ struct Inner {
     pub can_read: Arc<Mutex<Pipeline>>,
     pub can_mutate: Arc<Mutex<Pipeline>>,
     pub migration: Arc<Mutex<Migration>>,
+    pub soft_delete: Arc<Mutex<Option<String>>>,
     pub data: Arc<Mutex<BTreeMap<String, Value>>>,
     pub app_data: AppData,
 }
@@ -81,12 +85,17 @@ impl Builder {
                 can_read: Arc::new(Mutex::new(Pipeline::new())),
                 can_mutate: Arc::new(Mutex::new(Pipeline::new())),
                 migration: Arc::new(Mutex::new(Default::default())),
+                soft_delete: Arc::new(Mutex::new(None)),
                 data: Arc::new(Mutex::new(Default::default())),
                 app_data,
             })
         }
     }
 
+    pub fn path(&self) -> &Vec<String> {
+        &self.inner.path
+    }
+
     pub fn namespace_path(&self) -> &Vec<String> {
         &self.inner.namespace_path
     }
@@ -251,6 +260,14 @@ impl Builder {
         *self.inner.migration.lock().unwrap() = migration;
     }
 
+    pub fn soft_delete(&self) -> Option<String> {
+        self.inner.soft_delete.lock().unwrap().clone()
+    }
+
+    pub fn set_soft_delete(&self, soft_delete: Option<String>) {
+        *self.inner.soft_delete.lock().unwrap() = soft_delete;
+    }
+
     pub fn data(&self) -> BTreeMap<String, Value> {
         self.inner.data.lock().unwrap().clone()
     }
@@ -272,6 +289,27 @@ impl Builder {
     }
 
     pub(crate) fn build(self, shape: ModelResolved) -> Result<Model> {
+        // validate version field
+        if self.fields().values().filter(|f| f.data().get("version").is_some()).count() > 1 {
+            return Err(Error::new(format!("model has more than one version field: {}", self.inner.path.join("."))));
+        }
+        // validate soft delete field
+        if let Some(soft_delete) = self.soft_delete() {
+            let Some(field) = self.fields().get(&soft_delete).cloned() else {
+                return Err(Error::new(format!("soft delete field is not found: {}.{}", self.inner.path.join("."), soft_delete)));
+            };
+            if !field.r#type().is_datetime() || !field.is_optional() {
+                return Err(Error::new(format!("soft delete field should be an optional DateTime: {}.{}", self.inner.path.join("."), soft_delete)));
+            }
+        }
+        // validate refresh token store fields
+        if self.data_entry("identity:refreshTokenStore").is_some() {
+            for name in ["jti", "accessJti", "model", "identifier", "expiresAt", "revoked", "rotated"] {
+                if !self.fields().contains_key(name) {
+                    return Err(Error::new(format!("refresh token store field is not found: {}.{}", self.inner.path.join("."), name)));
+                }
+            }
+        }
         // set primary index if it is set through model decorator
         let mut primary_index_name = "".to_owned();
         for index in self.indexes().values() {
@@ -410,6 +448,16 @@ impl Builder {
             }
             map
         };
+        // a builtin handler is only served if its input can be decoded
+        let builtin_handlers: Vec<Action> = self.figure_out_builtin_handlers().into_iter().filter(|action| {
+            match builtin_input_type(&shape, *action) {
+                Some(input) => {
+                    cache.builtin_inputs.insert(*action, input);
+                    true
+                }
+                None => false,
+            }
+        }).collect();
         cache.shape = shape;
         Ok(Model {
             inner: Arc::new(model::model::Inner {
@@ -435,9 +483,10 @@ impl Builder {
                 can_read: self.inner.can_read.lock().unwrap().clone(),
                 can_mutate: self.inner.can_mutate.lock().unwrap().clone(),
                 migration: self.inner.migration.lock().unwrap().clone(),
+                soft_delete: self.inner.soft_delete.lock().unwrap().clone(),
                 data: self.inner.data.lock().unwrap().clone(),
                 cache,
-                builtin_handlers: self.figure_out_builtin_handlers()
+                builtin_handlers,
             }),
         })
     }
@@ -445,6 +494,10 @@ impl Builder {
     fn figure_out_builtin_handlers(&self) -> Vec<Action> {
         let mut result = vec![];
         for action in Action::builtin_handlers() {
+            // export streams whole tables, it's only served for models marked with `@export`
+            if *action == EXPORT_HANDLER && self.data_entry("export").is_none() {
+                continue;
+            }
             result.push(*action);
         }
         result
@@ -0,0 +1,142 @@
+use indexmap::{IndexMap, indexmap};
+use teo_parser::ast::model::ModelResolved;
+use teo_parser::r#type::synthesized_shape::SynthesizedShape;
+use teo_parser::r#type::synthesized_shape_reference::SynthesizedShapeReferenceKind;
+use teo_parser::r#type::Type;
+use crate::action::Action;
+use crate::action::action::*;
+
+/// The input type of the builtin handler `action`. This is the arguments shape which the schema
+/// parser synthesizes, with the arguments which the runtime accepts on top of it. The parser
+/// doesn't synthesize `upsertMany` arguments, they are built from the `upsert` arguments.
+pub(crate) fn builtin_input_type(shape: &ModelResolved, action: Action) -> Option<Type> {
+    let kind = match action {
+        FIND_UNIQUE_HANDLER => SynthesizedShapeReferenceKind::FindUniqueArgs,
+        FIND_FIRST_HANDLER => SynthesizedShapeReferenceKind::FindFirstArgs,
+        FIND_MANY_HANDLER => SynthesizedShapeReferenceKind::FindManyArgs,
+        CREATE_HANDLER => SynthesizedShapeReferenceKind::CreateArgs,
+        UPDATE_HANDLER => SynthesizedShapeReferenceKind::UpdateArgs,
+        COPY_HANDLER => SynthesizedShapeReferenceKind::CopyArgs,
+        UPSERT_HANDLER => SynthesizedShapeReferenceKind::UpsertArgs,
+        DELETE_HANDLER => SynthesizedShapeReferenceKind::DeleteArgs,
+        CREATE_MANY_HANDLER => SynthesizedShapeReferenceKind::CreateManyArgs,
+        UPSERT_MANY_HANDLER => return upsert_many_args(shape.get(SynthesizedShapeReferenceKind::UpsertArgs)?),
+        UPDATE_MANY_HANDLER => SynthesizedShapeReferenceKind::UpdateManyArgs,
+        COPY_MANY_HANDLER => SynthesizedShapeReferenceKind::CopyManyArgs,
+        DELETE_MANY_HANDLER => SynthesizedShapeReferenceKind::DeleteManyArgs,
+        COUNT_HANDLER => SynthesizedShapeReferenceKind::CountArgs,
+        AGGREGATE_HANDLER => SynthesizedShapeReferenceKind::AggregateArgs,
+        GROUP_BY_HANDLER => SynthesizedShapeReferenceKind::GroupByArgs,
+        EXPORT_HANDLER => SynthesizedShapeReferenceKind::FindManyArgs,
+        _ => None?,
+    };
+    Some(with_runtime_arguments(shape.get(kind)?, runtime_arguments(action)))
+}
+
+/// The arguments which the runtime accepts on top of the parser shape of `action`. They are
+/// optional, and a `null` value is kept, it's different from the argument being absent.
+fn runtime_arguments(action: Action) -> Vec<(&'static str, Type)> {
+    match action {
+        FIND_UNIQUE_HANDLER | FIND_FIRST_HANDLER | COUNT_HANDLER | AGGREGATE_HANDLER | GROUP_BY_HANDLER | EXPORT_HANDLER => vec![("withDeleted", Type::Bool)],
+        FIND_MANY_HANDLER => vec![("after", Type::String), ("before", Type::String), ("count", Type::Bool), ("withDeleted", Type::Bool)],
+        CREATE_MANY_HANDLER => vec![("skipDuplicates", Type::Bool), ("conflictTarget", Type::String)],
+        _ => vec![],
+    }
+}
+
+fn with_runtime_arguments(args: &Type, arguments: Vec<(&'static str, Type)>) -> Type {
+    match args {
+        Type::SynthesizedShape(shape) if !arguments.is_empty() => {
+            let mut fields: IndexMap<String, Type> = shape.iter().map(|(k, t)| (k.clone(), t.clone())).collect();
+            for (key, t) in arguments {
+                fields.insert(key.to_owned(), Type::Optional(Box::new(t)));
+            }
+            Type::SynthesizedShape(SynthesizedShape::new(fields))
+        }
+        _ => args.clone(),
+    }
+}
+
+/// `{ upsert: [{ create, update }], select?, include?, conflictTarget? }`, where `create`,
+/// `update`, `select` and `include` have the types of the `upsert` arguments.
+fn upsert_many_args(upsert_args: &Type) -> Option<Type> {
+    let Type::SynthesizedShape(upsert_args) = upsert_args else {
+        return None;
+    };
+    let entry = SynthesizedShape::new(indexmap! {
+        "create".to_owned() => upsert_args.get("create")?.clone(),
+        "update".to_owned() => upsert_args.get("update")?.clone(),
+    });
+    let mut fields = indexmap! {
+        "upsert".to_owned() => Type::Array(Box::new(Type::SynthesizedShape(entry))),
+    };
+    for key in ["select", "include"] {
+        if let Some(t) = upsert_args.get(key) {
+            fields.insert(key.to_owned(), t.clone());
+        }
+    }
+    fields.insert("conflictTarget".to_owned(), Type::Optional(Box::new(Type::String)));
+    Some(Type::SynthesizedShape(SynthesizedShape::new(fields)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use key_path::path;
+    use crate::app::data::AppData;
+    use crate::app::entrance::Entrance;
+    use crate::app::runtime_version::RuntimeVersion;
+    use crate::coder::json_to_teon::json_to_teon;
+    use crate::namespace;
+    use crate::value::Value;
+    use super::*;
+
+    fn upsert_args() -> Type {
+        Type::SynthesizedShape(SynthesizedShape::new(indexmap! {
+            "where".to_owned() => Type::Dictionary(Box::new(Type::Any)),
+            "create".to_owned() => Type::Dictionary(Box::new(Type::Any)),
+            "update".to_owned() => Type::Dictionary(Box::new(Type::Any)),
+            "select".to_owned() => Type::Optional(Box::new(Type::Dictionary(Box::new(Type::Bool)))),
+        }))
+    }
+
+    fn create_many_args() -> Type {
+        Type::SynthesizedShape(SynthesizedShape::new(indexmap! {
+            "create".to_owned() => Type::Array(Box::new(Type::Dictionary(Box::new(Type::Any)))),
+        }))
+    }
+
+    fn main_namespace() -> namespace::Namespace {
+        namespace::Builder::main(AppData::new(Entrance::APP, RuntimeVersion::Rust("test"))).build()
+    }
+
+    #[test]
+    fn upsert_many_args_are_built_from_upsert_args() {
+        let Some(Type::SynthesizedShape(shape)) = upsert_many_args(&upsert_args()) else {
+            panic!("expect a shape");
+        };
+        assert_eq!(shape.keys().map(|k| k.as_str()).collect::<Vec<&str>>(), vec!["upsert", "select", "conflictTarget"]);
+        let namespace = main_namespace();
+        let input = Type::SynthesizedShape(shape);
+        let value = json_to_teon(&json!({"upsert": [{"create": {"a": 1}, "update": {"a": 2}}], "conflictTarget": "email"}), &path![], &input, &namespace).unwrap();
+        assert_eq!(value.get("conflictTarget"), Some(&Value::String("email".to_owned())));
+        assert_eq!(value.get("upsert").unwrap().as_array().unwrap().len(), 1);
+        // an entry needs both create and update
+        assert!(json_to_teon(&json!({"upsert": [{"create": {"a": 1}}]}), &path![], &input, &namespace).is_err());
+        assert!(json_to_teon(&json!({"upsert": [], "where": {}}), &path![], &input, &namespace).is_err());
+    }
+
+    #[test]
+    fn create_many_conflict_arguments_are_decoded_with_the_shape() {
+        let input = with_runtime_arguments(&create_many_args(), runtime_arguments(CREATE_MANY_HANDLER));
+        let namespace = main_namespace();
+        let value = json_to_teon(&json!({"create": [], "skipDuplicates": true, "conflictTarget": null}), &path![], &input, &namespace).unwrap();
+        assert_eq!(value.get("skipDuplicates"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("conflictTarget"), Some(&Value::Null));
+        assert!(json_to_teon(&json!({"create": [], "skipDuplicates": "yes"}), &path![], &input, &namespace).is_err());
+        assert!(json_to_teon(&json!({"create": [], "conflictTarget": 1}), &path![], &input, &namespace).is_err());
+        // other handlers don't take the conflict arguments
+        let input = with_runtime_arguments(&create_many_args(), runtime_arguments(UPDATE_MANY_HANDLER));
+        assert!(json_to_teon(&json!({"create": [], "skipDuplicates": true}), &path![], &input, &namespace).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use futures_util::future::BoxFuture;
+use teo_result::Result;
+use crate::message::info_message;
+use crate::namespace::Namespace;
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Create,
+    Update,
+    Delete,
+}
+
+impl ChangeKind {
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Update => "update",
+            ChangeKind::Delete => "delete",
+        }
+    }
+
+    pub fn from_str(name: &str) -> Option<Self> {
+        match name {
+            "create" => Some(ChangeKind::Create),
+            "update" => Some(ChangeKind::Update),
+            "delete" => Some(ChangeKind::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// A committed change of a record.
+#[derive(Debug, Clone)]
+pub struct ModelChange {
+    pub model: Vec<String>,
+    pub kind: ChangeKind,
+    pub identifier: Value,
+    /// The field values of the record after the change. For deletions, these are the last
+    /// values of the record.
+    pub snapshot: Value,
+    pub changed_keys: Vec<String>,
+}
+
+pub type ChangeListener = Arc<dyn Fn(ModelChange) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+pub type WriteListener = Arc<dyn Fn(&Vec<String>) + Send + Sync>;
+
+/// Tells the write listeners that the model at `path` is written.
+pub(crate) fn notify_model_written(namespace: &Namespace, path: &Vec<String>) {
+    for listener in namespace.model_write_listeners() {
+        listener(path);
+    }
+}
+
+/// Calls the listeners of each change in order. A failing listener doesn't affect the others,
+/// since the changes are already committed.
+pub(crate) async fn dispatch_model_changes(namespace: &Namespace, changes: Vec<ModelChange>) {
+    for change in changes {
+        for listener in namespace.model_change_listeners(&change.model) {
+            if let Err(err) = listener(change.clone()).await {
+                info_message(format!("model change listener of {} failed: {}", change.model.join("."), err.message()));
+            }
+        }
+    }
+}
+
+/// Calls the listeners of a change loaded from an outbox. Unlike `dispatch_model_changes`,
+/// the first error is returned, so that the entry is delivered again later.
+pub(crate) async fn deliver_model_change(namespace: &Namespace, change: ModelChange) -> Result<()> {
+    for listener in namespace.model_change_listeners(&change.model) {
+        listener(change.clone()).await?;
+    }
+    Ok(())
+}
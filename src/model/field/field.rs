@@ -144,6 +144,13 @@ impl Field {
         &self.inner.can_read
     }
 
+    /// Whether the value of this field can be read by anyone. Values of the other fields must
+    /// not be copied into records which are read without the field's read checks, like audit
+    /// entries and change snapshots.
+    pub(crate) fn is_always_readable(&self) -> bool {
+        matches!(self.read(), Read::Read) && self.can_read().is_empty() && self.data().get("rbac:require").is_none()
+    }
+
     pub fn data(&self) -> &BTreeMap<String, Value> {
         &self.inner.data
     }
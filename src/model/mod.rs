@@ -7,7 +7,12 @@ pub mod index;
 pub mod migration;
 pub mod model;
 pub mod builder;
+pub mod builtin_input;
 pub mod ctx;
+pub mod change;
+pub mod permission;
+pub mod policy;
+pub mod tenant;
 
 pub use model::Model;
 pub use builder::Builder;
@@ -19,3 +24,4 @@ pub use field::Field;
 pub use relation::Relation;
 pub use property::Property;
 pub use ctx::Ctx;
+pub use change::{ChangeKind, ModelChange};
@@ -9,7 +9,7 @@ use teo_parser::r#type::reference::Reference;
 use teo_parser::r#type::synthesized_shape_reference::SynthesizedShapeReference;
 use teo_parser::r#type::Type;
 use crate::action::Action;
-use crate::action::action::{AGGREGATE_HANDLER, COPY_HANDLER, COPY_MANY_HANDLER, COUNT_HANDLER, CREATE_HANDLER, CREATE_MANY_HANDLER, DELETE_HANDLER, DELETE_MANY_HANDLER, FIND_FIRST_HANDLER, FIND_MANY_HANDLER, FIND_UNIQUE_HANDLER, GROUP_BY_HANDLER, UPDATE_HANDLER, UPDATE_MANY_HANDLER, UPSERT_HANDLER};
+use crate::action::action::{AGGREGATE_HANDLER, COPY_HANDLER, COPY_MANY_HANDLER, COUNT_HANDLER, CREATE_HANDLER, CREATE_MANY_HANDLER, DELETE_HANDLER, DELETE_MANY_HANDLER, EXPORT_HANDLER, FIND_FIRST_HANDLER, FIND_MANY_HANDLER, FIND_UNIQUE_HANDLER, GROUP_BY_HANDLER, UPDATE_HANDLER, UPDATE_MANY_HANDLER, UPSERT_HANDLER, UPSERT_MANY_HANDLER};
 use crate::comment::Comment;
 use crate::model::field::column_named::ColumnNamed;
 use crate::model::field::Field;
@@ -64,6 +64,8 @@ pub(super) struct Inner {
     #[serde(rename = "canMutate")]
     pub(super) can_mutate: Pipeline,
     pub(super) migration: Migration,
+    #[serde(rename = "softDelete")]
+    pub(super) soft_delete: Option<String>,
     pub(super) builtin_handlers: Vec<Action>,
     pub(super) data: BTreeMap<String, Value>,
     pub(super) cache: Cache,
@@ -103,6 +105,7 @@ impl Model {
                 can_mutate: Pipeline::new(),
                 actions: vec![],
                 migration: Default::default(),
+                soft_delete: None,
                 data: btreemap! {},
                 cache: Cache::new(),
                 builtin_handlers: vec![],
@@ -200,6 +203,16 @@ impl Model {
         &self.inner.migration
     }
 
+    /// The field marked with `@version`, which is used for optimistic concurrency control.
+    pub fn version_field(&self) -> Option<&Field> {
+        self.fields().values().find(|f| f.data().get("version").is_some())
+    }
+
+    /// The field which records the deletion time, if the model is soft deleted.
+    pub fn soft_delete(&self) -> Option<&str> {
+        self.inner.soft_delete.as_deref()
+    }
+
     pub fn data(&self) -> &BTreeMap<String, Value> {
         &self.inner.data
     }
@@ -261,6 +274,8 @@ impl Model {
             COUNT_HANDLER => Type::SynthesizedShapeReference(SynthesizedShapeReference::count_args(self.as_type_reference())),
             AGGREGATE_HANDLER => Type::SynthesizedShapeReference(SynthesizedShapeReference::aggregate_args(self.as_type_reference())),
             GROUP_BY_HANDLER => Type::SynthesizedShapeReference(SynthesizedShapeReference::group_by_args(self.as_type_reference())),
+            EXPORT_HANDLER => Type::SynthesizedShapeReference(SynthesizedShapeReference::find_many_args(self.as_type_reference())),
+            UPSERT_MANY_HANDLER => self.cache().builtin_inputs.get(&handler).cloned().unwrap(),
             _ => unreachable!()
         }
     }
@@ -311,10 +326,11 @@ impl Model {
                     Type::SynthesizedShapeReference(SynthesizedShapeReference::result(self.as_type_reference()))
                 ])
             },
-            CREATE_MANY_HANDLER => {
+            CREATE_MANY_HANDLER | UPSERT_MANY_HANDLER => {
+                // the meta has the count and the outcome of each input row
                 Type::InterfaceObject(data_meta, vec![
                     Type::Array(Box::new(Type::SynthesizedShapeReference(SynthesizedShapeReference::result(self.as_type_reference())))),
-                    Type::InterfaceObject(paging_info, vec![])
+                    Type::Dictionary(Box::new(Type::Union(vec![Type::Int64, Type::Array(Box::new(Type::String))])))
                 ])
             },
             UPDATE_MANY_HANDLER => {
@@ -350,6 +366,7 @@ impl Model {
                     Type::SynthesizedShapeReference(SynthesizedShapeReference::group_by_result(self.as_type_reference()))
                 ])
             },
+            EXPORT_HANDLER => Type::Any,
             _ => unreachable!()
         }
     }
@@ -398,6 +415,9 @@ pub struct Cache {
     #[serde(rename = "hasVirtualFields")]
     pub has_virtual_fields: bool,
     pub shape: ModelResolved,
+    /// The input types of the builtin handlers, which builtin handler requests are decoded with
+    #[serde(skip)]
+    pub builtin_inputs: IndexMap<Action, Type>,
 }
 
 impl Cache {
@@ -421,6 +441,7 @@ impl Cache {
             field_property_map: Default::default(),
             has_virtual_fields: false,
             shape: ModelResolved::new(),
+            builtin_inputs: IndexMap::new(),
         }
     }
 }
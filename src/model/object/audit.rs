@@ -0,0 +1,87 @@
+use chrono::Utc;
+use indexmap::IndexMap;
+use serde_json::Value as JsonValue;
+use teo_result::Result;
+use crate::model::{Model, Object};
+use crate::model::change::ChangeKind;
+use crate::namespace::Namespace;
+use crate::teon;
+use crate::value::Value;
+
+/// Returns the model which stores the audit entries of `model`, or `None` if `model` is not
+/// audited.
+pub(crate) fn audit_store<'a>(namespace: &'a Namespace, model: &Model) -> Option<&'a Model> {
+    let config = model.data().get("audit:model")?;
+    let stores = namespace.collect_models(|m| m.data().get("audit:store").is_some());
+    let store = match config.as_str() {
+        Some(name) => stores.into_iter().find(|m| m.path().join(".") == name),
+        None => stores.into_iter().next(),
+    }?;
+    // never audit the audit entries themselves
+    if store.path() == model.path() {
+        None
+    } else {
+        Some(store)
+    }
+}
+
+impl Object {
+
+    /// The changed fields of this object in the form of `{ field: { from, to } }`. Created
+    /// records have no `from`, and deleted records have no `to`. Fields which are not always
+    /// readable are left out.
+    fn audit_changes(&self, action: ChangeKind, modified_fields: &Vec<String>) -> Result<Value> {
+        let model = self.model();
+        let keys: Vec<&String> = match action {
+            ChangeKind::Update => modified_fields.iter().collect(),
+            _ => model.cache().save_keys.iter().collect(),
+        };
+        let mut changes: IndexMap<String, Value> = IndexMap::new();
+        for key in keys {
+            if !model.field(key).map_or(false, |f| f.is_always_readable()) {
+                continue;
+            }
+            let change = match action {
+                ChangeKind::Create => teon!({ "to": self.get_value(key)? }),
+                ChangeKind::Update => teon!({ "from": self.get_previous_value(key)?, "to": self.get_value(key)? }),
+                ChangeKind::Delete => teon!({ "from": self.get_value(key)? }),
+            };
+            changes.insert(key.clone(), change);
+        }
+        Ok(Value::Dictionary(changes))
+    }
+
+    /// Writes an entry into the audit store inside the transaction of this object. This does
+    /// nothing if the model is not marked with `@audit`.
+    pub(super) async fn record_audit_entry(&self, action: ChangeKind, modified_fields: &Vec<String>) -> Result<()> {
+        let Some(store) = audit_store(self.namespace(), self.model()) else {
+            return Ok(());
+        };
+        let changes = self.audit_changes(action, modified_fields)?;
+        if action == ChangeKind::Update && changes.as_dictionary().map_or(true, |c| c.is_empty()) {
+            return Ok(());
+        }
+        let identifier: JsonValue = self.identifier().try_into()?;
+        let changes: JsonValue = changes.try_into()?;
+        let identity = match self.request().as_ref().map(|r| r.local_values().get::<Object>("account")) {
+            Some(Ok(account)) => {
+                let account_identifier: JsonValue = account.identifier().try_into()?;
+                let identity = serde_json::json!({
+                    "model": account.model().path().join("."),
+                    "identifier": account_identifier,
+                });
+                Value::String(identity.to_string())
+            }
+            _ => Value::Null,
+        };
+        let entry = self.transaction_ctx().create_object(store, teon!({
+            "model": self.model().path().join("."),
+            "identifier": identifier.to_string(),
+            "action": action.as_str(),
+            "changes": changes.to_string(),
+            "identity": identity,
+            "createdAt": Utc::now(),
+        }), self.request()).await?;
+        entry.save().await
+    }
+}
@@ -0,0 +1,31 @@
+use key_path::KeyPath;
+use teo_result::Result;
+use crate::model::{Model, Object};
+
+/// Saves new objects of `model` with a single `save_objects` call of the transaction. The
+/// `onSave` pipelines, the required field validation and the callbacks run for each object
+/// like `save_with_session_and_path` does. Whether the records are written in one statement is
+/// up to the connector. The objects shouldn't have relation manipulations, which are not
+/// performed. Errors of the object at `index` are reported at `path + index`.
+pub(crate) async fn save_new_objects(model: &Model, objects: &[Object], path: &KeyPath) -> Result<()> {
+    let Some(first) = objects.first() else {
+        return Ok(());
+    };
+    let mut changed_fields = vec![];
+    for (index, object) in objects.iter().enumerate() {
+        let path = path + index;
+        object.before_save_callback_check(&path)?;
+        object.apply_on_save_pipeline_and_validate_required_fields(&path, false).await?;
+        object.trigger_before_save_callbacks(&path).await?;
+        object.prepare_to_save_to_database(&path).await?;
+        changed_fields.push(object.modified_field_names());
+    }
+    first.transaction_ctx().transaction_for_model(model).await.save_objects(objects, path.clone()).await?;
+    for (index, object) in objects.iter().enumerate() {
+        let path = path + index;
+        object.did_save_to_database(true, &changed_fields[index], &path).await?;
+        object.clear_state();
+        object.trigger_after_save_callbacks(&path).await?;
+    }
+    Ok(())
+}
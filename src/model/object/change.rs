@@ -0,0 +1,106 @@
+use chrono::Utc;
+use indexmap::IndexMap;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::model::{Model, Object};
+use crate::model::change::{ChangeKind, ModelChange};
+use crate::namespace::Namespace;
+use crate::teon;
+use crate::value::Value;
+
+/// Returns the outbox model which the changes of `model` are written into, or `None` if
+/// `model` is not marked with `@publishChanges`.
+pub(crate) fn outbox_store<'a>(namespace: &'a Namespace, model: &Model) -> Option<&'a Model> {
+    let config = model.data().get("outbox:publish")?;
+    let stores = namespace.collect_models(|m| m.data().get("outbox:store").is_some());
+    let store = match config.as_str() {
+        Some(name) => stores.into_iter().find(|m| m.path().join(".") == name),
+        None => stores.into_iter().next(),
+    }?;
+    // never publish the outbox entries themselves
+    if store.path() == model.path() {
+        None
+    } else {
+        Some(store)
+    }
+}
+
+fn parse_json(entry: &Object, key: &str) -> Result<JsonValue> {
+    let value = entry.get_value(key)?;
+    let string = value.as_str().ok_or_else(|| Error::new(format!("outbox entry has invalid {}", key)))?;
+    serde_json::from_str(string).map_err(|err| Error::new(format!("outbox entry has invalid {}: {}", key, err)))
+}
+
+/// Restores the change which is recorded in an outbox entry.
+pub(crate) fn model_change_from_outbox_entry(entry: &Object) -> Result<ModelChange> {
+    let model = entry.get_value("model")?;
+    let kind = entry.get_value("kind")?;
+    let Some(kind) = kind.as_str().and_then(ChangeKind::from_str) else {
+        return Err(Error::new("outbox entry has invalid kind"));
+    };
+    let changed_keys = parse_json(entry, "changedKeys")?;
+    Ok(ModelChange {
+        model: model.as_str().map_or(vec![], |m| m.split(".").map(|s| s.to_owned()).collect()),
+        kind,
+        identifier: parse_json(entry, "identifier")?.into(),
+        snapshot: parse_json(entry, "snapshot")?.into(),
+        changed_keys: changed_keys.as_array().map_or(vec![], |keys| keys.iter().filter_map(|k| k.as_str().map(|k| k.to_owned())).collect()),
+    })
+}
+
+impl Object {
+
+    /// The field values of this object. Fields which are not always readable are left out.
+    fn change_snapshot(&self) -> Result<Value> {
+        let model = self.model();
+        let mut snapshot: IndexMap<String, Value> = IndexMap::new();
+        for key in &model.cache().save_keys {
+            if model.field(key).map_or(false, |f| f.is_always_readable()) {
+                snapshot.insert(key.clone(), self.get_value(key)?);
+            }
+        }
+        Ok(Value::Dictionary(snapshot))
+    }
+
+    /// Publishes a change of this object. If the model is marked with `@publishChanges`, the
+    /// change is written into the outbox inside the transaction of this object. Otherwise, it's
+    /// handed to the change listeners once the transaction is committed.
+    pub(super) async fn publish_model_change(&self, kind: ChangeKind, modified_fields: &Vec<String>) -> Result<()> {
+        let namespace = self.namespace();
+        let model = self.model();
+        let store = outbox_store(namespace, model);
+        if store.is_none() && namespace.model_change_listeners(model.path()).is_empty() {
+            return Ok(());
+        }
+        let changed_keys: Vec<String> = match kind {
+            ChangeKind::Update => modified_fields.iter().filter(|k| model.field(k).map_or(false, |f| f.is_always_readable())).cloned().collect(),
+            _ => model.cache().save_keys.iter().filter(|k| model.field(k).map_or(false, |f| f.is_always_readable())).cloned().collect(),
+        };
+        if kind == ChangeKind::Update && changed_keys.is_empty() {
+            return Ok(());
+        }
+        let change = ModelChange {
+            model: model.path().clone(),
+            kind,
+            identifier: self.identifier(),
+            snapshot: self.change_snapshot()?,
+            changed_keys,
+        };
+        let Some(store) = store else {
+            self.transaction_ctx().push_model_change(change).await;
+            return Ok(());
+        };
+        let identifier = JsonValue::try_from(&change.identifier)?;
+        let snapshot = JsonValue::try_from(&change.snapshot)?;
+        let changed_keys = JsonValue::from(change.changed_keys);
+        let entry = self.transaction_ctx().create_object(store, teon!({
+            "model": model.path().join("."),
+            "kind": kind.as_str(),
+            "identifier": identifier.to_string(),
+            "snapshot": snapshot.to_string(),
+            "changedKeys": changed_keys.to_string(),
+            "createdAt": Utc::now(),
+        }), self.request()).await?;
+        entry.save().await
+    }
+}
@@ -1,4 +1,10 @@
 pub mod object;
 pub mod input;
+pub(crate) mod audit;
+pub(crate) mod change;
+pub(crate) mod storage;
+pub(crate) mod policy;
+pub(crate) mod tenant;
+pub(crate) mod batch;
 
 pub use object::Object;
@@ -15,11 +15,13 @@ use crate::model::{Field, Model};
 use key_path::{path, KeyPath};
 use crate::traits::named::Named;
 use async_recursion::async_recursion;
+use chrono::Utc;
 use futures_util::StreamExt;
 use indexmap::{IndexMap, indexmap};
 use itertools::Itertools;
 use crate::teon;
 use crate::action::action::*;
+use crate::model::change::ChangeKind;
 use crate::model::object::input::Input;
 use crate::model::object::input::Input::{AtomicUpdater, SetValue};
 use crate::model::relation::Relation;
@@ -34,6 +36,7 @@ use crate::optionality::Optionality;
 use crate::readwrite::write::Write;
 use crate::utils::ContainsStr;
 use crate::error_ext;
+use crate::model::permission::check_permissions;
 
 #[derive(Clone)]
 pub struct Object {
@@ -218,25 +221,25 @@ impl Object {
     async fn check_model_write_permission<'a>(&self, path: impl AsRef<KeyPath>) -> Result<()> {
         let ctx = self.pipeline_ctx_for_path_and_value(path.as_ref().clone(), Value::Null);
         ctx.run_pipeline_ignore_return_value(self.model().can_mutate()).await.alter_error_code(401)?;
-        Ok(())
+        check_permissions(self.namespace(), self.model().data(), self.request(), path.as_ref()).await
     }
 
     async fn check_model_read_permission<'a>(&self, path: impl AsRef<KeyPath>) -> Result<()> {
         let ctx = self.pipeline_ctx_for_path_and_value(path.as_ref().clone(), Value::Null);
         ctx.run_pipeline_ignore_return_value(self.model().can_read()).await.alter_error_code(401)?;
-        Ok(())
+        check_permissions(self.namespace(), self.model().data(), self.request(), path.as_ref()).await
     }
 
     async fn check_field_write_permission<'a>(&self, field: &Field, path: impl AsRef<KeyPath>) -> Result<()> {
         let ctx = self.pipeline_ctx_for_path_and_value(path.as_ref().clone(), Value::Null);
         ctx.run_pipeline_ignore_return_value(field.can_mutate()).await.alter_error_code(401)?;
-        Ok(())
+        check_permissions(self.namespace(), field.data(), self.request(), path.as_ref()).await
     }
 
     async fn check_field_read_permission<'a>(&self, field: &Field, path: impl AsRef<KeyPath>) -> Result<()> {
         let ctx = self.pipeline_ctx_for_path_and_value(path.as_ref().clone(), Value::Null);
         ctx.run_pipeline_ignore_return_value(field.can_read()).await.alter_error_code(401)?;
-        Ok(())
+        check_permissions(self.namespace(), field.data(), self.request(), path.as_ref()).await
     }
 
     fn record_previous_value_for_field_if_needed(&self, key: &str) {
@@ -383,6 +386,12 @@ impl Object {
         }
     }
 
+    /// Sets the related objects of a relation, as if they're fetched with the query of this
+    /// object.
+    pub(crate) fn set_query_relation_objects(&self, key: &str, objects: Vec<Object>) {
+        self.inner.relation_query_map.lock().unwrap().insert(key.to_owned(), objects);
+    }
+
     pub fn has_query_relation_fetched(&self, key: impl AsRef<str>) -> bool {
         self.inner.relation_query_map.lock().unwrap().contains_key(key.as_ref())
     }
@@ -651,26 +660,46 @@ impl Object {
     pub async fn delete_from_database(&self, path: &KeyPath) -> Result<()> {
         let model = self.model();
         let namespace = self.namespace();
-        // check deny first
+        self.check_write_policy(self.identifier(), path).await?;
+        // check deny first, relations are checked regardless of policies
         for (opposite_model, opposite_relation) in namespace.model_opposite_relations(model) {
             if opposite_relation.delete() == Delete::Deny {
                 let finder = self.intrinsic_where_unique_for_opposite_relation(opposite_relation);
-                let count = self.transaction_ctx().count_objects(opposite_model, &finder, path.clone()).await.unwrap();
+                let count = self.transaction_ctx().bypassing_policies().count_objects(opposite_model, &finder, path.clone()).await.unwrap();
                 if count > 0 {
                     return Err(error_ext::deletion_denied(path.clone(), &format!("{}.{}", opposite_model.path().join("."), opposite_relation.name())));
                 }
             }
         }
-        // real delete
-        self.transaction_ctx().transaction_for_model(self.model()).await.delete_object(self, path.clone()).await?;
+        // real delete, or mark the record as deleted
+        self.inner.is_deleted.store(true, Ordering::SeqCst);
+        let soft_deleted = if let Some(soft_delete) = model.soft_delete() {
+            if self.get_value(soft_delete)?.is_null() {
+                self.set_value(soft_delete, Value::DateTime(Utc::now()))?;
+                self.save_to_database(path).await?;
+            }
+            true
+        } else {
+            self.transaction_ctx().transaction_for_model(self.model()).await.delete_object(self, path.clone()).await?;
+            self.transaction_ctx().record_model_write(model);
+            self.transaction_ctx().forget_identity(model, &self.identifier());
+            false
+        };
+        self.record_audit_entry(ChangeKind::Delete, &vec![]).await?;
+        self.publish_model_change(ChangeKind::Delete, &vec![]).await?;
         // nullify and cascade
         for (opposite_model, opposite_relation) in namespace.model_opposite_relations(model) {
+            // a soft deleted record still exists, references to it are kept so that it can be
+            // restored, and only children which can be restored too are cascaded
+            if soft_deleted && (opposite_relation.delete() != Delete::Cascade || opposite_model.soft_delete().is_none()) {
+                continue;
+            }
             match opposite_relation.delete() {
                 Delete::NoAction => {} // do nothing
                 Delete::Deny => {}, // done before
                 Delete::Nullify => {
                     let finder = self.intrinsic_where_unique_for_opposite_relation(opposite_relation);
-                    self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
+                    self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
                         for key in opposite_relation.fields() {
                             object.set_value(key, Value::Null)?;
                         }
@@ -680,14 +709,14 @@ impl Object {
                 },
                 Delete::Cascade => {
                     let finder = self.intrinsic_where_unique_for_opposite_relation(opposite_relation);
-                    self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DELETE | SINGLE, self.request(), path.clone(), |object| async move {
+                    self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DELETE | SINGLE, self.request(), path.clone(), |object| async move {
                         object.delete_from_database(path).await?;
                         Ok(())
                     }).await?;
                 }
                 Delete::Default => {
                     let finder = self.intrinsic_where_unique_for_opposite_relation(opposite_relation);
-                    self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
+                    self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
                         for key in opposite_relation.fields() {
                             let field = opposite_model.field(key).unwrap();
                             if let Some(default) = field.default() {
@@ -714,6 +743,9 @@ impl Object {
 
     #[async_recursion]
     async fn save_to_database(&self, path: &KeyPath) -> Result<()> {
+        let is_new = self.is_new();
+        let condition = self.prepare_to_save_to_database(path).await?;
+        let changed_fields = self.modified_field_names();
         if !self.is_new() && self.is_modified() {
             let modified_fields = self.inner.modified_fields.lock().unwrap().clone();
             let namespace = self.namespace();
@@ -729,7 +761,7 @@ impl Object {
                     }
                     if contains {
                         let finder = self.intrinsic_where_unique_for_opposite_relation_with_prev_value(opposite_relation);
-                        let count = self.transaction_ctx().count_objects(opposite_model, &finder, path.clone()).await.unwrap();
+                        let count = self.transaction_ctx().bypassing_policies().count_objects(opposite_model, &finder, path.clone()).await.unwrap();
                         if count > 0 {
                             return Err(error_ext::updation_denied(path.clone(), &format!("{}.{}", opposite_model.path().join("."), opposite_relation.name())));
                         }
@@ -753,7 +785,7 @@ impl Object {
                         Update::Nullify => {
                             if opposite_relation.has_foreign_key() {
                                 let finder = self.intrinsic_where_unique_for_opposite_relation_with_prev_value(opposite_relation);
-                                self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
+                                self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
                                     for key in opposite_relation.fields() {
                                         object.set_value(key, Value::Null)?;
                                     }
@@ -764,7 +796,7 @@ impl Object {
                         },
                         Update::Update => {
                             let finder = self.intrinsic_where_unique_for_opposite_relation_with_prev_value(opposite_relation);
-                            self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
+                            self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
                                 for (local, foreign) in opposite_relation.iter() {
                                     let current = self.get_value(foreign)?;
                                     if object.get_value(local)? != current {
@@ -777,14 +809,14 @@ impl Object {
                         }
                         Update::Delete => {
                             let finder = self.intrinsic_where_unique_for_opposite_relation_with_prev_value(opposite_relation);
-                            self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DELETE | SINGLE, self.request(), path.clone(), |object| async move {
+                            self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DELETE | SINGLE, self.request(), path.clone(), |object| async move {
                                 object.delete_from_database(path).await?;
                                 Ok(())
                             }).await?;
                         }
                         Update::Default => {
                             let finder = self.intrinsic_where_unique_for_opposite_relation_with_prev_value(opposite_relation);
-                            self.transaction_ctx().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
+                            self.transaction_ctx().bypassing_policies().batch(opposite_model, &finder, CODE_NAME | DISCONNECT | SINGLE, self.request(), path.clone(), |object| async move {
                                 for key in opposite_relation.fields() {
                                     let field = opposite_model.field(key).unwrap();
                                     if let Some(default) = field.default() {
@@ -807,12 +839,78 @@ impl Object {
                 }
             }
         }
-        self.transaction_ctx().transaction_for_model(self.model()).await.save_object(self, path.clone()).await?;
+        let transaction = self.transaction_ctx().transaction_for_model(self.model()).await;
+        match condition {
+            // the record is changed by someone else since it was read
+            Some(condition) => if !transaction.save_object_if(self, &condition, path.clone()).await? {
+                return Err(error_ext::version_conflict(path.clone(), self.model()));
+            },
+            None => transaction.save_object(self, path.clone()).await?,
+        }
+        self.did_save_to_database(is_new, &changed_fields, path).await
+    }
+
+    /// Assigns the values which are decided right before the record is written, and checks
+    /// whether the record can be written. Returns the values which the stored record must
+    /// still have when it's updated.
+    pub(super) async fn prepare_to_save_to_database(&self, path: &KeyPath) -> Result<Option<IndexMap<String, Value>>> {
+        self.assign_tenant(path)?;
+        self.move_files_into_storage(path).await?;
+        if !self.is_new() && self.is_modified() {
+            self.check_write_policy(self.previous_identifier(), path).await?;
+            return self.bump_version(path);
+        }
+        Ok(None)
+    }
+
+    pub(super) fn modified_field_names(&self) -> Vec<String> {
+        self.inner.modified_fields.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Finishes saving after the record is written.
+    pub(super) async fn did_save_to_database(&self, is_new: bool, changed_fields: &Vec<String>, path: &KeyPath) -> Result<()> {
+        self.transaction_ctx().record_model_write(self.model());
+        self.transaction_ctx().forget_identity(self.model(), &self.identifier());
+        self.check_write_policy(self.identifier(), path).await?;
         self.clear_new_state();
+        // a soft delete is audited and published as a deletion
+        if !self.inner.is_deleted.load(Ordering::SeqCst) {
+            let kind = if is_new { ChangeKind::Create } else { ChangeKind::Update };
+            self.record_audit_entry(kind, changed_fields).await?;
+            self.publish_model_change(kind, changed_fields).await?;
+        }
         Ok(())
     }
 
-    fn before_save_callback_check(&self, path: &KeyPath) -> Result<()> {
+    /// Increases the `@version` field of the record, and returns the version which the stored
+    /// record must still have when it's written. This fails if the client sends a version other
+    /// than the one which is read.
+    fn bump_version(&self, path: &KeyPath) -> Result<Option<IndexMap<String, Value>>> {
+        let Some(field) = self.model().version_field() else {
+            return Ok(None);
+        };
+        let modified = self.inner.modified_fields.lock().unwrap().contains(field.name());
+        let read_version = if modified {
+            self.get_previous_value(field.name())?
+        } else {
+            self.get_value(field.name())?
+        };
+        if modified && self.get_value(field.name())? != read_version {
+            return Err(error_ext::version_conflict(path.clone(), self.model()));
+        }
+        let next_version = match &read_version {
+            Value::Int(version) => Value::Int(version + 1),
+            Value::Int64(version) => Value::Int64(version + 1),
+            _ if field.r#type().is_int() => Value::Int(1),
+            _ => Value::Int64(1),
+        };
+        self.set_value(field.name(), next_version)?;
+        let mut condition = IndexMap::new();
+        condition.insert(field.name().to_owned(), read_version);
+        Ok(Some(condition))
+    }
+
+    pub(super) fn before_save_callback_check(&self, path: &KeyPath) -> Result<()> {
         let inside_before_callback = self.inner.inside_before_save_callback.load(Ordering::SeqCst);
         if inside_before_callback {
             return Err(error_ext::invalid_operation(path.clone(), "save called inside before callback"));
@@ -873,13 +971,13 @@ impl Object {
         Ok(())
     }
 
-    async fn trigger_before_save_callbacks<'a>(&self, path: impl AsRef<KeyPath>) -> Result<()> {
+    pub(super) async fn trigger_before_save_callbacks<'a>(&self, path: impl AsRef<KeyPath>) -> Result<()> {
         let ctx = self.pipeline_ctx_for_path_and_value(path.as_ref().clone(), Value::Null);
         ctx.run_pipeline_ignore_return_value(self.model().before_save()).await?;
         Ok(())
     }
 
-    async fn trigger_after_save_callbacks<'a>(&self, path: impl AsRef<KeyPath>) -> Result<()> {
+    pub(super) async fn trigger_after_save_callbacks<'a>(&self, path: impl AsRef<KeyPath>) -> Result<()> {
         let inside_after_save_callback = self.inner.inside_after_save_callback.load(Ordering::SeqCst);
         if inside_after_save_callback {
             return Ok(());
@@ -1143,6 +1241,7 @@ impl Object {
     }
 
     async fn link_and_save_relation_object(&self, relation: &Relation, object: &Object, path: &KeyPath) -> Result<()> {
+        self.check_relation_tenant(object, path)?;
         let mut linked = false;
         let (_, opposite_relation) = self.namespace().opposite_relation(relation);
         if let Some(opposite_relation) = opposite_relation {
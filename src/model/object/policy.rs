@@ -0,0 +1,29 @@
+use key_path::KeyPath;
+use teo_result::Result;
+use crate::error_ext;
+use crate::model::Object;
+use crate::model::policy::PolicyKind;
+use crate::teon;
+use crate::value::Value;
+
+impl Object {
+
+    /// Checks that the record identified by `identifier` satisfies the write policy of the
+    /// model. Records are checked before they're updated or deleted, and after they're
+    /// saved, so that a record can neither be written if it's not allowed, nor be moved out
+    /// of what's allowed.
+    pub(super) async fn check_write_policy(&self, identifier: Value, path: &KeyPath) -> Result<()> {
+        let Some(clause) = self.transaction_ctx().policy_where(self.model(), PolicyKind::Write) else {
+            return Ok(());
+        };
+        let finder = teon!({
+            "where": { "AND": [identifier, clause] },
+            "withDeleted": true,
+        });
+        let count = self.transaction_ctx().bypassing_policies().count_objects(self.model(), &finder, path.clone()).await?;
+        if count == 0 {
+            return Err(error_ext::write_denied_by_policy(path.clone(), self.model()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+use bytes::Bytes;
+use key_path::KeyPath;
+use teo_result::{Error, Result};
+use crate::model::Object;
+use crate::request::multipart::upload_temp_dir;
+use crate::traits::named::Named;
+use crate::value::Value;
+
+impl Object {
+
+    /// Moves the files assigned to `@storage` fields into their file storage backends, and
+    /// replaces the files with the keys of the stored files. Only files in the upload temp
+    /// directory are accepted, since the file path of a file value may come from the client.
+    pub(super) async fn move_files_into_storage(&self, path: &KeyPath) -> Result<()> {
+        for field in self.model().fields().values() {
+            let Some(storage_name) = field.data().get("storage").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let Value::File(file) = self.get_value(field.name())? else {
+                continue;
+            };
+            let Some(storage) = self.namespace().file_storage(storage_name) else {
+                return Err(Error::new(format!("file storage is not defined: {}", storage_name)));
+            };
+            let Some(filepath) = uploaded_file_path(file.filepath(), &upload_temp_dir()).await else {
+                return Err(Error::invalid_request_pathed(path + field.name(), "file is not uploaded"));
+            };
+            let data = tokio::fs::read(&filepath).await.map_err(|_| {
+                Error::invalid_request_pathed(path + field.name(), "file is not readable")
+            })?;
+            let prefix = field.data().get("storage:prefix").and_then(|v| v.as_str()).unwrap_or("");
+            let key = match file.filename_ext() {
+                Some(ext) => format!("{}{}.{}", prefix, uuid::Uuid::new_v4(), ext),
+                None => format!("{}{}", prefix, uuid::Uuid::new_v4()),
+            };
+            storage.put(&key, Bytes::from(data), file.content_type()).await?;
+            self.transaction_ctx().push_stored_file(storage_name, &key);
+            self.set_value(field.name(), Value::String(key))?;
+        }
+        Ok(())
+    }
+}
+
+/// The resolved path of a file in `temp_dir`. Paths outside of it, including the ones which
+/// escape it with `..` or links, are `None`.
+async fn uploaded_file_path(filepath: &str, temp_dir: &Path) -> Option<PathBuf> {
+    let filepath = tokio::fs::canonicalize(filepath).await.ok()?;
+    let temp_dir = tokio::fs::canonicalize(temp_dir).await.ok()?;
+    if filepath.starts_with(&temp_dir) {
+        Some(filepath)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_files_in_the_temp_dir_are_uploaded_files() {
+        let root = std::env::temp_dir().join(format!("teo-storage-test-{}", uuid::Uuid::new_v4()));
+        let temp_dir = root.join("uploads");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let uploaded = temp_dir.join("uploaded");
+        let outside = root.join("outside");
+        std::fs::write(&uploaded, "a").unwrap();
+        std::fs::write(&outside, "b").unwrap();
+        let resolved = uploaded_file_path(uploaded.to_str().unwrap(), &temp_dir).await;
+        assert_eq!(resolved, Some(std::fs::canonicalize(&uploaded).unwrap()));
+        assert_eq!(uploaded_file_path(outside.to_str().unwrap(), &temp_dir).await, None);
+        let escaping = temp_dir.join("..").join("outside");
+        assert_eq!(uploaded_file_path(escaping.to_str().unwrap(), &temp_dir).await, None);
+        assert_eq!(uploaded_file_path(temp_dir.join("missing").to_str().unwrap(), &temp_dir).await, None);
+        #[cfg(unix)]
+        {
+            let link = temp_dir.join("link");
+            std::os::unix::fs::symlink(&outside, &link).unwrap();
+            assert_eq!(uploaded_file_path(link.to_str().unwrap(), &temp_dir).await, None);
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
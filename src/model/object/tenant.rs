@@ -0,0 +1,35 @@
+use key_path::KeyPath;
+use teo_result::{Error, Result};
+use crate::model::Object;
+use crate::model::tenant::tenant_field;
+
+impl Object {
+
+    /// Assigns the current tenant to a record which has no tenant, and rejects writing a record
+    /// of another tenant.
+    pub(super) fn assign_tenant(&self, path: &KeyPath) -> Result<()> {
+        let Some((field, tenant)) = self.transaction_ctx().tenant_where(self.model())? else {
+            return Ok(());
+        };
+        let value = self.get_value(&field)?;
+        if value.is_null() {
+            self.set_value(&field, tenant)?;
+        } else if value != tenant {
+            return Err(Error::unauthorized_pathed(path + field.as_str(), "cannot write record of another tenant"));
+        }
+        Ok(())
+    }
+
+    /// Rejects connecting records of different tenants.
+    pub(super) fn check_relation_tenant(&self, object: &Object, path: &KeyPath) -> Result<()> {
+        let (Some(field), Some(other_field)) = (tenant_field(self.model()), tenant_field(object.model())) else {
+            return Ok(());
+        };
+        let tenant = self.get_value(field)?;
+        let other_tenant = object.get_value(other_field)?;
+        if !tenant.is_null() && !other_tenant.is_null() && tenant != other_tenant {
+            return Err(Error::invalid_request_pathed(path.clone(), "cannot connect records of different tenants"));
+        }
+        Ok(())
+    }
+}
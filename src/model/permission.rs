@@ -0,0 +1,21 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use futures_util::future::BoxFuture;
+use key_path::KeyPath;
+use teo_result::Result;
+use crate::namespace::Namespace;
+use crate::request::Request;
+use crate::value::Value;
+
+/// A check which runs when a model or a field is read or written, after `@canRead` and
+/// `@canMutate`. It receives the data of the model or the field, so that a library can check
+/// the requirements its decorators store there.
+pub type PermissionCheck = Arc<dyn Fn(&BTreeMap<String, Value>, Option<Request>, &KeyPath) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Runs the permission checks against the data of a model or a field.
+pub(crate) async fn check_permissions(namespace: &Namespace, data: &BTreeMap<String, Value>, request: Option<Request>, path: &KeyPath) -> Result<()> {
+    for check in namespace.permission_checks() {
+        check(data, request.clone(), path).await?;
+    }
+    Ok(())
+}
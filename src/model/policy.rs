@@ -0,0 +1,72 @@
+use indexmap::IndexMap;
+use crate::model::{Model, Object};
+use crate::value::Value;
+
+/// The operations which `@policy` restricts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyKind {
+    Read,
+    Write,
+}
+
+impl PolicyKind {
+
+    fn data_key(&self) -> &'static str {
+        match self {
+            PolicyKind::Read => "policy:read",
+            PolicyKind::Write => "policy:write",
+        }
+    }
+}
+
+/// The field values of an identity, which policies are evaluated against. The values are
+/// copied so that the transaction ctx doesn't hold the object, which holds the ctx.
+pub(crate) fn identity_values(identity: &Object) -> Value {
+    let mut values = IndexMap::new();
+    for name in identity.model().fields().keys() {
+        values.insert(name.clone(), identity.get_value(name).unwrap());
+    }
+    Value::Dictionary(values)
+}
+
+/// Builds the where clause of the `@policy` of `model`, or returns `None` if the model has no
+/// policy of `kind`.
+///
+/// The policy is a where input template. Strings like `"$identity.id"` are replaced with the
+/// values of the identity's fields. If the template refers to the identity while there is no
+/// identity, the clause matches no records.
+pub(crate) fn policy_where(model: &Model, kind: PolicyKind, identity: Option<&Value>) -> Option<Value> {
+    let template = model.data().get(kind.data_key())?;
+    Some(resolve_template(template, identity).unwrap_or_else(|| deny_all_where(model)))
+}
+
+/// Returns `None` if the template refers to the identity while there is no identity.
+fn resolve_template(template: &Value, identity: Option<&Value>) -> Option<Value> {
+    Some(match template {
+        Value::String(string) => match string.strip_prefix("$identity.") {
+            Some(key) => identity?.get(key).cloned().unwrap_or(Value::Null),
+            None => template.clone(),
+        },
+        Value::Dictionary(map) => {
+            let mut result = IndexMap::new();
+            for (key, value) in map {
+                result.insert(key.clone(), resolve_template(value, identity)?);
+            }
+            Value::Dictionary(result)
+        }
+        Value::Array(items) => {
+            let mut result = vec![];
+            for item in items {
+                result.push(resolve_template(item, identity)?);
+            }
+            Value::Array(result)
+        }
+        _ => template.clone(),
+    })
+}
+
+/// A clause which matches no records, since primary keys are never null.
+fn deny_all_where(model: &Model) -> Value {
+    let keys = model.primary_index().map(|index| index.keys().clone()).unwrap_or_default();
+    Value::Dictionary(keys.into_iter().map(|key| (key, Value::Null)).collect())
+}
@@ -0,0 +1,27 @@
+use teo_parser::r#type::Type;
+use teo_result::{Error, Result};
+use crate::model::Model;
+use crate::namespace::Namespace;
+use crate::value::Value;
+
+/// The field which the records of `model` are scoped by, if the model is marked with `@tenant`.
+pub fn tenant_field(model: &Model) -> Option<&str> {
+    model.data().get("tenant:field").and_then(|v| v.as_str())
+}
+
+/// Returns the tenant field of `model` and the value of `tenant` for the field. Tenants read
+/// from headers and subdomains are strings, they're parsed for integer tenant fields.
+pub(crate) fn tenant_field_value<'a>(model: &'a Model, tenant: &Value, namespace: &Namespace) -> Result<Option<(&'a str, Value)>> {
+    let Some(name) = tenant_field(model) else {
+        return Ok(None);
+    };
+    let Some(field) = model.field(name) else {
+        return Err(Error::new(format!("tenant field is not defined: {}.{}", model.path().join("."), name)));
+    };
+    let value = match (field.r#type().unwrap_optional(), tenant) {
+        (Type::Int, Value::String(s)) => Value::Int(s.parse().map_err(|_| Error::invalid_request_message("invalid tenant"))?),
+        (Type::Int64, Value::String(s)) => Value::Int64(s.parse().map_err(|_| Error::invalid_request_message("invalid tenant"))?),
+        (t, _) => tenant.cast(Some(t), namespace),
+    };
+    Ok(Some((name, value)))
+}
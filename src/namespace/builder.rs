@@ -2,6 +2,8 @@ use std::collections::BTreeMap;
 use std::future::Future;
 use std::sync::{Arc, Mutex};
 use educe::Educe;
+use futures_util::future::BoxFuture;
+use key_path::KeyPath;
 use maplit::btreemap;
 use teo_parser::ast::handler::HandlerInputFormat;
 use teo_parser::ast::middleware::MiddlewareType;
@@ -25,7 +27,10 @@ use hyper::Method;
 use crate::middleware::middleware_imp::{empty_middleware, MiddlewareImp};
 use crate::middleware::Middleware;
 use crate::middleware::next::Next;
-use crate::model::{Model, Relation};
+use crate::model::{Model, ModelChange, Relation};
+use crate::model::change::{ChangeListener, WriteListener};
+use crate::model::permission::PermissionCheck;
+use crate::storage::FileStorage;
 use crate::namespace::Namespace;
 use crate::pipeline::item::Call;
 use crate::pipeline::item::item_impl::ItemImpl;
@@ -85,6 +90,13 @@ struct Inner {
     pub handler_middleware_stack: Arc<Mutex<Middleware>>,
     #[educe(Debug(ignore))]
     pub request_middleware_stack: Arc<Mutex<Middleware>>,
+    #[educe(Debug(ignore))]
+    pub model_change_listeners: Arc<Mutex<BTreeMap<Vec<String>, Vec<ChangeListener>>>>,
+    #[educe(Debug(ignore))]
+    pub model_write_listeners: Arc<Mutex<Vec<WriteListener>>>,
+    #[educe(Debug(ignore))]
+    pub permission_checks: Arc<Mutex<Vec<PermissionCheck>>>,
+    pub file_storages: Arc<Mutex<BTreeMap<String, Arc<dyn FileStorage>>>>,
     pub app_data: AppData,
 }
 
@@ -130,6 +142,10 @@ impl Builder {
                 handler_map: Arc::new(Mutex::new(handler::Map::new())),
                 handler_middleware_stack: Arc::new(Mutex::new(empty_middleware())),
                 request_middleware_stack: Arc::new(Mutex::new(empty_middleware())),
+                model_change_listeners: Arc::new(Mutex::new(Default::default())),
+                model_write_listeners: Arc::new(Mutex::new(vec![])),
+                permission_checks: Arc::new(Mutex::new(vec![])),
+                file_storages: Arc::new(Mutex::new(Default::default())),
                 app_data
             })
         }
@@ -1003,6 +1019,45 @@ impl Builder {
         *self.inner.model_opposite_relations_map.lock().unwrap() = map;
     }
 
+    /// Registers a callback which is called with each committed change of the model at `path`.
+    /// Changes made inside a transaction are delivered after the transaction is committed, and
+    /// dropped if it's aborted. Changes of models marked with `@publishChanges` are delivered
+    /// by `transaction::Ctx::deliver_outbox` instead.
+    pub fn on_model_change<F, Fut>(&self, path: Vec<&str>, callback: F) where
+        F: Fn(ModelChange) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static {
+        let listener: ChangeListener = Arc::new(move |change: ModelChange| -> BoxFuture<'static, Result<()>> {
+            Box::pin(callback(change))
+        });
+        let mut model_change_listeners = self.inner.model_change_listeners.lock().unwrap();
+        model_change_listeners.entry(path.iter().map(|s| s.to_string()).collect()).or_default().push(listener);
+    }
+
+    /// Registers a callback which is called with the path of each written model. Writes made
+    /// inside a transaction are reported after the transaction is committed, and dropped if
+    /// it's aborted.
+    pub fn on_model_write<F>(&self, callback: F) where F: Fn(&Vec<String>) + Send + Sync + 'static {
+        self.inner.model_write_listeners.lock().unwrap().push(Arc::new(callback));
+    }
+
+    /// Registers a check which runs when a model or a field is read or written. The check is
+    /// called with the data of the model or the field, the request if there is one, and the
+    /// path of the object. The returned future must not borrow the arguments.
+    pub fn define_permission_check<F, Fut>(&self, check: F) where
+        F: Fn(&BTreeMap<String, Value>, Option<request::Request>, &KeyPath) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static {
+        let check: PermissionCheck = Arc::new(move |data: &BTreeMap<String, Value>, request: Option<request::Request>, path: &KeyPath| -> BoxFuture<'static, Result<()>> {
+            Box::pin(check(data, request, path))
+        });
+        self.inner.permission_checks.lock().unwrap().push(check);
+    }
+
+    /// Registers a file storage backend. `@storage` fields use the backend named `default`
+    /// unless they name another one.
+    pub fn define_file_storage<T>(&self, name: &str, storage: T) where T: FileStorage + 'static {
+        self.inner.file_storages.lock().unwrap().insert(name.to_owned(), Arc::new(storage));
+    }
+
     pub fn set_handler_middlewares_block(&self, block: Option<middleware::Block>) {
         *self.inner.handler_middlewares_block.lock().unwrap() = block;
     }
@@ -1071,6 +1126,10 @@ impl Builder {
                 request_middleware_stack: self.inner.request_middleware_stack.lock().unwrap().clone(),
                 handler_map: self.inner.handler_map.lock().unwrap().clone(),
                 model_opposite_relations_map: self.inner.model_opposite_relations_map.lock().unwrap().clone(),
+                model_change_listeners: self.inner.model_change_listeners.lock().unwrap().clone(),
+                model_write_listeners: self.inner.model_write_listeners.lock().unwrap().clone(),
+                permission_checks: self.inner.permission_checks.lock().unwrap().clone(),
+                file_storages: self.inner.file_storages.lock().unwrap().clone(),
                 app_data: self.app_data().clone(),
             })
         }
@@ -11,6 +11,9 @@ use crate::connection::connection::Connection;
 use crate::handler;
 use crate::interface::Interface;
 use crate::model::relation::Relation;
+use crate::model::change::{ChangeListener, WriteListener};
+use crate::model::permission::PermissionCheck;
+use crate::storage::FileStorage;
 use crate::r#enum::Enum;
 use crate::r#struct::Struct;
 use crate::database::database::Database;
@@ -72,6 +75,14 @@ pub(super) struct Inner {
     #[educe(Debug(ignore))] #[serde(skip)]
     pub(super) handler_map: handler::Map,
     pub(super) model_opposite_relations_map: BTreeMap<Vec<String>, Vec<(Vec<String>, String)>>,
+    #[educe(Debug(ignore))] #[serde(skip)]
+    pub(super) model_change_listeners: BTreeMap<Vec<String>, Vec<ChangeListener>>,
+    #[educe(Debug(ignore))] #[serde(skip)]
+    pub(super) model_write_listeners: Vec<WriteListener>,
+    #[educe(Debug(ignore))] #[serde(skip)]
+    pub(super) permission_checks: Vec<PermissionCheck>,
+    #[serde(skip)]
+    pub(super) file_storages: BTreeMap<String, Arc<dyn FileStorage>>,
     #[serde(skip)]
     pub(super) app_data: AppData,
 }
@@ -532,6 +543,41 @@ impl Namespace {
         }).collect()
     }
 
+    /// Get the change listeners of the model at `path`, including the ones registered on child
+    /// namespaces
+    pub fn model_change_listeners(&self, path: &Vec<String>) -> Vec<ChangeListener> {
+        let mut result = self.inner.model_change_listeners.get(path).cloned().unwrap_or_default();
+        for n in self.inner.namespaces.values() {
+            result.extend(n.model_change_listeners(path));
+        }
+        result
+    }
+
+    /// Get the write listeners, including the ones registered on child namespaces
+    pub fn model_write_listeners(&self) -> Vec<WriteListener> {
+        let mut result = self.inner.model_write_listeners.clone();
+        for n in self.inner.namespaces.values() {
+            result.extend(n.model_write_listeners());
+        }
+        result
+    }
+
+    /// Get the permission checks, including the ones registered on child namespaces
+    pub fn permission_checks(&self) -> Vec<PermissionCheck> {
+        let mut result = self.inner.permission_checks.clone();
+        for n in self.inner.namespaces.values() {
+            result.extend(n.permission_checks());
+        }
+        result
+    }
+
+    pub fn file_storage(&self, name: &str) -> Option<Arc<dyn FileStorage>> {
+        if let Some(storage) = self.inner.file_storages.get(name) {
+            return Some(storage.clone());
+        }
+        self.inner.namespaces.values().find_map(|n| n.file_storage(name))
+    }
+
     pub fn collect_models<F>(&self, f: F) -> Vec<&Model> where F: Fn(&Model) -> bool {
         let filter = &f;
         self._collect_models(filter)
@@ -2,6 +2,7 @@ pub mod local_objects;
 pub mod local_values;
 pub mod request;
 pub mod extract;
+pub mod multipart;
 
 pub use request::Request;
 pub use hyper::Method;
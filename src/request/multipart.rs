@@ -0,0 +1,366 @@
+use std::path::PathBuf;
+use bytes::Bytes;
+use futures_util::stream::{BoxStream, StreamExt};
+use http_body_util::BodyExt;
+use key_path::{path, KeyPath};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use teo_result::{Error, Result};
+use tokio::io::AsyncWriteExt;
+use crate::coder::json_to_teon::json_to_teon;
+use crate::handler::Handler;
+use crate::namespace::Namespace;
+use crate::request::Request;
+use crate::value::Value;
+
+/// Limits applied to the files uploaded to a handler with form input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadLimits {
+    /// The maximum size of a single file in bytes
+    pub max_file_size: u64,
+    /// The maximum size of a single text field in bytes
+    pub max_field_size: u64,
+    /// The maximum size of all parts of the request in bytes
+    pub max_total_size: u64,
+    /// The allowed content types of files. Entries like `image/*` match any subtype.
+    pub content_types: Option<Vec<String>>,
+}
+
+impl Default for UploadLimits {
+
+    fn default() -> Self {
+        Self {
+            max_file_size: 10 * 1024 * 1024,
+            max_field_size: 1024 * 1024,
+            max_total_size: 50 * 1024 * 1024,
+            content_types: None,
+        }
+    }
+}
+
+/// The largest array index accepted in form field names like `tags[0]`. Without a bound a
+/// single field name could make the parser allocate an arbitrarily large array.
+const MAX_ARRAY_INDEX: usize = 1000;
+
+impl UploadLimits {
+
+    fn allows_content_type(&self, content_type: Option<&str>) -> bool {
+        let Some(content_types) = &self.content_types else {
+            return true;
+        };
+        let Some(content_type) = content_type else {
+            return false;
+        };
+        content_types.iter().any(|allowed| match allowed.strip_suffix("/*") {
+            Some(prefix) => content_type.split("/").next() == Some(prefix),
+            None => allowed.as_str() == content_type,
+        })
+    }
+}
+
+/// The temporary files of a request. The files are removed when the request is dropped, which
+/// happens after the response is sent. Handlers should move or copy files they want to keep.
+#[derive(Debug, Default)]
+struct TempFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl Drop for TempFiles {
+
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+const TEMP_FILES_KEY: &str = "multipart:tempFiles";
+
+/// The directory where uploaded files are stored until the request is dropped.
+pub fn upload_temp_dir() -> PathBuf {
+    std::env::temp_dir().join("teo-uploads")
+}
+
+/// Parses a `multipart/form-data` request body into the input value of `handler`.
+///
+/// Files are streamed into the upload temp directory and represented as `File` values. Text
+/// fields are strings, unless the part has the `application/json` content type, then it's
+/// decoded as JSON. Field names like `user[name]` and `tags[0]` build nested values.
+pub async fn parse_multipart(request: &Request, handler: &Handler, namespace: &Namespace) -> Result<Value> {
+    let Some(content_type) = request.content_type()? else {
+        return Err(Error::invalid_request_message("missing content type of multipart request"));
+    };
+    let boundary = multer::parse_boundary(&content_type).map_err(|err| Error::invalid_request_message(format!("invalid multipart content type: {}", err)))?;
+    let stream: BodyStream = if let Some(incoming) = request.take_incoming() {
+        incoming.into_data_stream().map(|r| r.map_err(|e| e.into())).boxed()
+    } else if let Some(incoming) = request.take_incoming_bytes_for_test() {
+        incoming.into_data_stream().map(|r| r.map_err(|e| e.into())).boxed()
+    } else {
+        return Err(Error::internal_server_error_message("request body is taken"));
+    };
+    let mut temp_files = TempFiles::default();
+    let result = decode_multipart(stream, boundary, handler.upload_limits(), &mut temp_files).await;
+    request.local_objects().insert(TEMP_FILES_KEY, temp_files);
+    json_to_teon(&result?, &path![], handler.input_type(), namespace)
+}
+
+type BodyStream = BoxStream<'static, std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync>>>;
+
+/// Decodes the parts of a multipart body into a JSON value. Saved files are recorded in
+/// `temp_files` even if a later part fails.
+async fn decode_multipart(stream: BodyStream, boundary: String, limits: &UploadLimits, temp_files: &mut TempFiles) -> Result<JsonValue> {
+    let mut multipart = multer::Multipart::new(stream, boundary);
+    let mut json = JsonValue::Object(JsonMap::new());
+    let mut total_size: u64 = 0;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => Err(Error::invalid_request_message(format!("invalid multipart body: {}", err)))?,
+        };
+        let name = field.name().unwrap_or("").to_owned();
+        let field_path = &path![] + name.as_str();
+        let field_content_type = field.content_type().map(|m| m.essence_str().to_owned());
+        let value = if let Some(filename) = field.file_name().map(|f| f.to_owned()) {
+            if !limits.allows_content_type(field_content_type.as_deref()) {
+                return Err(Error::invalid_request_pathed(field_path, "file content type is not allowed"));
+            }
+            let filepath = save_file(field, &field_path, limits, &mut total_size, temp_files).await?;
+            let filename_ext = filename.rsplit_once(".").map(|(_, ext)| JsonValue::String(ext.to_owned())).unwrap_or(JsonValue::Null);
+            serde_json::json!({
+                "filepath": filepath,
+                "filename": filename,
+                "contentType": field_content_type,
+                "filenameExt": filename_ext,
+            })
+        } else {
+            let bytes = read_field(field, &field_path, limits, &mut total_size).await?;
+            let text = String::from_utf8_lossy(&bytes).into_owned();
+            if field_content_type.as_deref() == Some(mime::APPLICATION_JSON.essence_str()) {
+                match serde_json::from_str(&text) {
+                    Ok(value) => value,
+                    Err(_) => return Err(Error::invalid_request_pathed(field_path, "invalid json")),
+                }
+            } else {
+                JsonValue::String(text)
+            }
+        };
+        insert_field(&mut json, &name, value, &field_path)?;
+    }
+    Ok(json)
+}
+
+async fn save_file(mut field: multer::Field<'static>, path: &KeyPath, limits: &UploadLimits, total_size: &mut u64, temp_files: &mut TempFiles) -> Result<String> {
+    let dir = upload_temp_dir();
+    tokio::fs::create_dir_all(&dir).await.map_err(io_error)?;
+    let filepath = dir.join(uuid::Uuid::new_v4().to_string());
+    let mut file = tokio::fs::File::create(&filepath).await.map_err(io_error)?;
+    temp_files.paths.push(filepath.clone());
+    let mut file_size: u64 = 0;
+    loop {
+        let chunk = match field.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(err) => Err(Error::invalid_request_pathed(path.clone(), format!("invalid multipart field: {}", err)))?,
+        };
+        file_size += chunk.len() as u64;
+        *total_size += chunk.len() as u64;
+        if file_size > limits.max_file_size {
+            return Err(payload_too_large(path, "file is too large"));
+        }
+        check_total_size(*total_size, limits)?;
+        file.write_all(&chunk).await.map_err(io_error)?;
+    }
+    file.flush().await.map_err(io_error)?;
+    Ok(filepath.to_string_lossy().into_owned())
+}
+
+async fn read_field(mut field: multer::Field<'static>, path: &KeyPath, limits: &UploadLimits, total_size: &mut u64) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    loop {
+        let chunk = match field.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(err) => Err(Error::invalid_request_pathed(path.clone(), format!("invalid multipart field: {}", err)))?,
+        };
+        *total_size += chunk.len() as u64;
+        if (bytes.len() + chunk.len()) as u64 > limits.max_field_size {
+            return Err(payload_too_large(path, "field is too large"));
+        }
+        check_total_size(*total_size, limits)?;
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+fn check_total_size(total_size: u64, limits: &UploadLimits) -> Result<()> {
+    if total_size > limits.max_total_size {
+        Err(Error::new_with_code("request body is too large", 413))
+    } else {
+        Ok(())
+    }
+}
+
+fn payload_too_large(path: &KeyPath, message: &str) -> Error {
+    let mut error = Error::invalid_request_pathed(path.clone(), message);
+    error.code = 413;
+    error
+}
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::internal_server_error_message(&format!("cannot save uploaded file: {}", error))
+}
+
+/// Inserts `value` at the position described by a form field name like `user[tags][0]`. Fields
+/// sharing a plain name are collected into an array.
+fn insert_field(json: &mut JsonValue, name: &str, value: JsonValue, path: &KeyPath) -> Result<()> {
+    let mut keys: Vec<&str> = vec![];
+    let (first, mut rest) = match name.find("[") {
+        Some(index) => (&name[..index], &name[index..]),
+        None => (name, ""),
+    };
+    keys.push(first);
+    while !rest.is_empty() {
+        let Some(end) = rest.find("]").filter(|_| rest.starts_with("[")) else {
+            return Err(Error::invalid_request_pathed(path.clone(), "invalid form field name"));
+        };
+        keys.push(&rest[1..end]);
+        rest = &rest[end + 1..];
+    }
+    let mut current = json;
+    for (index, key) in keys.iter().enumerate() {
+        let is_last = index == keys.len() - 1;
+        current = if let Ok(item_index) = key.parse::<usize>() {
+            if item_index > MAX_ARRAY_INDEX {
+                return Err(Error::invalid_request_pathed(path.clone(), "form field array index is too large"));
+            }
+            if current.is_null() {
+                *current = JsonValue::Array(vec![]);
+            }
+            let Some(array) = current.as_array_mut() else {
+                return Err(Error::invalid_request_pathed(path.clone(), "conflicting form field names"));
+            };
+            if array.len() <= item_index {
+                array.resize(item_index + 1, JsonValue::Null);
+            }
+            &mut array[item_index]
+        } else {
+            if current.is_null() {
+                *current = JsonValue::Object(JsonMap::new());
+            }
+            let Some(object) = current.as_object_mut() else {
+                return Err(Error::invalid_request_pathed(path.clone(), "conflicting form field names"));
+            };
+            if is_last && keys.len() == 1 {
+                match object.get_mut(*key) {
+                    Some(JsonValue::Array(items)) => items.push(value),
+                    Some(existing) => *existing = JsonValue::Array(vec![existing.take(), value]),
+                    None => { object.insert(key.to_string(), value); }
+                }
+                return Ok(());
+            }
+            object.entry(key.to_string()).or_insert(JsonValue::Null)
+        };
+    }
+    *current = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    const BOUNDARY: &str = "X-BOUNDARY";
+
+    /// A multipart body of `(name, filename, content type, content)` parts.
+    fn body(parts: Vec<(&str, Option<&str>, Option<&str>, &str)>) -> BodyStream {
+        let mut body = String::new();
+        for (name, filename, content_type, content) in parts {
+            body.push_str(&format!("--{}\r\n", BOUNDARY));
+            match filename {
+                Some(filename) => body.push_str(&format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", name, filename)),
+                None => body.push_str(&format!("Content-Disposition: form-data; name=\"{}\"\r\n", name)),
+            }
+            if let Some(content_type) = content_type {
+                body.push_str(&format!("Content-Type: {}\r\n", content_type));
+            }
+            body.push_str("\r\n");
+            body.push_str(content);
+            body.push_str("\r\n");
+        }
+        body.push_str(&format!("--{}--\r\n", BOUNDARY));
+        futures_util::stream::once(async move { Ok(Bytes::from(body)) }).boxed()
+    }
+
+    async fn decode(parts: Vec<(&str, Option<&str>, Option<&str>, &str)>, limits: &UploadLimits, temp_files: &mut TempFiles) -> Result<JsonValue> {
+        decode_multipart(body(parts), BOUNDARY.to_owned(), limits, temp_files).await
+    }
+
+    #[tokio::test]
+    async fn fields_build_nested_values() {
+        let mut temp_files = TempFiles::default();
+        let json = decode(vec![
+            ("user[name]", None, None, "Ann"),
+            ("user[tags][1]", None, None, "b"),
+            ("user[tags][0]", None, None, "a"),
+            ("meta", None, Some("application/json"), "{\"age\": 3}"),
+            ("ids", None, None, "1"),
+            ("ids", None, None, "2"),
+        ], &UploadLimits::default(), &mut temp_files).await.unwrap();
+        assert_eq!(json, json!({
+            "user": { "name": "Ann", "tags": ["a", "b"] },
+            "meta": { "age": 3 },
+            "ids": ["1", "2"],
+        }));
+    }
+
+    #[tokio::test]
+    async fn files_are_saved_into_the_upload_temp_dir() {
+        let mut temp_files = TempFiles::default();
+        let json = decode(vec![
+            ("avatar", Some("me.png"), Some("image/png"), "png bytes"),
+        ], &UploadLimits::default(), &mut temp_files).await.unwrap();
+        let filepath = PathBuf::from(json["avatar"]["filepath"].as_str().unwrap());
+        assert!(filepath.starts_with(upload_temp_dir()));
+        assert_eq!(std::fs::read_to_string(&filepath).unwrap(), "png bytes");
+        assert_eq!(json["avatar"]["filename"], json!("me.png"));
+        assert_eq!(json["avatar"]["contentType"], json!("image/png"));
+        assert_eq!(json["avatar"]["filenameExt"], json!("png"));
+        drop(temp_files);
+        assert!(!filepath.exists());
+    }
+
+    #[tokio::test]
+    async fn files_over_the_limits_are_rejected() {
+        let limits = UploadLimits { max_file_size: 4, ..Default::default() };
+        let mut temp_files = TempFiles::default();
+        let error = decode(vec![("avatar", Some("me.png"), Some("image/png"), "png bytes")], &limits, &mut temp_files).await.unwrap_err();
+        assert_eq!(error.code, 413);
+        // the partially written file is still removed
+        assert_eq!(temp_files.paths.len(), 1);
+        let limits = UploadLimits { max_field_size: 2, ..Default::default() };
+        let error = decode(vec![("name", None, None, "Ann")], &limits, &mut TempFiles::default()).await.unwrap_err();
+        assert_eq!(error.code, 413);
+        let limits = UploadLimits { max_total_size: 8, ..Default::default() };
+        let error = decode(vec![("a", None, None, "12345"), ("b", None, None, "12345")], &limits, &mut TempFiles::default()).await.unwrap_err();
+        assert_eq!(error.code, 413);
+    }
+
+    #[tokio::test]
+    async fn file_content_types_are_checked() {
+        let limits = UploadLimits { content_types: Some(vec!["image/*".to_owned()]), ..Default::default() };
+        let mut temp_files = TempFiles::default();
+        assert!(decode(vec![("avatar", Some("me.png"), Some("image/png"), "png")], &limits, &mut temp_files).await.is_ok());
+        assert!(decode(vec![("avatar", Some("me.txt"), Some("text/plain"), "text")], &limits, &mut temp_files).await.is_err());
+        assert!(decode(vec![("avatar", Some("me"), None, "bytes")], &limits, &mut temp_files).await.is_err());
+    }
+
+    #[test]
+    fn invalid_field_names_are_rejected() {
+        let mut json = JsonValue::Object(JsonMap::new());
+        assert!(insert_field(&mut json, "tags[1001]", json!("a"), &path![]).is_err());
+        assert!(insert_field(&mut json, "user[name", json!("a"), &path![]).is_err());
+        insert_field(&mut json, "user[name]", json!("Ann"), &path![]).unwrap();
+        assert!(insert_field(&mut json, "user[0]", json!("a"), &path![]).is_err());
+    }
+}
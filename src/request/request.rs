@@ -1,5 +1,6 @@
 use std::cell::RefCell;
 use std::fmt::{Debug, Formatter};
+use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
 use hyper::{self, Method, Uri, Version};
@@ -33,6 +34,7 @@ struct Inner {
     transaction_ctx: transaction::Ctx,
     cookies: HistoryBox<Cookies>,
     handler_match: HistoryBox<HandlerMatch>,
+    remote_addr: HistoryBox<SocketAddr>,
     body_value: HistoryBox<Value>,
     local_values: LocalValues,
     local_objects: LocalObjects,
@@ -44,6 +46,7 @@ impl Request {
 
     pub fn new(hyper_request: hyper::Request<Incoming>, transaction_ctx: transaction::Ctx) -> Self {
         let (parts, incoming) = hyper_request.into_parts();
+        transaction_ctx.enforce_policies(None);
         Self {
             inner: Arc::new(Inner {
                 method: HistoryBox::new_with(parts.method),
@@ -55,6 +58,7 @@ impl Request {
                 transaction_ctx,
                 cookies: HistoryBox::new(),
                 handler_match: HistoryBox::new(),
+                remote_addr: HistoryBox::new(),
                 body_value: HistoryBox::new(),
                 local_values: LocalValues::new(),
                 local_objects: LocalObjects::new(),
@@ -64,6 +68,7 @@ impl Request {
 
     pub fn new_for_test(hyper_request: hyper::Request<Full<Bytes>>, transaction_ctx: transaction::Ctx) -> Self {
         let (parts, incoming) = hyper_request.into_parts();
+        transaction_ctx.enforce_policies(None);
         Self {
             inner: Arc::new(Inner {
                 method: HistoryBox::new_with(parts.method),
@@ -75,6 +80,7 @@ impl Request {
                 transaction_ctx,
                 cookies: HistoryBox::new(),
                 handler_match: HistoryBox::new(),
+                remote_addr: HistoryBox::new_with(SocketAddr::from(([127, 0, 0, 1], 0))),
                 body_value: HistoryBox::new(),
                 local_values: LocalValues::new(),
                 local_objects: LocalObjects::new(),
@@ -186,6 +192,15 @@ impl Request {
         self.inner.handler_match.set(handler_match);
     }
 
+    /// The address of the connected peer, this is set by the server.
+    pub fn remote_addr(&self) -> Option<&SocketAddr> {
+        self.inner.remote_addr.get()
+    }
+
+    pub fn set_remote_addr(&self, remote_addr: SocketAddr) {
+        self.inner.remote_addr.set(remote_addr);
+    }
+
     pub fn captures(&self) -> Result<&IndexMap<String, String>> {
         Ok(self.handler_match()?.captures())
     }
@@ -1,5 +1,9 @@
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use bytes::Bytes;
+use futures::Stream;
+use futures::stream::BoxStream;
+use teo_result::Result;
 
 use crate::value::Value;
 
@@ -34,6 +38,24 @@ impl Body {
         }
     }
 
+    pub fn bytes(content: Bytes) -> Self {
+        Self {
+            inner: Arc::new(BodyInner::Bytes(content))
+        }
+    }
+
+    pub fn stream<S>(content: S) -> Self where S: Stream<Item = Result<Bytes>> + Send + 'static {
+        Self {
+            inner: Arc::new(BodyInner::Stream(BodyStream::new(content, None)))
+        }
+    }
+
+    pub fn stream_with_length<S>(content: S, content_length: u64) -> Self where S: Stream<Item = Result<Bytes>> + Send + 'static {
+        Self {
+            inner: Arc::new(BodyInner::Stream(BodyStream::new(content, Some(content_length))))
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         match self.inner.as_ref() {
             BodyInner::Empty => true,
@@ -69,6 +91,43 @@ impl Body {
         }
     }
 
+    pub fn is_bytes(&self) -> bool {
+        match self.inner.as_ref() {
+            BodyInner::Bytes(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&Bytes> {
+        match self.inner.as_ref() {
+            BodyInner::Bytes(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn is_stream(&self) -> bool {
+        match self.inner.as_ref() {
+            BodyInner::Stream(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Takes the stream out of the body. A stream can only be consumed once, so this returns
+    /// `None` when it has been taken before.
+    pub fn take_stream(&self) -> Option<BoxStream<'static, Result<Bytes>>> {
+        match self.inner.as_ref() {
+            BodyInner::Stream(v) => v.stream.lock().unwrap().take(),
+            _ => None,
+        }
+    }
+
+    pub fn stream_content_length(&self) -> Option<u64> {
+        match self.inner.as_ref() {
+            BodyInner::Stream(v) => v.content_length,
+            _ => None,
+        }
+    }
+
     pub fn is_teon(&self) -> bool {
         match self.inner.as_ref() {
             BodyInner::Teon(_) => true,
@@ -89,4 +148,21 @@ pub enum BodyInner {
     String(String),
     File(PathBuf),
     Teon(Value),
+    Bytes(Bytes),
+    Stream(BodyStream),
+}
+
+pub struct BodyStream {
+    stream: Mutex<Option<BoxStream<'static, Result<Bytes>>>>,
+    content_length: Option<u64>,
+}
+
+impl BodyStream {
+
+    fn new<S>(content: S, content_length: Option<u64>) -> Self where S: Stream<Item = Result<Bytes>> + Send + 'static {
+        Self {
+            stream: Mutex::new(Some(Box::pin(content))),
+            content_length,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,40 @@
+use indexmap::IndexMap;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use teo_result::Result;
+use crate::value::Value;
+
+/// Sorts the keys of dictionaries, so that values which differ only in key order are equal.
+pub fn normalized_value(value: &Value) -> Value {
+    match value {
+        Value::Dictionary(map) => {
+            let mut map: IndexMap<String, Value> = map.iter().map(|(k, v)| (k.clone(), normalized_value(v))).collect();
+            map.sort_keys();
+            Value::Dictionary(map)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(normalized_value).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Computes a strong ETag of a TEON value. The ETag doesn't depend on the key order of
+/// dictionaries.
+pub fn etag_for_value(value: &Value) -> Result<String> {
+    let json = JsonValue::try_from(&normalized_value(value))?;
+    let digest = Sha256::digest(json.to_string().as_bytes());
+    Ok(format!("\"{}\"", hex::encode(&digest[..16])))
+}
+
+/// Checks whether an `If-Match` or `If-None-Match` header value matches `etag`. Weak ETags
+/// match only if `weak` comparison is requested, as `If-None-Match` does.
+pub fn etag_matches(header: &str, etag: &str, weak: bool) -> bool {
+    header.split(",").map(|item| item.trim()).any(|item| {
+        if item == "*" {
+            return true;
+        }
+        match item.strip_prefix("W/") {
+            Some(item) => weak && item == etag.trim_start_matches("W/"),
+            None => item == etag || (weak && item == etag.trim_start_matches("W/")),
+        }
+    })
+}
@@ -1,6 +1,9 @@
 pub mod response;
 pub mod body;
 pub mod error;
+pub mod sse;
+pub mod etag;
 
 pub use response::Response;
 pub use body::Body;
+pub use sse::SseEvent;
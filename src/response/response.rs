@@ -1,13 +1,16 @@
 use std::fmt::{Debug, Formatter};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use hyper::header::CONTENT_TYPE;
+use futures::{Stream, StreamExt};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG};
 use crate::value::Value;
 use crate::teon;
 use teo_result::{Result, Error};
 use crate::cookies::Cookies;
 use crate::headers::headers::Headers;
 use crate::response::body::Body;
+use crate::response::etag::etag_for_value;
+use crate::response::sse::SseEvent;
 
 #[derive(Clone)]
 pub struct Response {
@@ -79,6 +82,17 @@ impl Response {
         }
     }
 
+    /// A server-sent events response. The connection is kept open until the stream ends.
+    pub fn sse<S>(events: S) -> Result<Response> where S: Stream<Item = Result<SseEvent>> + Send + 'static {
+        let res = Self::empty();
+        res.headers().insert(CONTENT_TYPE.as_str(), "text/event-stream")?;
+        res.headers().insert(CACHE_CONTROL.as_str(), "no-cache")?;
+        // prevents reverse proxies from buffering the events
+        res.headers().insert("X-Accel-Buffering", "no")?;
+        res.set_body(Body::stream(events.map(|event| event.map(|e| e.to_bytes()))));
+        Ok(res)
+    }
+
     pub fn redirect(path: impl Into<String>) -> Result<Response> {
         let res = Self::empty();
         res.set_code(301);
@@ -86,6 +100,31 @@ impl Response {
         Ok(res)
     }
 
+    /// A `304 Not Modified` response carrying `etag`.
+    pub fn not_modified(etag: &str) -> Result<Response> {
+        let res = Self::empty();
+        res.set_code(304);
+        res.headers().insert(ETAG.as_str(), etag)?;
+        Ok(res)
+    }
+
+    /// Computes the ETag of the TEON body. Returns `None` if the body is not TEON.
+    pub fn teon_etag(&self) -> Result<Option<String>> {
+        match self.body().as_teon() {
+            Some(value) => Ok(Some(etag_for_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the `ETag` header from the TEON body, and returns the ETag.
+    pub fn set_teon_etag(&self) -> Result<Option<String>> {
+        let etag = self.teon_etag()?;
+        if let Some(etag) = &etag {
+            self.headers().insert(ETAG.as_str(), etag.as_str())?;
+        }
+        Ok(etag)
+    }
+
     pub fn set_code(&self, code: u16) {
         self.inner.lock().unwrap().code = code;
     }
@@ -0,0 +1,59 @@
+use std::time::Duration;
+use bytes::Bytes;
+
+/// An event of a server-sent events response.
+#[derive(Debug, Clone, Default)]
+pub struct SseEvent {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<Duration>,
+}
+
+impl SseEvent {
+
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
+        let mut result = String::new();
+        if let Some(id) = &self.id {
+            result.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(event) = &self.event {
+            result.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(retry) = &self.retry {
+            result.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        // each line of a multiline payload is sent in its own data field
+        for line in self.data.split('\n') {
+            result.push_str(&format!("data: {}\n", line.trim_end_matches('\r')));
+        }
+        result.push('\n');
+        Bytes::from(result)
+    }
+}
+
+fn single_line(value: &str) -> String {
+    value.replace(|c| c == '\r' || c == '\n', "")
+}
@@ -60,7 +60,7 @@ pub fn load_handler(main_namespace: &namespace::Builder, schema: &Schema, handle
             handler_builder.method(),
             handler_builder.url().as_ref().map(|u| u.as_str()),
             handler_builder.ignore_prefix(),
-        );
+        )?;
     }
     main_namespace.replace_handler_at_path(&handler_declaration.str_path(), handler_builder.build(), handler_declaration.inside_group)?;
     Ok(())
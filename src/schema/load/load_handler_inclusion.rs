@@ -63,22 +63,7 @@ pub fn load_handler_inclusion(main_namespace: &namespace::Builder, schema: &Sche
             handler_builder.method(),
             handler_builder.url().as_ref().map(AsRef::as_ref),
             handler_builder.ignore_prefix(),
-        );
-    }
-    if (handler_builder.method() != Method::POST) || handler_builder.url().is_some() {
-        let parent_string_path = handler_inclusion.parent_string_path();
-        main_namespace.handler_map().lock().unwrap().add_record(
-            &handler_inclusion.namespace_str_path(),
-            if handler_inclusion.namespace_skip() == 2 {
-                Some(parent_string_path.last().unwrap().as_str())
-            } else {
-                None
-            },
-            handler_inclusion.name(),
-            handler_builder.method(),
-            handler_builder.url().as_ref().map(|u| u.as_str()),
-            handler_builder.ignore_prefix(),
-        );
+        )?;
     }
     main_namespace.replace_handler_at_path(&handler_inclusion.str_path(), handler_builder.build(), true)?;
     Ok(())
@@ -0,0 +1,65 @@
+use indexmap::IndexMap;
+use key_path::path;
+use serde_json::Value as JsonValue;
+use teo_result::Error;
+use crate::model;
+use crate::model::object::audit::audit_store;
+use crate::namespace;
+use crate::request::Request;
+use crate::response::Response;
+use crate::teon;
+use crate::value::Value;
+
+pub(super) fn load_audit_library(std_namespace: &namespace::Builder) {
+
+    std_namespace.define_model_decorator("audit", |arguments, model| {
+        let store: Option<String> = arguments.get_optional("store")?;
+        model.insert_data_entry("audit:model".to_owned(), store.map_or(Value::Null, |s| s.into()));
+        Ok(())
+    });
+
+    std_namespace.define_model_decorator("auditStore", |_, model| {
+        model.insert_data_entry("audit:store".to_owned(), true.into());
+        Ok(())
+    });
+
+    std_namespace.define_handler_template("auditHistory", |request: Request| async move {
+        let transaction_ctx = request.transaction_ctx();
+        let model = transaction_ctx.namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
+        let Some(store) = audit_store(transaction_ctx.namespace(), &model) else {
+            return Err(Error::internal_server_error_message(&format!("model is not audited: {}", model.path().join("."))));
+        };
+        // the record is identified by its primary key values only
+        let mut identifier: IndexMap<String, Value> = IndexMap::new();
+        for item in model.primary_index().unwrap().items() {
+            let Some(value) = request.body_value()?.get(item.field.as_str()) else {
+                return Err(Error::invalid_request_pathed(path![item.field.as_str()], "value is required"));
+            };
+            identifier.insert(item.field.clone(), value.clone());
+        }
+        // the history is readable by those who can read the record
+        let record: Option<model::Object> = transaction_ctx.find_unique(&model, &teon!({
+            "where": Value::Dictionary(identifier.clone()),
+            "withDeleted": true,
+        }), Some(request.clone()), path![]).await?;
+        let Some(record) = record else {
+            return Err(Error::not_found_pathed(path![], "record is not found"));
+        };
+        if record.to_teon_internal(&path![]).await.is_err() {
+            return Err(Error::unauthorized_pathed(path![], "not allowed to read"));
+        }
+        let identifier: JsonValue = Value::Dictionary(identifier).try_into()?;
+        let entries: Vec<model::Object> = transaction_ctx.find_many(store, &teon!({
+            "where": {
+                "model": model.path().join("."),
+                "identifier": identifier.to_string(),
+            },
+            "orderBy": { "createdAt": "asc" },
+        }), Some(request.clone()), path![]).await?;
+        let mut result: Vec<Value> = vec![];
+        for (index, entry) in entries.iter().enumerate() {
+            result.push(entry.to_teon_internal(&path!["data", index]).await?);
+        }
+        Ok(Response::data(Value::Array(result)))
+    });
+}
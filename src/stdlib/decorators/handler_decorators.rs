@@ -1,5 +1,6 @@
 use hyper::Method;
 use crate::namespace;
+use crate::request::multipart::UploadLimits;
 
 pub(in crate::stdlib) fn load_handler_decorators(namespace: &namespace::Builder) {
 
@@ -16,4 +17,19 @@ pub(in crate::stdlib) fn load_handler_decorators(namespace: &namespace::Builder)
         handler.set_interface(interface);
         Ok(())
     });
-}
\ No newline at end of file
+
+    namespace.define_handler_decorator("upload", |arguments, handler| {
+        let max_file_size: Option<usize> = arguments.get_optional("maxFileSize")?;
+        let max_field_size: Option<usize> = arguments.get_optional("maxFieldSize")?;
+        let max_total_size: Option<usize> = arguments.get_optional("maxTotalSize")?;
+        let content_types: Option<Vec<String>> = arguments.get_optional("contentTypes")?;
+        let defaults = UploadLimits::default();
+        handler.set_upload_limits(UploadLimits {
+            max_file_size: max_file_size.map_or(defaults.max_file_size, |s| s as u64),
+            max_field_size: max_field_size.map_or(defaults.max_field_size, |s| s as u64),
+            max_total_size: max_total_size.map_or(defaults.max_total_size, |s| s as u64),
+            content_types,
+        });
+        Ok(())
+    });
+}
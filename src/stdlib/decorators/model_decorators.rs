@@ -4,6 +4,7 @@ use teo_result::Result;
 use crate::action::Action;
 use crate::namespace;
 use crate::pipeline::pipeline::Pipeline;
+use crate::value::interface_enum_variant::InterfaceEnumVariant;
 use crate::stdlib::decorators::model_indexable_decorators::{model_id_decorator, model_index_decorator, model_unique_decorator};
 
 pub(in crate::stdlib) fn load_model_decorators(namespace_builder: &namespace::Builder) {
@@ -78,6 +79,41 @@ pub(in crate::stdlib) fn load_model_decorators(namespace_builder: &namespace::Bu
         Ok(())
     });
 
+    namespace_builder.define_model_decorator("softDelete", |arguments, model| {
+        let field: Option<InterfaceEnumVariant> = arguments.get_optional("field")?;
+        model.set_soft_delete(Some(field.map_or("deletedAt".to_owned(), |f| f.value)));
+        Ok(())
+    });
+
+    namespace_builder.define_model_decorator("publishChanges", |arguments, model| {
+        let outbox: Option<String> = arguments.get_optional("outbox")?;
+        model.insert_data_entry("outbox:publish".to_owned(), outbox.map_or(Value::Null, |s| s.into()));
+        Ok(())
+    });
+
+    namespace_builder.define_model_decorator("outbox", |_, model| {
+        model.insert_data_entry("outbox:store".to_owned(), true.into());
+        Ok(())
+    });
+
+    namespace_builder.define_model_decorator("tenant", |arguments, model| {
+        let field: InterfaceEnumVariant = arguments.get("field")?;
+        model.insert_data_entry("tenant:field".to_owned(), field.value.into());
+        Ok(())
+    });
+
+    namespace_builder.define_model_decorator("policy", |arguments, model| {
+        let read: Option<Value> = arguments.get_optional("read")?;
+        let write: Option<Value> = arguments.get_optional("write")?;
+        if let Some(read) = read {
+            model.insert_data_entry("policy:read".to_owned(), read);
+        }
+        if let Some(write) = write {
+            model.insert_data_entry("policy:write".to_owned(), write);
+        }
+        Ok(())
+    });
+
     namespace_builder.define_model_decorator("canRead", |arguments, model| {
         let pipeline: Pipeline = arguments.get("pipeline")?;
         model.set_can_read(pipeline);
@@ -119,6 +155,11 @@ pub(in crate::stdlib) fn load_model_decorators(namespace_builder: &namespace::Bu
         Ok(())
     });
 
+    namespace_builder.define_model_decorator("export", |arguments, model| {
+        model.insert_data_entry("export".to_owned(), true.into());
+        Ok(())
+    });
+
     namespace_builder.define_model_decorator("generateClient", |arguments, model| {
         let gen: bool = arguments.get("generate")?;
         model.set_generate_client(gen);
@@ -1,3 +1,4 @@
+use teo_result::Error;
 use crate::value::Value;
 use crate::database::r#type::DatabaseType;
 use crate::model::field::Migration;
@@ -155,6 +156,35 @@ pub(in crate::stdlib) fn load_model_field_decorators(namespace_builder: &namespa
         Ok(())
     });
 
+    namespace_builder.define_model_field_decorator("version", |arguments, field| {
+        let default = if field.r#type().is_int64() {
+            Value::Int64(0)
+        } else if field.r#type().is_int() {
+            Value::Int(0)
+        } else {
+            Err(Error::new(format!("version field should be an integer: {}", field.name())))?
+        };
+        if field.default().is_none() {
+            field.set_default(Some(default));
+            field.set_input_omissible(true);
+        }
+        field.insert_data_entry("version".to_owned(), true.into());
+        Ok(())
+    });
+
+    namespace_builder.define_model_field_decorator("storage", |arguments, field| {
+        if !field.r#type().unwrap_optional().is_string() {
+            Err(Error::new(format!("storage field should be a string: {}", field.name())))?
+        }
+        let name: Option<String> = arguments.get_optional("name")?;
+        let prefix: Option<String> = arguments.get_optional("prefix")?;
+        field.insert_data_entry("storage".to_owned(), name.unwrap_or("default".to_owned()).into());
+        if let Some(prefix) = prefix {
+            field.insert_data_entry("storage:prefix".to_owned(), prefix.into());
+        }
+        Ok(())
+    });
+
     namespace_builder.define_model_field_decorator("foreignKey", |arguments, field| {
         field.set_foreign_key(true);
         field.set_input_omissible(true);
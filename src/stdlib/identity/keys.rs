@@ -0,0 +1,141 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use jsonwebtoken::{Algorithm, decode, decode_header, DecodingKey, encode, EncodingKey, Header, Validation};
+use jsonwebtoken::errors::ErrorKind;
+use teo_result::{Error, Result};
+use crate::app::data::AppData;
+use crate::model::Model;
+use crate::stdlib::identity::JwtClaims;
+use crate::value::Value;
+
+/// The keys used to sign and verify the tokens of an identity model.
+///
+/// A model declares either `@identity.jwtSecret` for HMAC signing with a shared secret, or
+/// `@identity.jwtKeys` for asymmetric signing. With asymmetric keys, tokens are signed with
+/// the first key which has a private key, and verified with the key matching the `kid` header.
+pub enum JwtKeys {
+    Secret(String),
+    Asymmetric {
+        algorithm: Algorithm,
+        keys: Vec<JwtKey>,
+    },
+}
+
+pub struct JwtKey {
+    kid: String,
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey,
+}
+
+fn extension_key(path: &Vec<String>) -> String {
+    format!("identity:jwtKeys:{}", path.join("."))
+}
+
+impl JwtKeys {
+
+    /// Keeps the keys of the model at `path` in the app data. The keys are parsed once when
+    /// `@identity.jwtSecret` or `@identity.jwtKeys` is applied.
+    pub fn install(self, app_data: &AppData, path: &Vec<String>) {
+        app_data.set_extension(extension_key(path), self);
+    }
+
+    pub fn from_model(app_data: &AppData, model: &Model) -> Result<Arc<Self>> {
+        match app_data.extension::<JwtKeys>(&extension_key(model.path())) {
+            Some(keys) => Ok(keys),
+            None => Err(Error::internal_server_error_message("missing @identity.jwtSecret or @identity.jwtKeys")),
+        }
+    }
+
+    pub fn encode(&self, claims: &JwtClaims) -> Result<String> {
+        match self {
+            JwtKeys::Secret(secret) => encode(&Header::default(), claims, &EncodingKey::from_secret(secret.as_ref())).map_err(|e| Error::internal_server_error_message(&format!("cannot sign jwt token: {}", e))),
+            JwtKeys::Asymmetric { algorithm, keys } => {
+                let Some((kid, encoding_key)) = keys.iter().find_map(|k| k.encoding_key.as_ref().map(|e| (&k.kid, e))) else {
+                    return Err(Error::internal_server_error_message("no jwt key with a private key is defined"));
+                };
+                let mut header = Header::new(*algorithm);
+                header.kid = Some(kid.clone());
+                encode(&header, claims, encoding_key).map_err(|e| Error::internal_server_error_message(&format!("cannot sign jwt token: {}", e)))
+            }
+        }
+    }
+
+    pub fn decode(&self, token: &str) -> std::result::Result<JwtClaims, jsonwebtoken::errors::Error> {
+        match self {
+            JwtKeys::Secret(secret) => decode::<JwtClaims>(token, &DecodingKey::from_secret(secret.as_ref()), &Validation::default()).map(|t| t.claims),
+            JwtKeys::Asymmetric { algorithm, keys } => {
+                let header = decode_header(token)?;
+                let key = match header.kid.as_ref() {
+                    Some(kid) => keys.iter().find(|k| &k.kid == kid),
+                    None => if keys.len() == 1 { keys.first() } else { None },
+                };
+                let Some(key) = key else {
+                    return Err(ErrorKind::InvalidToken.into());
+                };
+                decode::<JwtClaims>(token, &key.decoding_key, &Validation::new(*algorithm)).map(|t| t.claims)
+            }
+        }
+    }
+}
+
+/// Reads the claims without verifying the signature. This is only used to find out which
+/// identity model a token belongs to, the token must be decoded again with the model's keys.
+pub fn peek_claims(token: &str) -> std::result::Result<JwtClaims, jsonwebtoken::errors::Error> {
+    let header = decode_header(token)?;
+    let mut validation = Validation::new(header.alg);
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    decode::<JwtClaims>(token, &DecodingKey::from_secret(&[]), &validation).map(|t| t.claims)
+}
+
+/// Reads and parses the PEM files given to `@identity.jwtKeys`, invalid keys are reported at
+/// load time.
+pub fn load_jwt_keys(algorithm: String, keys: &Value) -> Result<JwtKeys> {
+    let Ok(algorithm) = Algorithm::from_str(&algorithm) else {
+        return Err(Error::new(format!("invalid jwt algorithm: {}", algorithm)));
+    };
+    let Some(keys) = keys.as_array() else {
+        return Err(Error::new("@identity.jwtKeys: keys should be an array"));
+    };
+    let mut loaded = vec![];
+    for key in keys {
+        let kid: String = key.get("kid").ok_or_else(|| Error::new("@identity.jwtKeys: missing kid"))?.try_into()?;
+        let public_key_path: String = key.get("publicKey").ok_or_else(|| Error::new(format!("@identity.jwtKeys: missing publicKey of '{}'", kid)))?.try_into()?;
+        let public_key = read_pem(&public_key_path)?;
+        let decoding_key = decoding_key(algorithm, public_key.as_bytes()).map_err(|e| Error::new(format!("invalid public key of jwt key '{}': {}", kid, e)))?;
+        let encoding_key = match key.get("privateKey").and_then(|v| v.as_str()) {
+            Some(private_key_path) => {
+                let private_key = read_pem(private_key_path)?;
+                Some(encoding_key(algorithm, private_key.as_bytes()).map_err(|e| Error::new(format!("invalid private key of jwt key '{}': {}", kid, e)))?)
+            }
+            None => None,
+        };
+        loaded.push(JwtKey { kid, encoding_key, decoding_key });
+    }
+    if loaded.is_empty() {
+        return Err(Error::new("@identity.jwtKeys requires at least one key"));
+    }
+    Ok(JwtKeys::Asymmetric { algorithm, keys: loaded })
+}
+
+fn read_pem(path: &str) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| Error::new(format!("cannot read jwt key file '{}': {}", path, e)))
+}
+
+fn encoding_key(algorithm: Algorithm, pem: &[u8]) -> std::result::Result<EncodingKey, jsonwebtoken::errors::Error> {
+    match algorithm {
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => EncodingKey::from_rsa_pem(pem),
+        Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(pem),
+        Algorithm::EdDSA => EncodingKey::from_ed_pem(pem),
+        _ => Err(ErrorKind::InvalidAlgorithm.into()),
+    }
+}
+
+fn decoding_key(algorithm: Algorithm, pem: &[u8]) -> std::result::Result<DecodingKey, jsonwebtoken::errors::Error> {
+    match algorithm {
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => DecodingKey::from_rsa_pem(pem),
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem),
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(pem),
+        _ => Err(ErrorKind::InvalidAlgorithm.into()),
+    }
+}
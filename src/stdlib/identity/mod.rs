@@ -1,6 +1,5 @@
 use chrono::Utc;
 use indexmap::{IndexMap, indexmap};
-use jsonwebtoken::{decode, DecodingKey, encode, EncodingKey, Header, Validation};
 use key_path::path;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
@@ -16,28 +15,134 @@ use crate::middleware::next::{Next, NextImp};
 use crate::request::Request;
 use crate::response::Response;
 use crate::traits::named::Named;
+use crate::connection::transaction;
+use crate::model::Model;
+use crate::namespace::Namespace;
+use crate::model::tenant::tenant_field;
+use crate::stdlib::identity::keys::{JwtKeys, load_jwt_keys, peek_claims};
+use crate::stdlib::middlewares::tenant::{tenant_matches_claim, JWT_TENANT_KEY};
+
+mod keys;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JwtClaims {
     pub id: JsonValue,
     pub model: Vec<String>,
-    pub exp: usize
+    pub exp: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refresh: bool,
+    /// The tenant of an identity of a `@tenant` model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<JsonValue>,
 }
 
-pub fn encode_token(claims: JwtClaims, secret: &str) -> String {
-    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_ref()));
-    token.unwrap()
+/// The default lifetime of a refresh token in seconds, which is 30 days.
+const DEFAULT_REFRESH_TOKEN_EXPIRED: i64 = 60 * 60 * 24 * 30;
+
+fn refresh_token_store(namespace: &Namespace) -> Option<&Model> {
+    namespace.collect_models(|m| m.data().get("identity:refreshTokenStore").is_some()).into_iter().next()
 }
 
-pub fn decode_token(token: &str, secret: &str) -> Result<JwtClaims, jsonwebtoken::errors::Error> {
-    match decode::<JwtClaims>(token, &DecodingKey::from_secret(secret.as_ref()), &Validation::default()) {
-        Ok(token) => {
-            Ok(token.claims)
-        }
-        Err(reason) => {
-            Err(reason)
+fn bearer_token(request: &Request) -> teo_result::Result<String> {
+    let Some(authorization) = request.headers().get("authorization")? else {
+        return Err(Error::unauthorized_message("missing authorization header value"));
+    };
+    if authorization.len() < 7 {
+        return Err(Error::unauthorized_message("invalid jwt token"));
+    }
+    Ok(authorization[7..].to_owned())
+}
+
+/// Returns true if the session of the access token id is recorded as revoked in the
+/// `@identity.refreshTokenStore` model.
+async fn is_token_revoked(transaction_ctx: &transaction::Ctx, jti: &str) -> teo_result::Result<bool> {
+    let transaction_ctx = transaction_ctx.bypassing_policies();
+    let Some(store) = refresh_token_store(transaction_ctx.namespace()) else {
+        return Ok(false);
+    };
+    let record: Option<model::Object> = transaction_ctx.find_first(store, &teon!({
+        "where": { "accessJti": jti }
+    }), None, path![]).await?;
+    Ok(match record {
+        Some(record) => record.get("revoked")?,
+        None => false,
+    })
+}
+
+/// Revokes the session of the access token id.
+async fn revoke_token(transaction_ctx: &transaction::Ctx, jti: &str) -> teo_result::Result<()> {
+    let transaction_ctx = transaction_ctx.bypassing_policies();
+    let Some(store) = refresh_token_store(transaction_ctx.namespace()) else {
+        return Ok(());
+    };
+    let record: Option<model::Object> = transaction_ctx.find_first(store, &teon!({
+        "where": { "accessJti": jti }
+    }), None, path![]).await?;
+    if let Some(record) = record {
+        record.set("revoked", true)?;
+        record.save().await?;
+    }
+    Ok(())
+}
+
+/// Revokes every session of an identity, this is used when a rotated refresh token is reused.
+async fn revoke_all_tokens(transaction_ctx: &transaction::Ctx, claims: &JwtClaims) -> teo_result::Result<()> {
+    let transaction_ctx = transaction_ctx.bypassing_policies();
+    let Some(store) = refresh_token_store(transaction_ctx.namespace()) else {
+        return Ok(());
+    };
+    let records: Vec<model::Object> = transaction_ctx.find_many(store, &teon!({
+        "where": {
+            "model": claims.model.join("."),
+            "identifier": claims.id.to_string(),
+            "revoked": false,
         }
+    }), None, path![]).await?;
+    for record in records {
+        record.set("revoked", true)?;
+        record.save().await?;
     }
+    Ok(())
+}
+
+/// Persists a session for the issued access token and returns a refresh token for it. The
+/// refresh token has its own `jti`, the session records both. Returns `None` if no model is
+/// marked with `@identity.refreshTokenStore`.
+async fn issue_refresh_token(request: &Request, access_token: &str, jwt_keys: &JwtKeys) -> teo_result::Result<Option<String>> {
+    let transaction_ctx = request.transaction_ctx().bypassing_policies();
+    let Some(store) = refresh_token_store(transaction_ctx.namespace()) else {
+        return Ok(None);
+    };
+    let Ok(access_claims) = jwt_keys.decode(access_token) else {
+        return Err(Error::internal_server_error_message("token issuer returns an invalid jwt token"));
+    };
+    let Some(access_jti) = access_claims.jti else {
+        return Err(Error::internal_server_error_message("token issuer returns a jwt token without jti"));
+    };
+    let jti = uuid::Uuid::new_v4().to_string();
+    let expired = store.data().get("identity:refreshTokenStore").unwrap().as_int64().unwrap_or(DEFAULT_REFRESH_TOKEN_EXPIRED);
+    let expires_at = Utc::now() + chrono::Duration::seconds(expired);
+    let record = transaction_ctx.create_object(store, teon!({
+        "jti": jti.as_str(),
+        "accessJti": access_jti.as_str(),
+        "model": access_claims.model.join("."),
+        "identifier": access_claims.id.to_string(),
+        "expiresAt": expires_at,
+        "revoked": false,
+        "rotated": false,
+    }), Some(request.clone())).await?;
+    record.save().await?;
+    let claims = JwtClaims {
+        id: access_claims.id,
+        model: access_claims.model,
+        exp: expires_at.timestamp() as usize,
+        jti: Some(jti),
+        refresh: true,
+        tenant: access_claims.tenant,
+    };
+    Ok(Some(jwt_keys.encode(&claims)?))
 }
 
 pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
@@ -58,7 +163,20 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
 
     identity_namespace.define_model_decorator("jwtSecret", |arguments, model| {
         let secret: String = arguments.get("secret")?;
-        model.insert_data_entry("identity:jwtSecret".to_owned(), secret.into());
+        JwtKeys::Secret(secret).install(model.app_data(), model.path());
+        Ok(())
+    });
+
+    identity_namespace.define_model_decorator("jwtKeys", |arguments, model| {
+        let algorithm: String = arguments.get("algorithm")?;
+        let keys: Value = arguments.get("keys")?;
+        load_jwt_keys(algorithm, &keys)?.install(model.app_data(), model.path());
+        Ok(())
+    });
+
+    identity_namespace.define_model_decorator("refreshTokenStore", |arguments, model| {
+        let expired: Option<i64> = arguments.get_optional("expired")?;
+        model.insert_data_entry("identity:refreshTokenStore".to_owned(), expired.unwrap_or(DEFAULT_REFRESH_TOKEN_EXPIRED).into());
         Ok(())
     });
 
@@ -84,11 +202,12 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
             let expired = expired.clone();
             async move {
                 let object = pipeline_ctx.object();
-                let Some(jwt_secret) = object.model().data().get("identity:jwtSecret") else {
-                    return Err(Error::internal_server_error_message("missing @identity.jwtSecret"));
-                };
-                let jwt_secret: String = jwt_secret.try_into()?;
+                let jwt_keys = JwtKeys::from_model(pipeline_ctx.transaction_ctx().namespace().app_data(), object.model())?;
                 let json_identifier: JsonValue = object.identifier().try_into()?;
+                let tenant: Option<JsonValue> = match tenant_field(object.model()) {
+                    Some(field) => Some(object.get_value(field)?.try_into()?),
+                    None => None,
+                };
                 let claims = JwtClaims {
                     id: json_identifier,
                     model: object.model().path().clone(),
@@ -101,15 +220,19 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
                         };
                         (Utc::now().timestamp() + expired_at) as usize
                     } else { usize::MAX },
+                    jti: Some(uuid::Uuid::new_v4().to_string()),
+                    refresh: false,
+                    tenant,
                 };
-                Ok(encode_token(claims, &jwt_secret).into())
+                Ok(jwt_keys.encode(&claims)?.into())
             }
         })
     });
 
     identity_namespace.define_handler_template("signIn", |request: Request| async move {
         let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
-        let model_ctx = request.transaction_ctx().model_ctx_for_model_at_path(request.handler_match().unwrap().path()).unwrap();
+        // the identity isn't known yet, the record is found regardless of its policies
+        let model_ctx = request.transaction_ctx().bypassing_policies().model_ctx_for_model_at_path(request.handler_match().unwrap().path()).unwrap();
         let input = request.body_value()?;
         let credentials = input.get("credentials").unwrap().as_dictionary().unwrap();
         let mut identity_key: Option<&String> = None;
@@ -186,36 +309,141 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
         };
         let token_issuer = token_issuer.as_pipeline().unwrap();
         let token_string: String = credentials_pipeline_ctx.run_pipeline(token_issuer).await?;
+        let jwt_keys = JwtKeys::from_model(request.transaction_ctx().namespace().app_data(), &model)?;
+        let refresh_token = issue_refresh_token(&request, &token_string, &jwt_keys).await?;
+        // Output to the client
+        let include = input.get("include");
+        let select = input.get("select");
+        let obj = object.refreshed(include, select).await?;
+        let obj_teon = obj.to_teon().await?;
+        Ok(Response::data_meta(obj_teon, if let Some(refresh_token) = refresh_token {
+            teon!({
+                "token": token_string,
+                "refreshToken": refresh_token,
+            })
+        } else {
+            teon!({
+                "token": token_string
+            })
+        }))
+    });
+
+    identity_namespace.define_handler_template("refreshToken", |request: Request| async move {
+        let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
+        // the identity isn't known yet, the record is found regardless of its policies
+        let model_ctx = request.transaction_ctx().bypassing_policies().model_ctx_for_model_at_path(request.handler_match().unwrap().path()).unwrap();
+        let jwt_keys = JwtKeys::from_model(request.transaction_ctx().namespace().app_data(), &model)?;
+        let input = request.body_value()?;
+        let Some(refresh_token) = input.get("refreshToken").and_then(|v| v.as_str()) else {
+            return Err(Error::invalid_request_pathed(path!["refreshToken"], "missing refresh token"));
+        };
+        let claims = match jwt_keys.decode(refresh_token) {
+            Ok(claims) => claims,
+            Err(error) => return match error.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => Err(Error::unauthorized_pathed(path!["refreshToken"], "refresh token expired")),
+                _ => Err(Error::unauthorized_pathed(path!["refreshToken"], "invalid refresh token")),
+            },
+        };
+        if !claims.refresh || &claims.model != model.path() {
+            return Err(Error::unauthorized_pathed(path!["refreshToken"], "invalid refresh token"));
+        }
+        let Some(jti) = claims.jti.as_ref() else {
+            return Err(Error::unauthorized_pathed(path!["refreshToken"], "invalid refresh token"));
+        };
+        let transaction_ctx = request.transaction_ctx().bypassing_policies();
+        let Some(store) = refresh_token_store(transaction_ctx.namespace()) else {
+            return Err(Error::internal_server_error_message("missing @identity.refreshTokenStore"));
+        };
+        let record: Option<model::Object> = transaction_ctx.find_unique(store, &teon!({
+            "where": { "jti": jti.as_str() }
+        }), None, path![]).await?;
+        let Some(record) = record else {
+            return Err(Error::unauthorized_pathed(path!["refreshToken"], "invalid refresh token"));
+        };
+        let rotated: bool = record.get("rotated")?;
+        if rotated {
+            // a rotated refresh token is reused, the token may be stolen
+            revoke_all_tokens(&transaction_ctx, &claims).await?;
+            return Err(Error::unauthorized_pathed(path!["refreshToken"], "refresh token revoked"));
+        }
+        let revoked: bool = record.get("revoked")?;
+        if revoked {
+            return Err(Error::unauthorized_pathed(path!["refreshToken"], "refresh token revoked"));
+        }
+        let teon_identifier = Value::from(&claims.id);
+        let object: Option<model::Object> = model_ctx.find_unique(&teon_identifier).await?;
+        let Some(object) = object else {
+            return Err(Error::unauthorized_message("identity not found"));
+        };
+        let self_pipeline_ctx = pipeline::Ctx::new(Value::from(&object), object.clone(), path![], CODE_NAME | CODE_AMOUNT | CODE_POSITION, request.transaction_ctx(), Some(request.clone()));
+        if let Some(validator) = model.data().get("identity:validateAccount") {
+            let validator = validator.as_pipeline().unwrap();
+            match self_pipeline_ctx.run_pipeline_ignore_return_value(validator).await {
+                Ok(_) => (),
+                Err(mut error) => {
+                    error.code = 401;
+                    return Err(error);
+                }
+            }
+        }
+        let Some(token_issuer) = model.data().get("identity:tokenIssuer") else {
+            return Err(Error::internal_server_error_message("missing identity token issuer"));
+        };
+        let token_issuer = token_issuer.as_pipeline().unwrap();
+        record.set("revoked", true)?;
+        record.set("rotated", true)?;
+        record.save().await?;
+        let token_string: String = self_pipeline_ctx.run_pipeline(token_issuer).await?;
+        let Some(refresh_token) = issue_refresh_token(&request, &token_string, &jwt_keys).await? else {
+            return Err(Error::internal_server_error_message("missing @identity.refreshTokenStore"));
+        };
         // Output to the client
         let include = input.get("include");
         let select = input.get("select");
         let obj = object.refreshed(include, select).await?;
         let obj_teon = obj.to_teon().await?;
         Ok(Response::data_meta(obj_teon, teon!({
-            "token": token_string
+            "token": token_string,
+            "refreshToken": refresh_token,
         })))
     });
 
+    identity_namespace.define_handler_template("signOut", |request: Request| async move {
+        let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
+        let jwt_keys = JwtKeys::from_model(request.transaction_ctx().namespace().app_data(), &model)?;
+        let token = bearer_token(&request)?;
+        let Ok(claims) = jwt_keys.decode(&token) else {
+            return Err(Error::unauthorized_message("invalid jwt token"));
+        };
+        if &claims.model != model.path() {
+            return Err(Error::unauthorized_message("wrong model of identity"));
+        }
+        if let Some(jti) = claims.jti.as_ref() {
+            revoke_token(&request.transaction_ctx(), jti).await?;
+        }
+        Ok(Response::data(teon!({})))
+    });
+
     identity_namespace.define_handler_template("identity", |request: Request| async move {
         let model = request.transaction_ctx().namespace().model_at_path(&request.handler_match().unwrap().path()).unwrap().clone();
         let model_ctx = request.transaction_ctx().model_ctx_for_model_at_path(request.handler_match().unwrap().path()).unwrap();
-        let Some(jwt_secret) = model.data().get("identity:jwtSecret") else {
-            return Err(Error::internal_server_error_message("missing @identity.jwtSecret"));
-        };
-        let jwt_secret: String = jwt_secret.try_into()?;
-        let Some(authorization) = request.headers().get("authorization")? else {
-            return Err(Error::unauthorized_message("missing authorization header value"));
+        let jwt_keys = JwtKeys::from_model(request.transaction_ctx().namespace().app_data(), &model)?;
+        let token = bearer_token(&request)?;
+        let token = token.as_str();
+        let Ok(claims) = jwt_keys.decode(token) else {
+            return Err(Error::unauthorized_message("invalid jwt token"));
         };
-        if authorization.len() < 7 {
+        if claims.refresh {
             return Err(Error::unauthorized_message("invalid jwt token"));
         }
-        let token = &authorization[7..];
-        let Ok(claims) = decode_token(token, jwt_secret.as_str()) else {
-            return Err(Error::unauthorized_message("invalid jwt token"));
-        };
         if &claims.model != model.path() {
             return Err(Error::unauthorized_message("wrong model of identity"));
         }
+        if let Some(jti) = claims.jti.as_ref() {
+            if is_token_revoked(&request.transaction_ctx(), jti).await? {
+                return Err(Error::unauthorized_message("token revoked"));
+            }
+        }
         let teon_value: Value = Value::from(claims.id);
         let object: Option<model::Object> = model_ctx.find_unique(&teon_value).await?;
         if let Some(object) = object {
@@ -232,7 +460,7 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
     });
 
     identity_namespace.define_handler_middleware("identityFromJwt", |arguments: Arguments| {
-        let secret: String = arguments.get("secret")?;
+        let secret: Option<String> = arguments.get_optional("secret")?;
         Ok(move |request: Request, next: Next| {
             let secret = secret.clone();
             async move {
@@ -241,10 +469,31 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
                         return Err(Error::unauthorized_message("invalid jwt token"));
                     }
                     let token = &authorization[7..];
-                    match decode_token(token, &secret) {
+                    let decoded = match secret.as_ref() {
+                        Some(secret) => JwtKeys::Secret(secret.clone()).decode(token),
+                        // without a secret, verify with the keys declared on the identity model
+                        None => match peek_claims(token) {
+                            Ok(claims) => match request.transaction_ctx().namespace().model_at_path(&claims.model) {
+                                Some(model) => JwtKeys::from_model(request.transaction_ctx().namespace().app_data(), model)?.decode(token),
+                                None => return Err(Error::unauthorized_message("invalid jwt token")),
+                            },
+                            Err(error) => Err(error),
+                        }
+                    };
+                    match decoded {
                         Ok(claims) => {
+                            if claims.refresh {
+                                return Err(Error::unauthorized_message("invalid jwt token"));
+                            }
+                            if let Some(jti) = claims.jti.as_ref() {
+                                if is_token_revoked(&request.transaction_ctx(), jti).await? {
+                                    return Err(Error::unauthorized_message("token revoked"));
+                                }
+                            }
                             let json_identifier = &claims.id;
-                            let Some(model_ctx) = request.transaction_ctx().model_ctx_for_model_at_path(&claims.model) else {
+                            // the identity is what policies are evaluated against, so it's found
+                            // regardless of them
+                            let Some(model_ctx) = request.transaction_ctx().bypassing_policies().model_ctx_for_model_at_path(&claims.model) else {
                                 return Err(Error::unauthorized_message("invalid jwt token"));
                             };
                             let teon_identifier = Value::from(json_identifier);
@@ -261,6 +510,18 @@ pub(super) fn load_identity_library(std_namespace: &namespace::Builder) {
                                         }
                                     }
                                 }
+                                if let Some(tenant) = claims.tenant.as_ref() {
+                                    let tenant = Value::from(tenant);
+                                    // the tenant middleware may run first, the tenant it resolves
+                                    // must be the tenant of the identity
+                                    if let Some(resolved) = request.transaction_ctx().tenant() {
+                                        if !tenant_matches_claim(&resolved, &tenant) {
+                                            return Err(Error::unauthorized_message("tenant doesn't match the identity"));
+                                        }
+                                    }
+                                    request.local_values().insert(JWT_TENANT_KEY, tenant);
+                                }
+                                request.transaction_ctx().enforce_policies(Some(&object));
                                 request.local_values().insert("account", Value::from(object));
                             } else {
                                 return Err(Error::unauthorized_message("invalid jwt token"));
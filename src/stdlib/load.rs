@@ -24,7 +24,13 @@ use crate::stdlib::pipeline_items::datetime::load_pipeline_datetime_items;
 use crate::stdlib::pipeline_items::debug::load_debug_items;
 use crate::stdlib::structs::load_structs;
 use crate::stdlib::identity::load_identity_library;
+use crate::stdlib::audit::load_audit_library;
+use crate::stdlib::rbac::load_rbac_library;
 use crate::stdlib::middlewares::cors::load_cors_middleware;
+use crate::stdlib::middlewares::rate_limit::load_rate_limit_middleware;
+use crate::stdlib::middlewares::compress::load_compress_middleware;
+use crate::stdlib::middlewares::cache::load_cache_middleware;
+use crate::stdlib::middlewares::tenant::load_tenant_middleware;
 use crate::stdlib::pipeline_items::request::load_pipeline_request_items;
 
 pub fn load(namespace_builder: &namespace::Builder) {
@@ -61,7 +67,13 @@ pub fn load(namespace_builder: &namespace::Builder) {
     // middlewares
     load_cors_middleware(&std_namespace_builder);
     load_log_request_middleware(&std_namespace_builder);
+    load_rate_limit_middleware(&std_namespace_builder);
+    load_compress_middleware(&std_namespace_builder);
+    load_cache_middleware(&std_namespace_builder);
+    load_tenant_middleware(&std_namespace_builder);
     // libraries
     load_identity_library(&std_namespace_builder);
     load_admin_library(&std_namespace_builder);
+    load_audit_library(&std_namespace_builder);
+    load_rbac_library(&std_namespace_builder);
 }
\ No newline at end of file
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use indexmap::IndexMap;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::arguments::Arguments;
+use crate::handler::default::conditional_read_response;
+use crate::middleware::next::{Next, NextImp};
+use crate::model;
+use crate::model::Model;
+use crate::namespace;
+use crate::namespace::Namespace;
+use crate::request::Request;
+use crate::response::Response;
+use crate::response::etag::normalized_value;
+use crate::value::Value;
+
+/// The handlers whose responses are cached. Other handlers are passed through.
+const CACHEABLE_HANDLERS: [&str; 6] = ["findUnique", "findFirst", "findMany", "count", "aggregate", "groupBy"];
+
+/// The number of writes to each model of an app. A cached response is stale once a model it's
+/// read from is written.
+#[derive(Default)]
+struct ModelGenerations {
+    generations: Mutex<HashMap<Vec<String>, u64>>,
+}
+
+impl ModelGenerations {
+
+    /// Invalidates the cached responses which read from the model at `path`.
+    fn increase(&self, path: &Vec<String>) {
+        *self.generations.lock().unwrap().entry(path.clone()).or_insert(0) += 1;
+    }
+
+    fn get(&self, paths: &Vec<Vec<String>>) -> Vec<u64> {
+        let generations = self.generations.lock().unwrap();
+        paths.iter().map(|path| generations.get(path).cloned().unwrap_or(0)).collect()
+    }
+}
+
+struct Entry {
+    code: u16,
+    body: Value,
+    expires_at: Instant,
+    models: Vec<Vec<String>>,
+    generations: Vec<u64>,
+}
+
+/// An in-process LRU cache. The least recently used entry is at the front.
+struct ResponseCache {
+    entries: IndexMap<String, Entry>,
+    capacity: usize,
+    model_generations: Arc<ModelGenerations>,
+}
+
+impl ResponseCache {
+
+    fn get(&mut self, key: &str) -> Option<(u16, Value)> {
+        let entry = self.entries.shift_remove(key)?;
+        if entry.expires_at <= Instant::now() || self.model_generations.get(&entry.models) != entry.generations {
+            return None;
+        }
+        let result = (entry.code, entry.body.clone());
+        self.entries.insert(key.to_owned(), entry);
+        Some(result)
+    }
+
+    fn insert(&mut self, key: String, entry: Entry) {
+        self.entries.shift_remove(&key);
+        self.entries.insert(key, entry);
+        while self.entries.len() > self.capacity {
+            self.entries.shift_remove_index(0);
+        }
+    }
+}
+
+/// Returns the model of the handler and the models of the relations it includes.
+fn read_models(model: &Model, finder: &Value, namespace: &Namespace, result: &mut Vec<Vec<String>>) {
+    if !result.contains(model.path()) {
+        result.push(model.path().clone());
+    }
+    let Some(include) = finder.get("include").and_then(|i| i.as_dictionary()) else {
+        return;
+    };
+    for (name, value) in include {
+        let Some(relation) = model.relation(name) else {
+            continue;
+        };
+        if let Some(through) = relation.through_path() {
+            if !result.contains(through) {
+                result.push(through.clone());
+            }
+        }
+        if let Some(related) = namespace.model_at_path(relation.model_path()) {
+            read_models(related, value, namespace, result);
+        }
+    }
+}
+
+fn cache_key(request: &Request) -> Result<String> {
+    let handler_match = request.handler_match()?;
+    let body = JsonValue::try_from(&normalized_value(request.body_value()?))?;
+    let identity = match request.local_values().get::<model::Object>("account") {
+        Ok(account) => {
+            let identifier: JsonValue = account.identifier().try_into()?;
+            format!("{}:{}", account.model().path().join("."), identifier)
+        }
+        Err(_) => "".to_owned(),
+    };
+    // the tenant and the policy identity decide which records the handler reads
+    let tenant = match request.transaction_ctx().tenant() {
+        Some(tenant) => JsonValue::try_from(&tenant)?.to_string(),
+        None => "".to_owned(),
+    };
+    let policy_identity = match request.transaction_ctx().policy_identity() {
+        // identity values may not be representable in JSON
+        Some(identity) => format!("{:?}", normalized_value(&identity)),
+        None => "".to_owned(),
+    };
+    Ok(format!("{}.{}\n{}\n{}\n{}\n{}", handler_match.path().join("."), handler_match.handler_name(), identity, tenant, policy_identity, body))
+}
+
+pub(in crate::stdlib) fn load_cache_middleware(namespace: &namespace::Builder) {
+    let model_generations = Arc::new(ModelGenerations::default());
+    let written_generations = model_generations.clone();
+    namespace.on_model_write(move |path| written_generations.increase(path));
+    namespace.define_handler_middleware("cache", move |arguments: Arguments| {
+        let ttl: i32 = arguments.get("ttl")?;
+        let capacity: Option<usize> = arguments.get_optional("capacity")?;
+        if ttl <= 0 {
+            Err(Error::new("cache: ttl should be positive"))?
+        }
+        let ttl = Duration::from_secs(ttl as u64);
+        let cache = Arc::new(Mutex::new(ResponseCache {
+            entries: IndexMap::new(),
+            capacity: capacity.unwrap_or(1000),
+            model_generations: model_generations.clone(),
+        }));
+        let model_generations = model_generations.clone();
+        Ok(move |request: Request, next: Next| {
+            let cache = cache.clone();
+            let model_generations = model_generations.clone();
+            async move {
+                let handler_match = request.handler_match()?.clone();
+                if !CACHEABLE_HANDLERS.contains(&handler_match.handler_name()) {
+                    return next.call(request).await;
+                }
+                let namespace = request.transaction_ctx().namespace().clone();
+                let Some(model) = namespace.model_at_path(handler_match.path()) else {
+                    return next.call(request).await;
+                };
+                let key = cache_key(&request)?;
+                let cached = cache.lock().unwrap().get(&key);
+                if let Some((code, body)) = cached {
+                    let res = Response::teon(body);
+                    res.set_code(code);
+                    // cached responses are conditional just like the responses of the handlers
+                    let res = conditional_read_response(&request, res)?;
+                    res.headers().insert("X-Cache", "HIT")?;
+                    return Ok(res);
+                }
+                let mut models = vec![];
+                read_models(model, request.body_value()?, &namespace, &mut models);
+                // generations are read before the query, so that a write during the query
+                // invalidates the entry
+                let generations = model_generations.get(&models);
+                let res = next.call(request).await?;
+                if res.code() == 200 {
+                    if let Some(body) = res.body().as_teon() {
+                        cache.lock().unwrap().insert(key, Entry {
+                            code: res.code(),
+                            body: body.clone(),
+                            expires_at: Instant::now() + ttl,
+                            models,
+                            generations,
+                        });
+                    }
+                }
+                res.headers().insert("X-Cache", "MISS")?;
+                Ok(res)
+            }
+        })
+    });
+}
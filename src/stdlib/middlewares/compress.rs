@@ -0,0 +1,184 @@
+use std::io::Write;
+use bytes::Bytes;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use hyper::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY};
+use mime::APPLICATION_JSON;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::arguments::Arguments;
+use crate::middleware::next::{Next, NextImp};
+use crate::namespace;
+use crate::request::Request;
+use crate::response::{Body, Response};
+use crate::response::body::BodyInner;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                writer.write_all(data)?;
+                writer.flush()?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Zstd => zstd::stream::encode_all(data, 3),
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Picks the encoding with the highest quality value in `Accept-Encoding`. Ties are broken by
+/// the order of the server's preferred encodings.
+fn negotiate(accept_encoding: &str, preferred: &Vec<Encoding>) -> Option<Encoding> {
+    let mut wildcard: Option<f32> = None;
+    let mut qualities: Vec<(Encoding, f32)> = vec![];
+    for item in accept_encoding.split(",") {
+        let mut parts = item.split(";");
+        let name = parts.next().unwrap().trim().to_lowercase();
+        let quality = parts
+            .filter_map(|p| p.trim().strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()))
+            .next()
+            .unwrap_or(1.0);
+        if name == "*" {
+            wildcard = Some(quality);
+        } else if let Some(encoding) = Encoding::from_name(&name) {
+            qualities.push((encoding, quality));
+        }
+    }
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in preferred {
+        let quality = qualities.iter().find(|(e, _)| e == encoding).map(|(_, q)| *q).or(wildcard).unwrap_or(0.0);
+        if quality > 0.0 && best.map_or(true, |(_, q)| quality > q) {
+            best = Some((*encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+fn is_compressed_content_type(content_type: &str) -> bool {
+    let essence = content_type.split(";").next().unwrap().trim().to_lowercase();
+    if essence == "image/svg+xml" {
+        return false;
+    }
+    essence.starts_with("image/") ||
+        essence.starts_with("video/") ||
+        essence.starts_with("audio/") ||
+        matches!(essence.as_str(),
+            "application/zip" | "application/gzip" | "application/x-gzip" | "application/zstd" |
+            "application/x-bzip2" | "application/x-7z-compressed" | "application/x-rar-compressed" |
+            "application/pdf" | "font/woff" | "font/woff2")
+}
+
+/// Files are only compressed if they are known to be textual, since the content type of a
+/// file body is otherwise inferred by the server from its extension.
+fn text_file_content_type(extension: &str) -> Option<&'static str> {
+    match extension.to_lowercase().as_str() {
+        "html" | "htm" => Some("text/html"),
+        "css" => Some("text/css"),
+        "js" | "mjs" => Some("text/javascript"),
+        "json" | "map" => Some("application/json"),
+        "txt" => Some("text/plain"),
+        "csv" => Some("text/csv"),
+        "md" => Some("text/markdown"),
+        "xml" => Some("application/xml"),
+        "svg" => Some("image/svg+xml"),
+        "wasm" => Some("application/wasm"),
+        _ => None,
+    }
+}
+
+/// Returns the bytes of a body which can be compressed, and the content type to set if the
+/// response doesn't have one.
+async fn compressible_bytes(body: &Body) -> Result<Option<(Vec<u8>, Option<&'static str>)>> {
+    Ok(match body.inner.as_ref() {
+        BodyInner::String(content) => Some((content.as_bytes().to_vec(), None)),
+        BodyInner::Teon(value) => {
+            let json = JsonValue::try_from(value)?;
+            Some((serde_json::to_vec(&json).unwrap(), Some(APPLICATION_JSON.essence_str())))
+        }
+        BodyInner::File(path) => match path.extension().and_then(|e| e.to_str()).and_then(text_file_content_type) {
+            Some(content_type) => Some((tokio::fs::read(path).await.map_err(|e| Error::internal_server_error_message(&format!("cannot read file: {}", e)))?, Some(content_type))),
+            None => None,
+        },
+        _ => None,
+    })
+}
+
+pub(in crate::stdlib) fn load_compress_middleware(namespace: &namespace::Builder) {
+    namespace.define_request_middleware("compress", |arguments: Arguments| {
+        let threshold: i32 = arguments.get_optional("threshold")?.unwrap_or(1024);
+        let encodings: Option<Vec<String>> = arguments.get_optional("encodings")?;
+        let encodings: Vec<Encoding> = match encodings {
+            Some(encodings) => encodings.iter().map(|e| Encoding::from_name(e).ok_or_else(|| Error::new(format!("compress: unsupported encoding: {}", e)))).collect::<Result<Vec<Encoding>>>()?,
+            None => vec![Encoding::Brotli, Encoding::Zstd, Encoding::Gzip],
+        };
+        Ok(move |request: Request, next: Next| {
+            let encodings = encodings.clone();
+            async move {
+                let accept_encoding = request.headers().get(ACCEPT_ENCODING.as_str())?;
+                let res = next.call(request).await?;
+                if res.code() == 204 || res.code() == 304 || res.headers().contains_key(CONTENT_ENCODING.as_str()) {
+                    return Ok(res);
+                }
+                if let Some(content_type) = res.headers().get(CONTENT_TYPE.as_str())? {
+                    if is_compressed_content_type(&content_type) {
+                        return Ok(res);
+                    }
+                }
+                let body = res.body();
+                let Some((bytes, content_type)) = compressible_bytes(&body).await? else {
+                    return Ok(res);
+                };
+                res.headers().append(VARY.as_str(), ACCEPT_ENCODING.as_str())?;
+                if bytes.len() < threshold.max(0) as usize {
+                    return Ok(res);
+                }
+                let Some(encoding) = accept_encoding.as_ref().and_then(|a| negotiate(a, &encodings)) else {
+                    return Ok(res);
+                };
+                let compressed = tokio::task::spawn_blocking(move || encoding.encode(&bytes)).await
+                    .map_err(|e| Error::internal_server_error_message(&format!("compression failed: {}", e)))?
+                    .map_err(|e| Error::internal_server_error_message(&format!("compression failed: {}", e)))?;
+                if let Some(content_type) = content_type {
+                    if !res.headers().contains_key(CONTENT_TYPE.as_str()) {
+                        res.headers().insert(CONTENT_TYPE.as_str(), content_type)?;
+                    }
+                }
+                res.headers().remove(CONTENT_LENGTH.as_str());
+                res.headers().insert(CONTENT_ENCODING.as_str(), encoding.name())?;
+                res.set_body(Body::bytes(Bytes::from(compressed)));
+                Ok(res)
+            }
+        })
+    });
+}
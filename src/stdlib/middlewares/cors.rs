@@ -1,32 +1,111 @@
+use hyper::Method;
+use regex::Regex;
+use teo_result::{Error, Result};
 use crate::arguments::Arguments;
 use crate::middleware::next::{Next, NextImp};
 use crate::namespace;
 use crate::request::Request;
 use crate::response::Response;
+use crate::value::Value;
+
+#[derive(Debug, Clone)]
+enum AllowedOrigin {
+    Any,
+    Exact(String),
+    Pattern(Regex),
+}
+
+impl AllowedOrigin {
+
+    fn from_value(value: &Value) -> Result<Vec<AllowedOrigin>> {
+        match value {
+            Value::String(origin) => Ok(vec![if origin == "*" {
+                AllowedOrigin::Any
+            } else {
+                AllowedOrigin::Exact(origin.clone())
+            }]),
+            // patterns match the whole origin, `example\.com` shouldn't allow `example.com.evil.io`
+            Value::Regex(regex) => match Regex::new(&format!("^(?:{})$", regex.as_str())) {
+                Ok(anchored) => Ok(vec![AllowedOrigin::Pattern(anchored)]),
+                Err(err) => Err(Error::new(format!("cors: invalid origin pattern: {}", err))),
+            },
+            Value::Array(origins) => {
+                let mut result = vec![];
+                for origin in origins {
+                    result.extend(Self::from_value(origin)?);
+                }
+                Ok(result)
+            }
+            _ => Err(Error::new(format!("cors: invalid origin: {}", value))),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            AllowedOrigin::Any => true,
+            AllowedOrigin::Exact(exact) => exact == origin,
+            AllowedOrigin::Pattern(regex) => regex.is_match(origin),
+        }
+    }
+}
 
 pub(in crate::stdlib) fn load_cors_middleware(namespace: &namespace::Builder) {
     namespace.define_request_middleware("cors", |arguments: Arguments| {
-        let origin: String = arguments.get("origin")?;
+        let origin: Value = arguments.get("origin")?;
+        let allowed_origins = AllowedOrigin::from_value(&origin)?;
         let methods: Vec<String> = arguments.get("methods")?;
         let headers: Vec<String> = arguments.get("headers")?;
         let max_age: i32 = arguments.get("maxAge")?;
+        let credentials: bool = arguments.get_optional("credentials")?.unwrap_or(false);
+        let expose_headers: Vec<String> = arguments.get_optional("exposeHeaders")?.unwrap_or_default();
+        let allows_any = allowed_origins.iter().any(|o| matches!(o, AllowedOrigin::Any));
+        if allows_any && credentials {
+            Err(Error::new("cors: origin \"*\" cannot be used with credentials"))?
+        }
+        // with a fixed wildcard, the response doesn't vary with the request origin
+        let echoes_origin = !allows_any;
         Ok(move |request: Request, next: Next| {
-            let origin = origin.clone();
+            let allowed_origins = allowed_origins.clone();
             let methods = methods.clone();
             let headers = headers.clone();
+            let expose_headers = expose_headers.clone();
             async move {
-                let res_or_err = next.call(request).await;
-                let res = if res_or_err.is_ok() {
-                    res_or_err.unwrap()
+                let request_origin = request.headers().get("origin")?;
+                let allow_origin = if !echoes_origin {
+                    Some("*".to_owned())
+                } else {
+                    request_origin.filter(|o| allowed_origins.iter().any(|a| a.matches(o)))
+                };
+                let is_preflight = request.method() == Method::OPTIONS && request.headers().contains_key("access-control-request-method");
+                let res = if is_preflight {
+                    let res = Response::empty();
+                    res.set_code(204);
+                    if allow_origin.is_some() {
+                        res.headers().insert("Access-Control-Allow-Methods", methods.join(", "))?;
+                        res.headers().insert("Access-Control-Allow-Headers", headers.join(", "))?;
+                        res.headers().insert("Access-Control-Max-Age", max_age.to_string())?;
+                    }
+                    res
                 } else {
-                    Response::from(res_or_err.err().unwrap())
+                    match next.call(request).await {
+                        Ok(res) => res,
+                        Err(err) => Response::from(err),
+                    }
                 };
-                res.headers().insert("Access-Control-Allow-Origin", origin)?;
-                res.headers().insert("Access-Control-Allow-Methods", methods.join(", "))?;
-                res.headers().insert("Access-Control-Allow-Headers", headers.join(", "))?;
-                res.headers().insert("Access-Control-Max-Age", max_age.to_string())?;
+                if let Some(allow_origin) = allow_origin {
+                    res.headers().insert("Access-Control-Allow-Origin", allow_origin)?;
+                    if credentials {
+                        res.headers().insert("Access-Control-Allow-Credentials", "true")?;
+                    }
+                    if !is_preflight && !expose_headers.is_empty() {
+                        res.headers().insert("Access-Control-Expose-Headers", expose_headers.join(", "))?;
+                    }
+                }
+                if echoes_origin {
+                    res.headers().append("Vary", "Origin")?;
+                }
                 return Ok(res);
             }
         })
     });
-}
\ No newline at end of file
+}
@@ -1,2 +1,8 @@
 pub(super) mod log_request;
-pub(super) mod cors;
\ No newline at end of file
+pub(super) mod cors;
+pub(super) mod compress;
+pub(crate) mod cache;
+pub(super) mod rate_limit;
+pub(crate) mod tenant;
+
+pub use rate_limit::{define_rate_limit_middlewares, MemoryRateLimitStore, RateLimitAlgorithm, RateLimitDecision, RateLimitPolicy, RateLimitStore};
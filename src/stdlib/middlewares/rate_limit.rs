@@ -0,0 +1,357 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::arguments::Arguments;
+use crate::middleware::middleware_imp::MiddlewareImp;
+use crate::middleware::next::{Next, NextImp};
+use crate::model;
+use crate::namespace;
+use crate::request::Request;
+use crate::response::Response;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitAlgorithm {
+    /// Requests consume tokens which are refilled continuously, `limit` tokens per `window`.
+    TokenBucket,
+    /// At most `limit` requests are accepted in any period of `window`.
+    SlidingWindow,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitPolicy {
+    pub algorithm: RateLimitAlgorithm,
+    pub limit: u32,
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitDecision {
+    Allowed { remaining: u32 },
+    Limited { retry_after: Duration },
+}
+
+/// The storage of rate limit buckets. The store applies the policy itself, so that a shared
+/// backend can update a bucket atomically.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn hit(&self, key: &str, policy: &RateLimitPolicy) -> Result<RateLimitDecision>;
+}
+
+enum Bucket {
+    TokenBucket { tokens: f64, updated_at: Instant },
+    SlidingWindow { hits: VecDeque<Instant> },
+}
+
+impl Bucket {
+
+    fn last_used_at(&self) -> Option<Instant> {
+        match self {
+            Bucket::TokenBucket { updated_at, .. } => Some(*updated_at),
+            Bucket::SlidingWindow { hits } => hits.back().cloned(),
+        }
+    }
+}
+
+/// The default in-process store.
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    buckets: HashMap<String, (Duration, Bucket)>,
+    hits_since_prune: usize,
+}
+
+impl MemoryRateLimitStore {
+
+    /// Idle buckets are dropped after the store served this many hits, or as many hits as it
+    /// holds buckets if that's more, which keeps pruning amortized constant per hit.
+    const PRUNE_INTERVAL: usize = 1_000;
+
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(Buckets { buckets: HashMap::new(), hits_since_prune: 0 }),
+        }
+    }
+
+    fn prune(buckets: &mut HashMap<String, (Duration, Bucket)>, now: Instant) {
+        buckets.retain(|_, (window, bucket)| match bucket.last_used_at() {
+            Some(last_used_at) => now.duration_since(last_used_at) < *window,
+            None => false,
+        });
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+
+    async fn hit(&self, key: &str, policy: &RateLimitPolicy) -> Result<RateLimitDecision> {
+        let now = Instant::now();
+        let mut guard = self.buckets.lock().unwrap();
+        let Buckets { buckets, hits_since_prune } = &mut *guard;
+        *hits_since_prune += 1;
+        if *hits_since_prune >= Self::PRUNE_INTERVAL.max(buckets.len()) {
+            Self::prune(buckets, now);
+            *hits_since_prune = 0;
+        }
+        let (_, bucket) = buckets.entry(key.to_owned()).or_insert_with(|| (policy.window, match policy.algorithm {
+            RateLimitAlgorithm::TokenBucket => Bucket::TokenBucket { tokens: policy.limit as f64, updated_at: now },
+            RateLimitAlgorithm::SlidingWindow => Bucket::SlidingWindow { hits: VecDeque::new() },
+        }));
+        Ok(match bucket {
+            Bucket::TokenBucket { tokens, updated_at } => {
+                let rate = policy.limit as f64 / policy.window.as_secs_f64();
+                *tokens = (*tokens + now.duration_since(*updated_at).as_secs_f64() * rate).min(policy.limit as f64);
+                *updated_at = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    RateLimitDecision::Allowed { remaining: *tokens as u32 }
+                } else {
+                    RateLimitDecision::Limited { retry_after: Duration::from_secs_f64((1.0 - *tokens) / rate) }
+                }
+            }
+            Bucket::SlidingWindow { hits } => {
+                while hits.front().map_or(false, |hit| now.duration_since(*hit) >= policy.window) {
+                    hits.pop_front();
+                }
+                if (hits.len() as u32) < policy.limit {
+                    hits.push_back(now);
+                    RateLimitDecision::Allowed { remaining: policy.limit - hits.len() as u32 }
+                } else {
+                    RateLimitDecision::Limited { retry_after: policy.window - now.duration_since(*hits.front().unwrap()) }
+                }
+            }
+        })
+    }
+}
+
+enum RateLimitKey {
+    Ip,
+    Header(String),
+    Identity,
+}
+
+/// Each declared middleware owns its own buckets even if they share a store.
+static NEXT_RATE_LIMIT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A proxy, or a network of proxies, trusted to append the address it receives a request from
+/// to `X-Forwarded-For`. This is written as an address or in CIDR notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedProxy {
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => Self::masked(u32::from(network) as u128, 32, self.prefix) == Self::masked(u32::from(*ip) as u128, 32, self.prefix),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => Self::masked(u128::from(network), 128, self.prefix) == Self::masked(u128::from(*ip), 128, self.prefix),
+            _ => false,
+        }
+    }
+
+    fn masked(bits: u128, width: u8, prefix: u8) -> u128 {
+        if prefix == 0 {
+            0
+        } else {
+            bits >> (width - prefix)
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = match s.split_once("/") {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let Ok(network) = address.parse::<IpAddr>() else {
+            Err(Error::new(format!("rateLimit: invalid trusted proxy: {}", s)))?
+        };
+        let width = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= width => prefix,
+                _ => Err(Error::new(format!("rateLimit: invalid trusted proxy: {}", s)))?,
+            },
+            None => width,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+fn is_trusted_proxy(ip: &IpAddr, trusted_proxies: &[TrustedProxy]) -> bool {
+    trusted_proxies.iter().any(|proxy| proxy.contains(ip))
+}
+
+/// The address of the client. When the peer is a trusted proxy, this is the rightmost
+/// `X-Forwarded-For` hop which isn't a trusted proxy, since the hops on its left may be set by
+/// the client. No proxy is trusted unless declared with `trustedProxies`.
+fn client_ip(request: &Request, trusted_proxies: &[TrustedProxy]) -> Result<String> {
+    let remote_ip = request.remote_addr().map(|addr| addr.ip());
+    if remote_ip.map_or(false, |ip| is_trusted_proxy(&ip, trusted_proxies)) {
+        if let Some(forwarded_for) = request.headers().get("x-forwarded-for")? {
+            let hops: Vec<&str> = forwarded_for.split(",").map(|hop| hop.trim()).filter(|hop| !hop.is_empty()).collect();
+            let untrusted = hops.iter().rev().find(|hop| match hop.parse::<IpAddr>() {
+                Ok(ip) => !is_trusted_proxy(&ip, trusted_proxies),
+                Err(_) => true,
+            });
+            if let Some(ip) = untrusted.or(hops.first()) {
+                return Ok(ip.to_string());
+            }
+        }
+        if let Some(real_ip) = request.headers().get("x-real-ip")? {
+            return Ok(real_ip);
+        }
+    }
+    Ok(remote_ip.map_or("unknown".to_owned(), |ip| ip.to_string()))
+}
+
+fn request_bucket_key(request: &Request, key: &RateLimitKey, trusted_proxies: &[TrustedProxy]) -> Result<String> {
+    Ok(match key {
+        RateLimitKey::Ip => format!("ip:{}", client_ip(request, trusted_proxies)?),
+        RateLimitKey::Header(name) => match request.headers().get(name)? {
+            Some(value) => format!("header:{}", value),
+            None => format!("ip:{}", client_ip(request, trusted_proxies)?),
+        },
+        // requests without identity are limited by ip
+        RateLimitKey::Identity => match request.local_values().get::<model::Object>("account") {
+            Ok(account) => {
+                let identifier: JsonValue = account.identifier().try_into()?;
+                format!("identity:{}:{}", account.model().path().join("."), identifier)
+            }
+            Err(_) => format!("ip:{}", client_ip(request, trusted_proxies)?),
+        },
+    })
+}
+
+fn rate_limit_middleware(arguments: Arguments, store: Arc<dyn RateLimitStore>) -> Result<impl MiddlewareImp> {
+    let key: Option<String> = arguments.get_optional("key")?;
+    let key = match key.as_deref() {
+        None | Some("ip") => RateLimitKey::Ip,
+        Some("header") => {
+            let header: String = arguments.get("header")?;
+            RateLimitKey::Header(header)
+        }
+        Some("identity") => RateLimitKey::Identity,
+        Some(key) => Err(Error::new(format!("rateLimit: invalid key: {}", key)))?,
+    };
+    let algorithm: Option<String> = arguments.get_optional("policy")?;
+    let algorithm = match algorithm.as_deref() {
+        None | Some("tokenBucket") => RateLimitAlgorithm::TokenBucket,
+        Some("slidingWindow") => RateLimitAlgorithm::SlidingWindow,
+        Some(algorithm) => Err(Error::new(format!("rateLimit: invalid policy: {}", algorithm)))?,
+    };
+    let limit: i32 = arguments.get("limit")?;
+    let window: i32 = arguments.get("window")?;
+    if limit <= 0 || window <= 0 {
+        Err(Error::new("rateLimit: limit and window should be positive"))?
+    }
+    let trusted_proxies: Vec<String> = arguments.get_optional("trustedProxies")?.unwrap_or_default();
+    let trusted_proxies = trusted_proxies.iter().map(|proxy| proxy.parse()).collect::<Result<Vec<TrustedProxy>>>()?;
+    let policy = RateLimitPolicy { algorithm, limit: limit as u32, window: Duration::from_secs(window as u64) };
+    let id = NEXT_RATE_LIMIT_ID.fetch_add(1, Ordering::SeqCst);
+    let key = Arc::new(key);
+    let trusted_proxies = Arc::new(trusted_proxies);
+    Ok(move |request: Request, next: Next| {
+        let store = store.clone();
+        let key = key.clone();
+        let trusted_proxies = trusted_proxies.clone();
+        async move {
+            let bucket_key = format!("{}:{}", id, request_bucket_key(&request, key.as_ref(), trusted_proxies.as_slice())?);
+            match store.hit(&bucket_key, &policy).await? {
+                RateLimitDecision::Allowed { remaining } => {
+                    let res = next.call(request).await?;
+                    res.headers().insert("X-RateLimit-Limit", policy.limit.to_string())?;
+                    res.headers().insert("X-RateLimit-Remaining", remaining.to_string())?;
+                    Ok(res)
+                }
+                RateLimitDecision::Limited { retry_after } => {
+                    let res = Response::from(Error::new_with_code("too many requests", 429));
+                    res.headers().insert("Retry-After", retry_after.as_secs_f64().ceil().to_string())?;
+                    res.headers().insert("X-RateLimit-Limit", policy.limit.to_string())?;
+                    res.headers().insert("X-RateLimit-Remaining", "0")?;
+                    Ok(res)
+                }
+            }
+        }
+    })
+}
+
+/// Defines the `rateLimit` request middleware and handler middleware with the given store.
+/// Use the handler middleware to key buckets by the identity resolved from `identityFromJwt`.
+/// Call this on the std namespace to replace the default in-process store.
+pub fn define_rate_limit_middlewares(namespace: &namespace::Builder, store: Arc<dyn RateLimitStore>) {
+    let request_store = store.clone();
+    namespace.define_request_middleware("rateLimit", move |arguments: Arguments| {
+        rate_limit_middleware(arguments, request_store.clone())
+    });
+    namespace.define_handler_middleware("rateLimit", move |arguments: Arguments| {
+        rate_limit_middleware(arguments, store.clone())
+    });
+}
+
+pub(in crate::stdlib) fn load_rate_limit_middleware(namespace: &namespace::Builder) {
+    define_rate_limit_middlewares(namespace, Arc::new(MemoryRateLimitStore::new()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn trusted_proxies_match_addresses_and_networks() {
+        let single: TrustedProxy = "10.0.0.1".parse().unwrap();
+        assert!(single.contains(&ip("10.0.0.1")));
+        assert!(!single.contains(&ip("10.0.0.2")));
+        let network: TrustedProxy = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains(&ip("10.255.1.2")));
+        assert!(!network.contains(&ip("11.0.0.1")));
+        assert!(!network.contains(&ip("::ffff:10.0.0.1")));
+        let v6: TrustedProxy = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(&ip("fd12::1")));
+        assert!(!v6.contains(&ip("fe80::1")));
+        let any: TrustedProxy = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(&ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn invalid_trusted_proxies_are_rejected() {
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy.local".parse::<TrustedProxy>().is_err());
+        assert!("10.0.0.0/x".parse::<TrustedProxy>().is_err());
+    }
+
+    #[test]
+    fn nothing_is_trusted_by_default() {
+        assert!(!is_trusted_proxy(&ip("127.0.0.1"), &[]));
+        assert!(!is_trusted_proxy(&ip("192.168.1.1"), &[]));
+    }
+
+    #[tokio::test]
+    async fn idle_buckets_are_pruned_periodically() {
+        let store = MemoryRateLimitStore::new();
+        let policy = RateLimitPolicy { algorithm: RateLimitAlgorithm::SlidingWindow, limit: 10, window: Duration::from_millis(1) };
+        for i in 0..MemoryRateLimitStore::PRUNE_INTERVAL - 1 {
+            store.hit(&format!("key:{}", i), &policy).await.unwrap();
+        }
+        assert_eq!(store.buckets.lock().unwrap().buckets.len(), MemoryRateLimitStore::PRUNE_INTERVAL - 1);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        store.hit("last", &policy).await.unwrap();
+        assert_eq!(store.buckets.lock().unwrap().buckets.len(), 1);
+    }
+}
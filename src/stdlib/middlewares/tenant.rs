@@ -0,0 +1,102 @@
+use hyper::header::HOST;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::arguments::Arguments;
+use crate::middleware::middleware_imp::MiddlewareImp;
+use crate::middleware::next::{Next, NextImp};
+use crate::namespace;
+use crate::request::Request;
+use crate::value::Value;
+
+/// The local value which `identityFromJwt` stores the verified tenant claim in.
+pub(crate) const JWT_TENANT_KEY: &str = "identity:tenant";
+
+#[derive(Debug, Clone)]
+enum TenantSource {
+    Header(String),
+    Subdomain,
+    Jwt,
+}
+
+/// Returns the first label of a host with at least three labels, like `acme` of
+/// `acme.example.com`.
+fn subdomain(request: &Request) -> Result<Option<String>> {
+    let host = match request.host() {
+        Some(host) => Some(host.to_owned()),
+        None => request.headers().get(HOST.as_str())?,
+    };
+    let Some(host) = host else {
+        return Ok(None);
+    };
+    let host = host.split(":").next().unwrap_or("");
+    let labels: Vec<&str> = host.split(".").collect();
+    Ok(if labels.len() >= 3 && !labels[0].is_empty() {
+        Some(labels[0].to_owned())
+    } else {
+        None
+    })
+}
+
+fn resolve_tenant(request: &Request, source: &TenantSource) -> Result<Option<Value>> {
+    Ok(match source {
+        TenantSource::Header(name) => request.headers().get(name)?.map(Value::String),
+        TenantSource::Subdomain => subdomain(request)?.map(Value::String),
+        TenantSource::Jwt => request.local_values().get_mut(JWT_TENANT_KEY).ok().map(|v| v.clone()),
+    })
+}
+
+/// Checks whether a tenant resolved from the request is the tenant claim of the JWT token.
+/// Tenants from headers and subdomains are strings, so they match claims of other types by
+/// their JSON representation.
+pub(crate) fn tenant_matches_claim(tenant: &Value, claim: &Value) -> bool {
+    if tenant == claim {
+        return true;
+    }
+    match (tenant, JsonValue::try_from(claim)) {
+        (Value::String(tenant), Ok(claim)) => !claim.is_string() && claim.to_string() == *tenant,
+        _ => false,
+    }
+}
+
+fn tenant_middleware(arguments: Arguments) -> Result<impl MiddlewareImp> {
+    let from: String = arguments.get("from")?;
+    let source = match from.as_str() {
+        "header" => {
+            let header: Option<String> = arguments.get_optional("header")?;
+            TenantSource::Header(header.unwrap_or("X-Tenant-Id".to_owned()))
+        }
+        "subdomain" => TenantSource::Subdomain,
+        "jwt" => TenantSource::Jwt,
+        from => Err(Error::new(format!("tenant: invalid source: {}", from)))?,
+    };
+    Ok(move |request: Request, next: Next| {
+        let source = source.clone();
+        async move {
+            let Some(tenant) = resolve_tenant(&request, &source)? else {
+                return Err(Error::unauthorized_message("missing tenant"));
+            };
+            // an identity of a `@tenant` model can't act on another tenant
+            if let Some(claim) = request.local_values().get_mut(JWT_TENANT_KEY).ok().map(|v| v.clone()) {
+                if !tenant_matches_claim(&tenant, &claim) {
+                    return Err(Error::unauthorized_message("tenant doesn't match the identity"));
+                }
+            }
+            request.transaction_ctx().set_tenant(Some(tenant));
+            next.call(request).await
+        }
+    })
+}
+
+/// Defines the `tenant` middlewares, which resolve the tenant of a request from a header, the
+/// subdomain, or the tenant claim of the JWT token, and scope the request to it. Requests
+/// without a tenant are rejected, and so are requests whose identity belongs to another tenant.
+/// Resolving from the JWT token requires `identityFromJwt` to
+/// run first, so use the handler middleware for it.
+pub(in crate::stdlib) fn load_tenant_middleware(namespace: &namespace::Builder) {
+    namespace.define_request_middleware("tenant", |arguments: Arguments| {
+        tenant_middleware(arguments)
+    });
+    namespace.define_handler_middleware("tenant", |arguments: Arguments| {
+        tenant_middleware(arguments)
+    });
+}
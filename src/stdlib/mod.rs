@@ -1,7 +1,9 @@
 pub mod load;
 mod pipeline_items;
 mod decorators;
-mod middlewares;
+pub mod middlewares;
 mod structs;
 mod identity;
-mod admin;
\ No newline at end of file
+mod admin;
+mod audit;
+pub(crate) mod rbac;
\ No newline at end of file
@@ -0,0 +1,172 @@
+use key_path::{path, KeyPath};
+use teo_result::{Error, Result};
+use crate::arguments::Arguments;
+use crate::middleware::next::{Next, NextImp};
+use crate::model;
+use crate::model::Model;
+use crate::namespace;
+use crate::pipeline::Ctx;
+use crate::request::Request;
+use crate::teon;
+use crate::value::Value;
+
+/// The local value which the permissions of the request identity are cached in.
+const PERMISSIONS_KEY: &str = "rbac:permissions";
+
+/// The field holding the permission name of a permission model, `name` unless it's set with
+/// `@rbac.permission`.
+fn permission_name_field(model: &Model) -> &str {
+    model.data().get("rbac:permission").and_then(|v| v.as_str()).unwrap_or("name")
+}
+
+/// Fetches the related objects of `object` through the relations marked with `key`.
+async fn related_objects(object: &model::Object, key: &str) -> Result<Vec<model::Object>> {
+    let mut result = vec![];
+    for relation in object.model().relations().values() {
+        if relation.data().get(key).is_none() {
+            continue;
+        }
+        if relation.is_vec() {
+            result.extend(object.fetch_relation_objects(relation.name(), None).await?);
+        } else {
+            match object.fetch_relation_object(relation.name(), None).await {
+                Ok(Some(related)) => result.push(related),
+                Ok(None) => (),
+                Err(error) if error.code == 404 => (),
+                Err(error) => Err(error)?,
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Collects the names of the permissions granted to `identity`, both directly and through its
+/// roles. Roles and permissions are found through the relations marked with `@rbac.roles` and
+/// `@rbac.permissions`, regardless of the policies of their models.
+async fn resolve_permissions(identity: &model::Object) -> Result<Vec<String>> {
+    let transaction_ctx = identity.transaction_ctx().bypassing_policies();
+    let identity: Option<model::Object> = transaction_ctx.find_unique(identity.model(), &teon!({
+        "where": identity.identifier()
+    }), None, path![]).await?;
+    let Some(identity) = identity else {
+        return Ok(vec![]);
+    };
+    let mut holders = vec![identity.clone()];
+    holders.extend(related_objects(&identity, "rbac:roles").await?);
+    let mut permissions: Vec<String> = vec![];
+    for holder in holders {
+        for permission in related_objects(&holder, "rbac:permissions").await? {
+            if let Value::String(name) = permission.get_value(permission_name_field(permission.model()))? {
+                if !permissions.contains(&name) {
+                    permissions.push(name);
+                }
+            }
+        }
+    }
+    Ok(permissions)
+}
+
+/// Returns the permissions of the identity of the request. The permissions are resolved once
+/// per request and cached in the local values. Requests without identity have no permissions.
+pub(crate) async fn request_permissions(request: &Request) -> Result<Vec<String>> {
+    if let Ok(permissions) = request.local_values().get::<Vec<String>>(PERMISSIONS_KEY) {
+        return Ok(permissions);
+    }
+    let permissions = match request.local_values().get::<model::Object>("account") {
+        Ok(identity) => resolve_permissions(&identity).await?,
+        Err(_) => vec![],
+    };
+    request.local_values().insert(PERMISSIONS_KEY, permissions.clone());
+    Ok(permissions)
+}
+
+pub(crate) async fn has_permission(request: &Request, permission: &str) -> Result<bool> {
+    Ok(request_permissions(request).await?.iter().any(|p| p == permission))
+}
+
+/// Checks the permission which `@rbac.require` stores. Operations outside of requests are not
+/// checked.
+async fn check_required_permission(permission: Option<String>, request: Option<Request>, path: &KeyPath) -> Result<()> {
+    let (Some(permission), Some(request)) = (permission, request) else {
+        return Ok(());
+    };
+    if has_permission(&request, &permission).await? {
+        Ok(())
+    } else {
+        Err(Error::unauthorized_pathed(path.clone(), format!("missing permission {}", permission)))
+    }
+}
+
+pub(super) fn load_rbac_library(std_namespace: &namespace::Builder) {
+
+    let rbac_namespace = std_namespace.child_namespace_or_create("rbac");
+
+    rbac_namespace.define_permission_check(|data, request, path| {
+        let permission = data.get("rbac:require").and_then(|v| v.as_str()).map(|s| s.to_owned());
+        let path = path.clone();
+        async move {
+            check_required_permission(permission, request, &path).await
+        }
+    });
+
+    rbac_namespace.define_model_decorator("permission", |arguments, model| {
+        let field: Option<String> = arguments.get_optional("field")?;
+        model.insert_data_entry("rbac:permission".to_owned(), field.unwrap_or("name".to_owned()).into());
+        Ok(())
+    });
+
+    rbac_namespace.define_model_relation_decorator("roles", |_, relation| {
+        relation.insert_data_entry("rbac:roles".to_owned(), true.into());
+        Ok(())
+    });
+
+    rbac_namespace.define_model_relation_decorator("permissions", |_, relation| {
+        relation.insert_data_entry("rbac:permissions".to_owned(), true.into());
+        Ok(())
+    });
+
+    rbac_namespace.define_model_decorator("require", |arguments, model| {
+        let permission: String = arguments.get("permission")?;
+        model.insert_data_entry("rbac:require".to_owned(), permission.into());
+        Ok(())
+    });
+
+    rbac_namespace.define_model_field_decorator("require", |arguments, field| {
+        let permission: String = arguments.get("permission")?;
+        field.insert_data_entry("rbac:require".to_owned(), permission.into());
+        Ok(())
+    });
+
+    rbac_namespace.define_handler_decorator("require", |arguments, handler| {
+        let permission: String = arguments.get("permission")?;
+        let call = handler.call();
+        handler.set_call(Next::new(move |request: Request| {
+            let call = call.clone();
+            let permission = permission.clone();
+            async move {
+                if !has_permission(&request, &permission).await? {
+                    return Err(Error::unauthorized_message(&format!("missing permission {}", permission)));
+                }
+                call.call(request).await
+            }
+        }));
+        Ok(())
+    });
+
+    rbac_namespace.define_pipeline_item("hasPermission", |arguments: Arguments| {
+        let permission: String = arguments.get("permission")?;
+        Ok(move |ctx: Ctx| {
+            let permission = permission.clone();
+            async move {
+                let Some(request) = ctx.request() else {
+                    return Err(Error::new_with_code(format!("missing permission {}", permission), 401));
+                };
+                if has_permission(&request, &permission).await? {
+                    Ok(ctx.value().clone())
+                } else {
+                    Err(Error::new_with_code(format!("missing permission {}", permission), 401))
+                }
+            }
+        })
+    });
+}
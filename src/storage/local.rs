@@ -0,0 +1,183 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use teo_result::{Error, Result};
+use crate::storage::s3::uri_encode;
+use crate::storage::storage::FileStorage;
+
+/// Stores files in a directory of the local disk.
+///
+/// Presigned URLs point to `base_url` and carry an expiration time and a signature made with
+/// `secret`. The handler which serves the files checks them with `verify_presigned`.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+    base_url: String,
+    secret: String,
+}
+
+impl LocalStorage {
+
+    pub fn new(root: impl Into<PathBuf>, base_url: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            root: root.into(),
+            base_url: base_url.into().trim_end_matches("/").to_owned(),
+            secret: secret.into(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// Returns the local path of the file at `key`. Keys escaping the root are rejected.
+    pub fn path_for_key(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Error::new(format!("invalid file storage key: {}", key)));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    /// Checks the `expires` and `signature` query parameters of a presigned URL.
+    pub fn verify_presigned(&self, key: &str, expires: u64, signature: &str) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        if expires < now {
+            return false;
+        }
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        self.mac(key, expires).verify_slice(&signature).is_ok()
+    }
+
+    fn mac(&self, key: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).unwrap();
+        mac.update(format!("{}\n{}", key, expires).as_bytes());
+        mac
+    }
+}
+
+fn io_error(key: &str, error: std::io::Error) -> Error {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        Error::new_with_code(format!("file is not found: {}", key), 404)
+    } else {
+        Error::internal_server_error_message(&format!("file storage error: {}", error))
+    }
+}
+
+#[async_trait]
+impl FileStorage for LocalStorage {
+
+    async fn put(&self, key: &str, data: Bytes, _content_type: Option<&str>) -> Result<()> {
+        let path = self.path_for_key(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| io_error(key, e))?;
+        }
+        tokio::fs::write(&path, data).await.map_err(|e| io_error(key, e))
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes> {
+        let path = self.path_for_key(key)?;
+        Ok(Bytes::from(tokio::fs::read(&path).await.map_err(|e| io_error(key, e))?))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.path_for_key(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(key, e)),
+        }
+    }
+
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<String> {
+        self.path_for_key(key)?;
+        let expires = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) + expires_in.as_secs();
+        let signature = hex::encode(self.mac(key, expires).finalize().into_bytes());
+        Ok(format!("{}/{}?expires={}&signature={}", self.base_url, uri_encode(key, true), expires, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> LocalStorage {
+        let root = std::env::temp_dir().join(format!("teo-local-storage-test-{}", uuid::Uuid::new_v4()));
+        LocalStorage::new(root, "https://example.com/files/", "secret")
+    }
+
+    /// The expires and signature query parameters of a presigned URL.
+    fn presigned_params(url: &str) -> (u64, String) {
+        let (_, query) = url.split_once("?").unwrap();
+        let mut expires = 0;
+        let mut signature = String::new();
+        for item in query.split("&") {
+            match item.split_once("=").unwrap() {
+                ("expires", value) => expires = value.parse().unwrap(),
+                ("signature", value) => signature = value.to_owned(),
+                _ => (),
+            }
+        }
+        (expires, signature)
+    }
+
+    #[tokio::test]
+    async fn files_are_put_read_and_deleted() {
+        let storage = storage();
+        storage.put("avatars/a.png", Bytes::from("png"), Some("image/png")).await.unwrap();
+        assert_eq!(std::fs::read(storage.root().join("avatars/a.png")).unwrap(), b"png");
+        assert_eq!(storage.get("avatars/a.png").await.unwrap(), Bytes::from("png"));
+        storage.delete("avatars/a.png").await.unwrap();
+        assert!(!storage.root().join("avatars/a.png").exists());
+        assert_eq!(storage.get("avatars/a.png").await.unwrap_err().code, 404);
+        // deleting a missing file is not an error
+        storage.delete("avatars/a.png").await.unwrap();
+        std::fs::remove_dir_all(storage.root()).unwrap();
+    }
+
+    #[test]
+    fn keys_never_escape_the_root() {
+        let storage = storage();
+        assert_eq!(storage.path_for_key("avatars/a.png").unwrap(), storage.root().join("avatars").join("a.png"));
+        assert!(storage.path_for_key("").is_err());
+        assert!(storage.path_for_key("../a.png").is_err());
+        assert!(storage.path_for_key("avatars/../../a.png").is_err());
+        assert!(storage.path_for_key("./a.png").is_err());
+        assert!(storage.path_for_key("/etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn presigned_urls_are_verified() {
+        let storage = storage();
+        let url = storage.presigned_url("avatars/a b.png", Duration::from_secs(60)).await.unwrap();
+        assert!(url.starts_with("https://example.com/files/avatars/a%20b.png?expires="));
+        let (expires, signature) = presigned_params(&url);
+        assert!(storage.verify_presigned("avatars/a b.png", expires, &signature));
+        assert!(!storage.verify_presigned("avatars/other.png", expires, &signature));
+        assert!(!storage.verify_presigned("avatars/a b.png", expires + 1, &signature));
+        assert!(!storage.verify_presigned("avatars/a b.png", expires, "not hex"));
+        assert!(!storage.verify_presigned("avatars/a b.png", expires, &"0".repeat(signature.len())));
+        let other = LocalStorage::new(storage.root(), "https://example.com/files", "other secret");
+        assert!(!other.verify_presigned("avatars/a b.png", expires, &signature));
+    }
+
+    #[test]
+    fn expired_presigned_urls_are_rejected() {
+        let storage = storage();
+        let signature = hex::encode(storage.mac("a.png", 1).finalize().into_bytes());
+        assert!(!storage.verify_presigned("a.png", 1, &signature));
+        let expires = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 60;
+        let signature = hex::encode(storage.mac("a.png", expires).finalize().into_bytes());
+        assert!(storage.verify_presigned("a.png", expires, &signature));
+    }
+
+    #[tokio::test]
+    async fn presigned_urls_need_valid_keys() {
+        assert!(storage().presigned_url("../a.png", Duration::from_secs(60)).await.is_err());
+    }
+}
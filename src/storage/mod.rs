@@ -0,0 +1,7 @@
+pub mod storage;
+pub mod local;
+pub mod s3;
+
+pub use storage::FileStorage;
+pub use local::LocalStorage;
+pub use s3::S3Storage;
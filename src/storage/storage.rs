@@ -0,0 +1,21 @@
+use std::fmt::Debug;
+use std::time::Duration;
+use async_trait::async_trait;
+use bytes::Bytes;
+use teo_result::Result;
+
+/// A backend which stores the files of `@storage` fields. Files are addressed by keys like
+/// `avatars/1d2c4e.png`.
+#[async_trait]
+pub trait FileStorage: Send + Sync + Debug {
+
+    async fn put(&self, key: &str, data: Bytes, content_type: Option<&str>) -> Result<()>;
+
+    async fn get(&self, key: &str) -> Result<Bytes>;
+
+    /// Deletes the file at `key`. Deleting a file which doesn't exist is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Returns a URL which grants read access to the file at `key` until `expires_in` elapses.
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<String>;
+}
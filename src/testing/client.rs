@@ -0,0 +1,174 @@
+use std::sync::Mutex;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::{CONTENT_TYPE, COOKIE};
+use hyper::Method;
+use indexmap::IndexMap;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::connection;
+use crate::connection::transaction;
+use crate::cookies::cookie::Cookie;
+use crate::handler::action::builtin_action_handler_from_name;
+use crate::handler::input::{decode_request_input_for_handler, read_json_body, validate_and_transform_json_input_for_builtin_action};
+use crate::handler::default;
+use crate::middleware::next::Next;
+use crate::middleware::middleware_imp::MiddlewareImp;
+use crate::namespace::Namespace;
+use crate::request::Request;
+use crate::response::Response;
+use crate::testing::response::TestResponse;
+
+/// Sends requests to a loaded namespace without binding a server. Each request runs through
+/// the request middlewares, handler matching, the handler middlewares and the handler, just
+/// like a request received by the server.
+///
+/// Headers set on the client are sent with every request, and cookies set by responses are
+/// sent with the following requests.
+pub struct TestClient {
+    namespace: Namespace,
+    connection_ctx: connection::Ctx,
+    headers: Mutex<IndexMap<String, String>>,
+    cookies: Mutex<IndexMap<String, String>>,
+}
+
+impl TestClient {
+
+    pub fn new(namespace: &Namespace) -> Self {
+        Self {
+            namespace: namespace.clone(),
+            connection_ctx: connection::Ctx::from_namespace(namespace),
+            headers: Mutex::new(IndexMap::new()),
+            cookies: Mutex::new(IndexMap::new()),
+        }
+    }
+
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    pub fn set_header(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.headers.lock().unwrap().insert(name.into(), value.into());
+    }
+
+    pub fn remove_header(&self, name: &str) {
+        self.headers.lock().unwrap().shift_remove(name);
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn set_cookie(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.cookies.lock().unwrap().insert(name.into(), value.into());
+    }
+
+    pub fn clear_cookies(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    pub async fn post_json(&self, url: &str, body: JsonValue) -> Result<TestResponse> {
+        let body = Bytes::from(serde_json::to_vec(&body).unwrap());
+        self.request(Method::POST, url, Some("application/json"), body).await
+    }
+
+    /// Sends a `multipart/form-data` request. `fields` are sent as text parts and `files` as file
+    /// parts, each file is given as the field name, the file name, the content type and the data.
+    pub async fn post_multipart(&self, url: &str, fields: Vec<(&str, &str)>, files: Vec<(&str, &str, &str, Bytes)>) -> Result<TestResponse> {
+        let boundary = format!("teo-test-{}", uuid::Uuid::new_v4().simple());
+        let mut body: Vec<u8> = vec![];
+        for (name, value) in fields {
+            body.extend(format!("--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n").as_bytes());
+        }
+        for (name, filename, content_type, data) in files {
+            body.extend(format!("--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"; filename=\"{filename}\"\r\nContent-Type: {content_type}\r\n\r\n").as_bytes());
+            body.extend(data.as_ref());
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{boundary}--\r\n").as_bytes());
+        let content_type = format!("multipart/form-data; boundary={boundary}");
+        self.request(Method::POST, url, Some(&content_type), Bytes::from(body)).await
+    }
+
+    pub async fn get(&self, url: &str) -> Result<TestResponse> {
+        self.request(Method::GET, url, None, Bytes::new()).await
+    }
+
+    pub async fn request(&self, method: Method, url: &str, content_type: Option<&str>, body: Bytes) -> Result<TestResponse> {
+        let mut builder = hyper::Request::builder().method(method).uri(url);
+        for (name, value) in self.headers.lock().unwrap().iter() {
+            builder = builder.header(name, value);
+        }
+        if let Some(content_type) = content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+        let cookie = self.cookies.lock().unwrap().iter().map(|(name, value)| {
+            Cookie::new(name.as_str(), value.as_str()).encoded()
+        }).collect::<Vec<String>>().join("; ");
+        if !cookie.is_empty() {
+            builder = builder.header(COOKIE, cookie);
+        }
+        let hyper_request = builder.body(Full::new(body)).map_err(|err| Error::new(err.to_string()))?;
+        let request = Request::new_for_test(hyper_request, transaction::Ctx::new(self.connection_ctx.clone()));
+        let namespace = self.namespace.clone();
+        let next = Next::new(move |request: Request| {
+            let namespace = namespace.clone();
+            async move {
+                dispatch(&namespace, request).await
+            }
+        });
+        let response = match self.namespace.request_middleware_stack().call(request, next).await {
+            Ok(response) => response,
+            Err(err) => Response::from(err),
+        };
+        self.store_cookies(&response)?;
+        TestResponse::new(response).await
+    }
+
+    fn store_cookies(&self, response: &Response) -> Result<()> {
+        let mut cookies = response.cookies().entries();
+        for header in response.headers().get_all("set-cookie")? {
+            cookies.push(Cookie::parse_encoded(header)?);
+        }
+        let mut jar = self.cookies.lock().unwrap();
+        for cookie in cookies {
+            if cookie.value().is_empty() || cookie.max_age().map_or(false, |max_age| max_age.is_zero()) {
+                jar.shift_remove(&cookie.name());
+            } else {
+                jar.insert(cookie.name(), cookie.value());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Matches the handler of the request, decodes the request body and calls the handler through
+/// the handler middlewares.
+async fn dispatch(namespace: &Namespace, request: Request) -> Result<Response> {
+    let Some(handler_match) = namespace.handler_map().match_all(request.method(), request.path()) else {
+        return Err(Error::not_found());
+    };
+    request.set_handler_match(handler_match.clone());
+    let mut handler_path = handler_match.path().clone();
+    handler_path.push(handler_match.handler_name().to_owned());
+    let dest_namespace = namespace.namespace_at_path(handler_match.path_without_last()).unwrap_or(namespace);
+    let stack = dest_namespace.handler_middleware_stack();
+    if let Some(handler) = namespace.handler_at_path(&handler_path) {
+        let body = decode_request_input_for_handler(&request, handler, namespace).await?;
+        request.set_body_value(body);
+        return stack.call(request, handler.call()).await;
+    }
+    let model = namespace.model_at_path(handler_match.path());
+    let action = builtin_action_handler_from_name(handler_match.handler_name());
+    let (Some(model), Some(action)) = (model, action) else {
+        return Err(Error::not_found());
+    };
+    if !model.builtin_handlers().contains(&action) {
+        return Err(Error::not_found());
+    }
+    let json = read_json_body(&request).await?;
+    request.set_body_value(validate_and_transform_json_input_for_builtin_action(model, action, &json, namespace)?);
+    stack.call(request, Next::new(move |request: Request| async move {
+        default::call_builtin_handler(action, request).await
+    })).await
+}
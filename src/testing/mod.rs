@@ -0,0 +1,5 @@
+pub mod client;
+pub mod response;
+
+pub use client::TestClient;
+pub use response::TestResponse;
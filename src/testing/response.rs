@@ -0,0 +1,121 @@
+use bytes::Bytes;
+use futures_util::StreamExt;
+use serde_json::Value as JsonValue;
+use teo_result::Result;
+use crate::headers::Headers;
+use crate::response::Response;
+use crate::value::Value;
+
+/// A response received by a `TestClient`. The body is decoded eagerly, so that it can be
+/// inspected and asserted multiple times.
+#[derive(Debug)]
+pub struct TestResponse {
+    code: u16,
+    headers: Headers,
+    body: Value,
+    text: Option<String>,
+}
+
+impl TestResponse {
+
+    pub(super) async fn new(response: Response) -> Result<Self> {
+        let body = response.body();
+        let (body_value, text) = if let Some(value) = body.as_teon() {
+            (value.clone(), None)
+        } else if let Some(text) = body.as_text() {
+            decode_text(text.clone())
+        } else if let Some(bytes) = body.as_bytes() {
+            decode_text(String::from_utf8_lossy(bytes).into_owned())
+        } else if let Some(mut stream) = body.take_stream() {
+            let mut chunks: Vec<Bytes> = vec![];
+            while let Some(chunk) = stream.next().await {
+                chunks.push(chunk?);
+            }
+            decode_text(String::from_utf8_lossy(&chunks.concat()).into_owned())
+        } else {
+            (Value::Null, None)
+        };
+        Ok(Self {
+            code: response.code(),
+            headers: response.headers(),
+            body: body_value,
+            text,
+        })
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The decoded body. JSON text bodies are decoded into values, other text bodies are
+    /// represented as strings.
+    pub fn body(&self) -> &Value {
+        &self.body
+    }
+
+    /// The `data` entry of the body, which is where handlers put their results.
+    pub fn data(&self) -> Option<&Value> {
+        self.body.get("data")
+    }
+
+    /// The raw text of the body if the body is not a TEON value.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn assert_code(&self, code: u16) -> &Self {
+        assert_eq!(self.code, code, "unexpected response code, body: {:?}", self.body);
+        self
+    }
+
+    pub fn assert_body_eq(&self, expected: &Value) -> &Self {
+        assert_eq!(&self.body, expected, "unexpected response body");
+        self
+    }
+
+    /// Asserts that the body contains `expected`. Dictionaries match if every entry of the
+    /// expected dictionary matches, arrays match if they have the same length and every item
+    /// matches, other values match if they are equal.
+    pub fn assert_body_matches(&self, expected: &Value) -> &Self {
+        assert!(value_matches(&self.body, expected), "response body {:?} doesn't match {:?}", self.body, expected);
+        self
+    }
+
+    pub fn assert_data_matches(&self, expected: &Value) -> &Self {
+        let data = self.data().unwrap_or(&Value::Null);
+        assert!(value_matches(data, expected), "response data {:?} doesn't match {:?}", data, expected);
+        self
+    }
+
+    /// Asserts that the response is an error response of the error type `error_type`, such as
+    /// `"NotFound"`.
+    pub fn assert_error_type(&self, error_type: &str) -> &Self {
+        let actual = self.body.get("type").and_then(|t| t.as_str());
+        assert_eq!(actual, Some(error_type), "unexpected error type, body: {:?}", self.body);
+        self
+    }
+}
+
+fn decode_text(text: String) -> (Value, Option<String>) {
+    let value = match serde_json::from_str::<JsonValue>(&text) {
+        Ok(json) => Value::from(json),
+        Err(_) => Value::String(text.clone()),
+    };
+    (value, Some(text))
+}
+
+fn value_matches(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Dictionary(actual), Value::Dictionary(expected)) => expected.iter().all(|(k, v)| {
+            actual.get(k).map_or(false, |a| value_matches(a, v))
+        }),
+        (Value::Array(actual), Value::Array(expected)) => {
+            actual.len() == expected.len() && actual.iter().zip(expected.iter()).all(|(a, e)| value_matches(a, e))
+        }
+        _ => actual == expected,
+    }
+}